@@ -3,13 +3,23 @@ extern crate clap;
 mod clidiary;
 mod diarydir;
 mod entryinput;
+mod highlight;
+mod passphrase;
 
 use chrono::prelude::*;
 use clap::{App, Arg, SubCommand};
 use clidiary::CLIDiary;
-use diary_core::DiaryEntryKey;
-use std::path::PathBuf;
+use diary_core::{
+    parse_jrnl, parse_local_datetime_with_clock, parse_range_end_with_clock, read_dayone,
+    read_markdown_notes, split_text, Conflict, DiaryEntryKey, DirectoryScheme, ExportFormat,
+    ExportOptions, Extension, MarkdownOptions, MatchingDateBehavior, Problem, RangeEnd, Resolution,
+    StorageFormat, WatchEvent, DEFAULT_SPLIT_REGEX,
+};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Duration;
 
 pub fn main() {
     let matches = App::new("ddiary")
@@ -32,6 +42,14 @@ pub fn main() {
                 .help("Name of the diary")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name(args::opts::WAIT)
+                .short("w")
+                .long("wait")
+                .value_name("SECONDS")
+                .help("Wait for other ddiary processes to finish writing to the diary")
+                .takes_value(true),
+        )
         .subcommand(
             SubCommand::with_name(args::add::SUBCOMMAND)
                 .about("Add a diary entry")
@@ -51,6 +69,25 @@ pub fn main() {
                         .takes_value(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name(args::attach::SUBCOMMAND)
+                .about("Attach files to an entry and link to them from its text")
+                .arg(
+                    Arg::with_name(args::attach::DATE)
+                        .short("d")
+                        .long("date")
+                        .value_name("DATE")
+                        .help("Entry date (defaults to the latest entry)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::attach::FILES)
+                        .value_name("FILE")
+                        .help("Files to attach")
+                        .required(true)
+                        .multiple(true),
+                ),
+        )
         .subcommand(
             SubCommand::with_name(args::edit::SUBCOMMAND)
                 .about("Edit or replace a diary entry")
@@ -71,6 +108,299 @@ pub fn main() {
                         .takes_value(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name(args::encrypt::SUBCOMMAND)
+                .about(
+                    "Encrypt the diary. The passphrase is read from DDIARY_PASSPHRASE, \
+                     from the output of DDIARY_PASSPHRASE_COMMAND or from the terminal",
+                )
+                .arg(
+                    Arg::with_name(args::encrypt::KEEP_GIT_HISTORY)
+                        .long("keep-git-history")
+                        .help(
+                            "Encrypt a diary that uses git, leaving the entries in plain text \
+                             in its earlier commits",
+                        )
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::decrypt::SUBCOMMAND).about("Decrypt an encrypted diary"),
+        )
+        .subcommand(
+            SubCommand::with_name(args::git::SUBCOMMAND)
+                .about("Configure committing changes to a git repository in the diary directory")
+                .arg(
+                    Arg::with_name(args::git::ENABLE)
+                        .long("enable")
+                        .help("Commit every change to git")
+                        .conflicts_with(args::git::DISABLE),
+                )
+                .arg(
+                    Arg::with_name(args::git::DISABLE)
+                        .long("disable")
+                        .help("Stop committing changes to git"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::check::SUBCOMMAND)
+                .about("Check the diary files and the tag index for problems")
+                .arg(
+                    Arg::with_name(args::check::FIX)
+                        .long("fix")
+                        .help("Move misplaced entries, delete empty ones and correct the index"),
+                )
+                .arg(
+                    Arg::with_name(args::check::CONVERT_UTF8)
+                        .long("convert-utf8")
+                        .help("Convert entries that aren't UTF-8 to UTF-8 first"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::import::SUBCOMMAND)
+                .about("Import entries from another diary program")
+                .arg(
+                    Arg::with_name(args::import::FROM)
+                        .long("from")
+                        .value_name("FORMAT")
+                        .possible_values(&["jrnl", "dayone", "markdown", "text"])
+                        .help("Format of the file to import")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::import::DRY_RUN)
+                        .long("dry-run")
+                        .help("Show what would be imported without changing the diary"),
+                )
+                .arg(
+                    Arg::with_name(args::import::ON_COLLISION)
+                        .long("on-collision")
+                        .value_name("BEHAVIOR")
+                        .possible_values(&["append", "overwrite"])
+                        .help("What to do with entries at a date that already has an entry (default append)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::import::DATE_PATTERN)
+                        .long("date-pattern")
+                        .value_name("PATTERN")
+                        .help("strftime pattern of the file names of Markdown notes (default %Y-%m-%d)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::import::DEFAULT_TIME)
+                        .long("default-time")
+                        .value_name("TIME")
+                        .help("Time of day of Markdown notes without one, as HH:MM (default 12:00)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::import::SPLIT_REGEX)
+                        .long("split-regex")
+                        .value_name("REGEX")
+                        .help("Regular expression matching the date headings of a text file; the date is the first group")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::import::FILE)
+                        .value_name("FILE")
+                        .help("File to import; a zip archive for Day One, a directory for Markdown")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::export::SUBCOMMAND)
+                .about("Write entries into one document")
+                .arg(
+                    Arg::with_name(args::export::FORMAT)
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["markdown", "text", "jrnl", "dayone"])
+                        .help(
+                            "Format of the document (default markdown); \
+                             jrnl reads @mentions back as tags",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::export::FROM)
+                        .short("f")
+                        .long("from")
+                        .value_name("DATE")
+                        .help("Only export entries from this date on")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::export::TO)
+                        .short("t")
+                        .long("to")
+                        .value_name("DATE")
+                        .help("Only export entries up to this date")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::export::TAG)
+                        .long("tag")
+                        .value_name("TAG")
+                        .help("Only export entries with this tag; can be given more than once")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::export::TITLE)
+                        .long("title")
+                        .value_name("TITLE")
+                        .help("Title of a Markdown document (default Diary)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::export::TIME_ZONE)
+                        .long("time-zone")
+                        .value_name("TIME_ZONE")
+                        .help("Time zone to write the dates in, like Europe/Helsinki (default the local time zone)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::export::OUTPUT)
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("File to write the document to (default standard output); required for Day One")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::watch::SUBCOMMAND)
+                .about("Keep the tag index up to date while entry files are edited by other programs")
+                .arg(
+                    Arg::with_name(args::watch::DEBOUNCE)
+                        .long("debounce")
+                        .value_name("MILLISECONDS")
+                        .help("Wait until files haven't changed for this long before indexing them (default 500)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::convert::SUBCOMMAND)
+                .about("Move the diary between a file per entry and a single SQLite database")
+                .arg(
+                    Arg::with_name(args::convert::TO)
+                        .long("to")
+                        .value_name("FORMAT")
+                        .possible_values(&["sqlite", "tree"])
+                        .help("Storage format to convert to")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::migrate_layout::SUBCOMMAND)
+                .about("Change how entry files are named and arranged in directories")
+                .arg(
+                    Arg::with_name(args::migrate_layout::EXTENSION)
+                        .short("e")
+                        .long("extension")
+                        .value_name("EXTENSION")
+                        .possible_values(&["none", "md", "txt", "org"])
+                        .help("File name extension of entries")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::migrate_layout::DIRECTORIES)
+                        .short("D")
+                        .long("directories")
+                        .value_name("SCHEME")
+                        .possible_values(&["flat", "year", "year-month", "year-month-day"])
+                        .help("Directories entries are kept in")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::migrate_layout::FILE_NAME)
+                        .short("f")
+                        .long("file-name")
+                        .value_name("PATTERN")
+                        .help("strftime pattern for file names, e.g. %Y-%m-%d_%H%M")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::log::SUBCOMMAND)
+                .about("Show the git history of the diary")
+                .arg(
+                    Arg::with_name(args::log::COUNT)
+                        .short("c")
+                        .long("count")
+                        .value_name("COUNT")
+                        .help("Show only the latest COUNT changes")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::history::SUBCOMMAND)
+                .about("List the earlier versions of an entry")
+                .arg(
+                    Arg::with_name(args::history::DATE)
+                        .short("d")
+                        .long("date")
+                        .value_name("DATE")
+                        .help("Entry date")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::diff::SUBCOMMAND)
+                .about("Compare two versions of an entry")
+                .arg(
+                    Arg::with_name(args::diff::DATE)
+                        .short("d")
+                        .long("date")
+                        .value_name("DATE")
+                        .help("Entry date")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::diff::FROM)
+                        .short("f")
+                        .long("from")
+                        .value_name("VERSION")
+                        .help("Version to compare from (defaults to the newest earlier version)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::diff::TO)
+                        .short("t")
+                        .long("to")
+                        .value_name("VERSION")
+                        .help("Version to compare to (defaults to the current text)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::restore::SUBCOMMAND)
+                .about("Replace an entry with an earlier version")
+                .arg(
+                    Arg::with_name(args::restore::DATE)
+                        .short("d")
+                        .long("date")
+                        .value_name("DATE")
+                        .help("Entry date")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::restore::VERSION)
+                        .short("v")
+                        .long("version")
+                        .value_name("VERSION")
+                        .help("Version to restore")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
         .subcommand(
             SubCommand::with_name(args::list::SUBCOMMAND)
                 .about("Lists entries")
@@ -122,6 +452,54 @@ pub fn main() {
                         .value_name("RNUMBER")
                         .help("Entry number (counting from last)")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::show::PLAIN)
+                        .long("plain")
+                        .help("Don't highlight tags")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::stats::SUBCOMMAND)
+                .about("Show statistics about entries")
+                .arg(
+                    Arg::with_name(args::stats::FROM)
+                        .short("f")
+                        .long("from")
+                        .value_name("DATE")
+                        .help("Only count entries from this date on")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::stats::TO)
+                        .short("t")
+                        .long("to")
+                        .value_name("DATE")
+                        .help("Only count entries up to this date")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(args::sync::SUBCOMMAND)
+                .about("Copy entries between this diary and another copy of it")
+                .arg(
+                    Arg::with_name(args::sync::OTHER_PATH)
+                        .value_name("OTHER_PATH")
+                        .help("Location of the other diary directory")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(args::sync::CONFLICTS)
+                        .short("c")
+                        .long("conflicts")
+                        .value_name("RESOLUTION")
+                        .help(
+                            "How to resolve entries changed in both diaries \
+                             (defaults to prompt on a terminal, both otherwise)",
+                        )
+                        .possible_values(&["prompt", "both", "markers", "this", "other"])
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -135,6 +513,39 @@ pub fn main() {
                         .help("Tags to search for")
                         .multiple(true),
                 )
+                .arg(
+                    Arg::with_name(args::tags::LIST)
+                        .short("l")
+                        .long("list")
+                        .help("List tags with the number of entries using them"),
+                )
+                .arg(
+                    Arg::with_name(args::tags::RELATED)
+                        .short("r")
+                        .long("related")
+                        .value_name("TAG")
+                        .help("List tags that appear in the same entries as TAG")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name(args::tags::DOT)
+                        .long("dot")
+                        .help("Print the tag co-occurrence graph in Graphviz DOT format"),
+                )
+                .arg(
+                    Arg::with_name(args::tags::ALIAS)
+                        .long("alias")
+                        .value_names(&["ALIAS", "TAG"])
+                        .help("Make ALIAS an alias of TAG")
+                        .number_of_values(2),
+                )
+                .arg(
+                    Arg::with_name(args::tags::UNALIAS)
+                        .long("unalias")
+                        .value_name("ALIAS")
+                        .help("Remove an alias")
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::with_name(args::tags::REINDEX)
                         .short("I")
@@ -152,17 +563,78 @@ pub fn main() {
             process::exit(1)
         });
     path.push(matches.value_of(args::opts::NAME).unwrap_or("default"));
-    let diary = CLIDiary::open(&path);
+    let lock_timeout = match matches.value_of(args::opts::WAIT).map(|s| s.parse::<u64>()) {
+        Some(Ok(seconds)) => Duration::from_secs(seconds),
+        Some(Err(_)) => {
+            eprintln!("Invalid number of seconds to wait");
+            process::exit(1)
+        }
+        None => Duration::from_secs(0),
+    };
+    if let Some(encrypt_matches) = matches.subcommand_matches(args::encrypt::SUBCOMMAND) {
+        let keep_git_history = encrypt_matches.is_present(args::encrypt::KEEP_GIT_HISTORY);
+        CLIDiary::encrypt(&path, keep_git_history, lock_timeout);
+        return;
+    } else if matches
+        .subcommand_matches(args::decrypt::SUBCOMMAND)
+        .is_some()
+    {
+        CLIDiary::decrypt(&path, lock_timeout);
+        return;
+    }
+    if let Some(git_matches) = matches.subcommand_matches(args::git::SUBCOMMAND) {
+        if git_matches.is_present(args::git::ENABLE) {
+            CLIDiary::set_git_enabled(&path, true, lock_timeout);
+        } else if git_matches.is_present(args::git::DISABLE) {
+            CLIDiary::set_git_enabled(&path, false, lock_timeout);
+        }
+        return;
+    }
+    if let Some(convert_matches) = matches.subcommand_matches(args::convert::SUBCOMMAND) {
+        let format = match convert_matches.value_of(args::convert::TO) {
+            Some("sqlite") => StorageFormat::Sqlite,
+            _ => StorageFormat::Tree,
+        };
+        CLIDiary::convert_storage(&path, format, lock_timeout);
+        return;
+    }
+    if let Some(layout_matches) = matches.subcommand_matches(args::migrate_layout::SUBCOMMAND) {
+        migrate_layout(&path, layout_matches, lock_timeout);
+        return;
+    }
+    let diary = CLIDiary::open(&path, lock_timeout);
     if let Some(list_matches) = matches.subcommand_matches(args::list::SUBCOMMAND) {
-        list_entries(&diary, &list_matches);
+        list_entries(&diary, list_matches);
     } else if let Some(show_matches) = matches.subcommand_matches(args::show::SUBCOMMAND) {
-        show_entry(&diary, &show_matches);
+        show_entry(&diary, show_matches);
     } else if let Some(add_matches) = matches.subcommand_matches(args::add::SUBCOMMAND) {
-        add_entry_with_args(&diary, &add_matches);
+        add_entry_with_args(&diary, add_matches);
+    } else if let Some(attach_matches) = matches.subcommand_matches(args::attach::SUBCOMMAND) {
+        attach_files(&diary, attach_matches);
     } else if let Some(edit_matches) = matches.subcommand_matches(args::edit::SUBCOMMAND) {
-        edit_entry_with_args(&diary, &edit_matches);
+        edit_entry_with_args(&diary, edit_matches);
     } else if let Some(tags_matches) = matches.subcommand_matches(args::tags::SUBCOMMAND) {
-        tags_with_args(&diary, &tags_matches)
+        tags_with_args(&diary, tags_matches)
+    } else if let Some(stats_matches) = matches.subcommand_matches(args::stats::SUBCOMMAND) {
+        show_stats(&diary, stats_matches)
+    } else if let Some(sync_matches) = matches.subcommand_matches(args::sync::SUBCOMMAND) {
+        sync_diaries(&diary, sync_matches, lock_timeout)
+    } else if let Some(check_matches) = matches.subcommand_matches(args::check::SUBCOMMAND) {
+        check_diary(&diary, check_matches)
+    } else if let Some(import_matches) = matches.subcommand_matches(args::import::SUBCOMMAND) {
+        import_entries(&diary, import_matches)
+    } else if let Some(export_matches) = matches.subcommand_matches(args::export::SUBCOMMAND) {
+        export_entries(&diary, export_matches)
+    } else if let Some(watch_matches) = matches.subcommand_matches(args::watch::SUBCOMMAND) {
+        watch_diary(&diary, &path, watch_matches)
+    } else if let Some(log_matches) = matches.subcommand_matches(args::log::SUBCOMMAND) {
+        show_log(&diary, log_matches)
+    } else if let Some(history_matches) = matches.subcommand_matches(args::history::SUBCOMMAND) {
+        show_history(&diary, history_matches)
+    } else if let Some(diff_matches) = matches.subcommand_matches(args::diff::SUBCOMMAND) {
+        show_diff(&diary, diff_matches)
+    } else if let Some(restore_matches) = matches.subcommand_matches(args::restore::SUBCOMMAND) {
+        restore_version(&diary, restore_matches)
     }
 }
 
@@ -212,7 +684,7 @@ fn make_entry_list(
             (1..)
                 .zip(keys)
                 .map(|(index, key): (usize, &DiaryEntryKey)| {
-                    format!("{:width$} {}", index, key.to_string(), width = width)
+                    format!("{:width$} {}", index, key, width = width)
                 })
                 .collect()
         }
@@ -222,12 +694,7 @@ fn make_entry_list(
             (0..)
                 .zip(keys)
                 .map(|(index, key): (usize, &DiaryEntryKey)| {
-                    format!(
-                        "{:width$} {}",
-                        key_count - index,
-                        key.to_string(),
-                        width = width
-                    )
+                    format!("{:width$} {}", key_count - index, key, width = width)
                 })
                 .collect()
         }
@@ -253,24 +720,25 @@ fn order_of_magnitude(n: usize) -> usize {
 }
 
 fn show_entry(diary: &CLIDiary, matches: &clap::ArgMatches) {
+    let highlight = !matches.is_present(args::show::PLAIN) && io::stdout().is_terminal();
     if let Some(date_param) = matches.value_of(args::show::DATE) {
-        diary.show_entry(&parse_date_param(date_param));
+        diary.show_entry(&parse_date_param(date_param), highlight);
     } else if let Some(ns) = matches.value_of(args::show::NUMBER) {
-        if let Ok(number) = usize::from_str_radix(ns, 10) {
+        if let Ok(number) = ns.parse::<usize>() {
             let keys = diary.list_keys();
             check_entry_number(number, &keys);
             let key = &keys[number - 1];
-            diary.show_entry(&key);
+            diary.show_entry(key, highlight);
         } else {
             eprintln!("Failed to parse number {}", ns);
             process::exit(1);
         }
     } else if let Some(ns) = matches.value_of(args::show::NUMBER_REVERSE) {
-        if let Ok(number) = usize::from_str_radix(ns, 10) {
+        if let Ok(number) = ns.parse::<usize>() {
             let keys = diary.list_keys();
             check_entry_number(number, &keys);
             let key = &keys[keys.len() - number];
-            diary.show_entry(&key);
+            diary.show_entry(key, highlight);
         } else {
             eprintln!("Failed to parse number {}", ns);
             process::exit(1);
@@ -278,7 +746,7 @@ fn show_entry(diary: &CLIDiary, matches: &clap::ArgMatches) {
     } else {
         let keys = diary.list_keys();
         if let Some(key) = keys.last() {
-            diary.show_entry(&key);
+            diary.show_entry(key, highlight);
         }
     }
 }
@@ -294,6 +762,21 @@ fn parse_date_param(s: &str) -> DiaryEntryKey {
     }
 }
 
+/// Parses the `end` of a date range. A date without a time covers the whole
+/// day.
+fn parse_range_param(s: &str, end: RangeEnd) -> DiaryEntryKey {
+    if let Some(key) = DiaryEntryKey::parse_from_string(s) {
+        key
+    } else if let Some(ldt) = parse_range_end_with_clock(s, end, &Local, Local::now) {
+        DiaryEntryKey {
+            date: ldt.with_timezone(&Utc),
+        }
+    } else {
+        eprintln!("Failed to parse date {}", s);
+        process::exit(1);
+    }
+}
+
 fn parse_local_date(s: &str) -> Option<DiaryEntryKey> {
     parse_local_datetime(s).map(|ldt| DiaryEntryKey {
         date: ldt.with_timezone(&Utc),
@@ -301,37 +784,9 @@ fn parse_local_date(s: &str) -> Option<DiaryEntryKey> {
 }
 
 fn parse_local_datetime(s: &str) -> Option<DateTime<Local>> {
-    parse_local_datetime_with_clock(s, &Local::now)
+    parse_local_datetime_with_clock(s, &Local, Local::now)
 }
 
-fn parse_local_datetime_with_clock<C>(s: &str, clock: C) -> Option<DateTime<Local>>
-where
-    C: Fn() -> DateTime<Local>,
-{
-    DATETIME_FORMATS
-        .iter()
-        .find_map(|fmt| Local.datetime_from_str(s, fmt).ok())
-        .or_else(|| {
-            NaiveDate::parse_from_str(s, "%Y-%m-%d")
-                .ok()
-                .map(|nd| nd.and_hms(12, 0, 0))
-                .and_then(|ndt| Local.from_local_datetime(&ndt).latest())
-        })
-        .or_else(|| {
-            TIME_FORMATS.iter().find_map(|fmt| {
-                NaiveTime::parse_from_str(s, fmt)
-                    .ok()
-                    .map(|nt| clock().date().naive_local().and_time(nt))
-                    .and_then(|ndt| Local.from_local_datetime(&ndt).latest())
-            })
-        })
-}
-
-const DATETIME_FORMATS: &'static [&'static str] = &["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"];
-
-const TIME_FORMATS: &'static [&'static str] =
-    &["%l:%M%P", "%I:%M%P", "%l:%M%p", "%I:%M%p", "%H:%M", "%H%M"];
-
 fn check_entry_number(number: usize, keys: &[DiaryEntryKey]) {
     if number > keys.len() {
         eprintln!("Invalid entry number {}", number);
@@ -352,7 +807,7 @@ fn add_entry_with_args(diary: &CLIDiary, matches: &clap::ArgMatches) {
 fn add_entry(diary: &CLIDiary, editor: AddEditor, key: Option<DiaryEntryKey>) {
     let entry = match editor {
         AddEditor::Stdin => entryinput::read_from_stdin(),
-        AddEditor::Environment => entryinput::read_from_editor(&""),
+        AddEditor::Environment => entryinput::read_from_editor(""),
     };
     match entry {
         Ok(e) if !e.trim().is_empty() => {
@@ -366,6 +821,23 @@ fn add_entry(diary: &CLIDiary, editor: AddEditor, key: Option<DiaryEntryKey>) {
     }
 }
 
+fn attach_files(diary: &CLIDiary, matches: &clap::ArgMatches) {
+    let key = match matches.value_of(args::attach::DATE).map(parse_date_param) {
+        Some(key) => key,
+        None => match diary.list_keys().pop() {
+            Some(key) => key,
+            None => {
+                eprintln!("No entry to attach to");
+                process::exit(1)
+            }
+        },
+    };
+    for file in matches.values_of(args::attach::FILES).into_iter().flatten() {
+        let name = diary.attach_file(&key, &PathBuf::from(file));
+        println!("Attached {} to entry {}", name, key);
+    }
+}
+
 fn edit_entry_with_args(diary: &CLIDiary, matches: &clap::ArgMatches) {
     let editor = if matches.is_present(args::edit::STDIN) {
         AddEditor::Stdin
@@ -411,11 +883,65 @@ fn tags_with_args(diary: &CLIDiary, tags_matches: &clap::ArgMatches) {
     if let Some(tags_values) = tags_matches.values_of(args::tags::SEARCH) {
         let tags: Vec<&str> = tags_values.collect();
         search_tags(diary, &tags)
+    } else if tags_matches.is_present(args::tags::LIST) {
+        list_tags(diary)
+    } else if let Some(tag) = tags_matches.value_of(args::tags::RELATED) {
+        related_tags(diary, tag)
+    } else if tags_matches.is_present(args::tags::DOT) {
+        print!("{}", diary.tag_graph_dot())
+    } else if let Some(alias_values) = tags_matches.values_of(args::tags::ALIAS) {
+        let alias: Vec<&str> = alias_values.collect();
+        diary.add_tag_alias(alias[0], alias[1])
+    } else if let Some(alias) = tags_matches.value_of(args::tags::UNALIAS) {
+        if !diary.remove_tag_alias(alias) {
+            eprintln!("{} is not an alias", alias);
+            process::exit(1)
+        }
     } else if tags_matches.is_present(args::tags::REINDEX) {
         reindex(diary)
     }
 }
 
+fn list_tags(diary: &CLIDiary) {
+    let tags = diary.list_tags();
+    let width = tags
+        .iter()
+        .map(|t| t.count.to_string().len())
+        .max()
+        .unwrap_or(0);
+    for tag in tags {
+        if tag.aliases.is_empty() {
+            println!("{:width$} {}", tag.count, tag.tag, width = width);
+        } else {
+            println!(
+                "{:width$} {} ({})",
+                tag.count,
+                tag.tag,
+                tag.aliases.join(", "),
+                width = width
+            );
+        }
+    }
+}
+
+fn related_tags(diary: &CLIDiary, tag: &str) {
+    let related = diary.related_tags(tag);
+    let width = related
+        .iter()
+        .map(|t| t.count.to_string().len())
+        .max()
+        .unwrap_or(0);
+    for tag in related {
+        println!(
+            "{:width$} {:5.2} {}",
+            tag.count,
+            tag.lift,
+            tag.tag,
+            width = width
+        );
+    }
+}
+
 fn search_tags(diary: &CLIDiary, tags: &[&str]) {
     let keys = diary.search_tags(tags);
     let entry_list = make_entry_list(&keys, ListOption::Plain, KeyOrdering::LatestFirst);
@@ -424,6 +950,412 @@ fn search_tags(diary: &CLIDiary, tags: &[&str]) {
     }
 }
 
+fn show_stats(diary: &CLIDiary, matches: &clap::ArgMatches) {
+    let from = matches
+        .value_of(args::stats::FROM)
+        .map(|s| parse_range_param(s, RangeEnd::From));
+    let to = matches
+        .value_of(args::stats::TO)
+        .map(|s| parse_range_param(s, RangeEnd::To));
+    let stats = diary.stats(from.as_ref(), to.as_ref());
+    println!("Entries: {}", stats.entries);
+    println!("Words: {}", stats.words);
+    println!("Average words per entry: {:.1}", stats.average_words);
+    println!("Current streak: {} days", stats.current_streak);
+    println!("Longest streak: {} days", stats.longest_streak);
+    if !stats.entries_per_month.is_empty() {
+        println!();
+        println!("Entries per month:");
+        for ((year, month), count) in &stats.entries_per_month {
+            println!("  {:04}-{:02} {:5}", year, month, count);
+        }
+    }
+    println!();
+    println!("Entries per weekday:");
+    for (name, count) in WEEKDAY_NAMES.iter().zip(stats.entries_per_weekday.iter()) {
+        println!("  {} {:5}", name, count);
+    }
+    if !stats.tags.is_empty() {
+        println!();
+        println!("Most used tags:");
+        for tag in stats.tags.iter().take(STATS_TAG_COUNT) {
+            println!("  {:5} {}", tag.count, tag.tag);
+        }
+    }
+}
+
+const WEEKDAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+const STATS_TAG_COUNT: usize = 10;
+
+fn sync_diaries(diary: &CLIDiary, matches: &clap::ArgMatches, lock_timeout: Duration) {
+    let other_path = PathBuf::from(matches.value_of(args::sync::OTHER_PATH).unwrap_or_default());
+    if !other_path.is_dir() {
+        eprintln!("Not a directory: {}", other_path.display());
+        process::exit(1)
+    }
+    let other = CLIDiary::open(&other_path, lock_timeout);
+    let conflicts =
+        matches
+            .value_of(args::sync::CONFLICTS)
+            .unwrap_or(if io::stdin().is_terminal() {
+                "prompt"
+            } else {
+                "both"
+            });
+    let report = diary.sync_with(&other, |conflict| match conflicts {
+        "both" => Resolution::KeepBoth,
+        "markers" => Resolution::Markers,
+        "this" => Resolution::KeepThis,
+        "other" => Resolution::KeepOther,
+        _ => prompt_resolution(conflict),
+    });
+    for key in &report.copied_here {
+        println!("< {}", key);
+    }
+    for key in &report.copied_there {
+        println!("> {}", key);
+    }
+    for (key, _) in &report.conflicts {
+        println!("! {}", key);
+    }
+}
+
+fn prompt_resolution(conflict: &Conflict) -> Resolution {
+    println!("Entry {} differs in the two diaries.", conflict.key);
+    println!("--- This diary:");
+    println!("{}", conflict.this_text.trim_end());
+    println!("--- Other diary:");
+    println!("{}", conflict.other_text.trim_end());
+    loop {
+        print!("Keep [t]his, [o]ther, [b]oth or [m]erge with conflict markers? ");
+        let _ = io::stdout().flush();
+        let mut answer = String::new();
+        match io::stdin().lock().read_line(&mut answer) {
+            Ok(0) | Err(_) => return Resolution::KeepBoth,
+            Ok(_) => (),
+        }
+        match answer.trim() {
+            "t" => return Resolution::KeepThis,
+            "o" => return Resolution::KeepOther,
+            "b" => return Resolution::KeepBoth,
+            "m" => return Resolution::Markers,
+            _ => (),
+        }
+    }
+}
+
+fn import_entries(diary: &CLIDiary, matches: &clap::ArgMatches) {
+    let file = matches.value_of(args::import::FILE).unwrap_or_default();
+    let read = match matches.value_of(args::import::FROM) {
+        Some("dayone") => read_dayone(Path::new(file)),
+        Some("text") => match fs::read_to_string(file) {
+            Ok(input) => split_text(
+                &input,
+                matches
+                    .value_of(args::import::SPLIT_REGEX)
+                    .unwrap_or(DEFAULT_SPLIT_REGEX),
+                &Local,
+                Local::now,
+            ),
+            Err(e) => Err(e.into()),
+        },
+        Some("markdown") => {
+            read_markdown_notes(Path::new(file), &markdown_options(matches), &Local)
+        }
+        _ => match fs::read_to_string(file) {
+            Ok(input) => parse_jrnl(&input, &Local),
+            Err(e) => Err(e.into()),
+        },
+    };
+    let entries = match read {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", file, e);
+            process::exit(1)
+        }
+    };
+    let behavior = match matches.value_of(args::import::ON_COLLISION) {
+        Some("overwrite") => MatchingDateBehavior::Overwrite,
+        _ => MatchingDateBehavior::Append,
+    };
+    let dry_run = matches.is_present(args::import::DRY_RUN);
+    let report = diary.import(&entries, behavior, dry_run);
+    for record in report.skipped.iter() {
+        eprintln!("Skipped {}: {}", record.record, record.reason);
+    }
+    if dry_run {
+        for key in report.imported.iter() {
+            if report.collisions.contains(key) {
+                println!("{} (already has an entry)", key);
+            } else {
+                println!("{}", key);
+            }
+        }
+        println!(
+            "Would import {} entries, {} at dates that already have entries",
+            report.imported.len(),
+            report.collisions.len()
+        );
+    } else {
+        println!("Imported {} entries", report.imported.len());
+    }
+}
+
+fn export_entries(diary: &CLIDiary, matches: &clap::ArgMatches) {
+    let format = match matches.value_of(args::export::FORMAT) {
+        Some("text") => ExportFormat::Text,
+        Some("jrnl") => ExportFormat::Jrnl,
+        Some("dayone") => ExportFormat::DayOne,
+        _ => ExportFormat::Markdown,
+    };
+    let output = matches.value_of(args::export::OUTPUT);
+    if format == ExportFormat::DayOne && output.is_none() {
+        eprintln!("Day One exports are zip files, give a file to write to with --output");
+        process::exit(1)
+    }
+    let mut options = ExportOptions {
+        from: matches
+            .value_of(args::export::FROM)
+            .map(|s| parse_range_param(s, RangeEnd::From)),
+        to: matches
+            .value_of(args::export::TO)
+            .map(|s| parse_range_param(s, RangeEnd::To)),
+        tags: matches
+            .values_of(args::export::TAG)
+            .map(|tags| tags.map(String::from).collect())
+            .unwrap_or_default(),
+        ..ExportOptions::default()
+    };
+    if let Some(title) = matches.value_of(args::export::TITLE) {
+        options.title = title.to_string();
+    }
+    let (count, document) = match matches.value_of(args::export::TIME_ZONE) {
+        Some(name) => match name.parse::<chrono_tz::Tz>() {
+            Ok(tz) => {
+                options.time_zone = Some(name.to_string());
+                diary.export(format, &options, &tz)
+            }
+            Err(_) => {
+                eprintln!("Unknown time zone {}", name);
+                process::exit(1)
+            }
+        },
+        None => {
+            options.time_zone = iana_time_zone::get_timezone().ok();
+            diary.export(format, &options, &Local)
+        }
+    };
+    match output {
+        Some(output) => {
+            if let Err(e) = fs::write(output, &document) {
+                eprintln!("Error writing {}: {}", output, e);
+                process::exit(1)
+            }
+            eprintln!("Exported {} entries to {}", count, output);
+        }
+        None => {
+            if let Err(e) = io::stdout().write_all(&document) {
+                eprintln!("Error writing document: {}", e);
+                process::exit(1)
+            }
+        }
+    }
+}
+
+fn markdown_options(matches: &clap::ArgMatches) -> MarkdownOptions {
+    let mut options = MarkdownOptions::default();
+    if let Some(pattern) = matches.value_of(args::import::DATE_PATTERN) {
+        options.file_name_pattern = pattern.to_string();
+    }
+    if let Some(time) = matches.value_of(args::import::DEFAULT_TIME) {
+        match NaiveTime::parse_from_str(time, "%H:%M") {
+            Ok(time) => options.default_time = time,
+            Err(_) => {
+                eprintln!("Invalid time {}, expected HH:MM", time);
+                process::exit(1)
+            }
+        }
+    }
+    options
+}
+
+fn watch_diary(diary: &CLIDiary, path: &Path, matches: &clap::ArgMatches) {
+    let debounce = match matches
+        .value_of(args::watch::DEBOUNCE)
+        .map(|s| s.parse::<u64>())
+    {
+        Some(Ok(millis)) => Duration::from_millis(millis),
+        Some(Err(_)) => {
+            eprintln!("Invalid number of milliseconds");
+            process::exit(1)
+        }
+        None => Duration::from_millis(500),
+    };
+    eprintln!("{} Watching {}", log_time(), path.display());
+    diary.watch(debounce, |event| {
+        match event {
+            WatchEvent::Indexed(key) => eprintln!("{} Indexed entry {}", log_time(), key),
+            WatchEvent::Removed(key) => {
+                eprintln!("{} Removed entry {} from index", log_time(), key)
+            }
+            WatchEvent::Skipped(key) => {
+                eprintln!("{} Skipped entry {}: not valid UTF-8", log_time(), key)
+            }
+            WatchEvent::Failed(message) => eprintln!("{} Error: {}", log_time(), message),
+        }
+        true
+    });
+}
+
+fn log_time() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn check_diary(diary: &CLIDiary, matches: &clap::ArgMatches) {
+    if matches.is_present(args::check::CONVERT_UTF8) {
+        for (key, encoding) in diary.convert_to_utf8() {
+            println!("Converted entry {} from {}", key, encoding);
+        }
+    }
+    let findings = diary.check(matches.is_present(args::check::FIX));
+    if findings.is_empty() {
+        println!("No problems found");
+        return;
+    }
+    for finding in findings.iter() {
+        let (description, fix) = match &finding.problem {
+            Problem::StrayFile(path) => (format!("Stray file {}", path.display()), ""),
+            Problem::MisplacedEntry(path, key) => (
+                format!("Entry {} in the wrong place: {}", key, path.display()),
+                "moved",
+            ),
+            Problem::InvalidUtf8(key) => (format!("Entry {} isn't valid UTF-8", key), ""),
+            Problem::UnencryptedEntry(key) => (format!("Entry {} isn't encrypted", key), ""),
+            Problem::EmptyEntry(key) => (format!("Entry {} is empty", key), "deleted"),
+            Problem::MissingEntry(key) => (
+                format!("Index has entry {} that doesn't exist", key),
+                "removed from index",
+            ),
+            Problem::TagMismatch {
+                key,
+                entry_tags,
+                index_tags,
+            } => (
+                format!(
+                    "Tags of entry {} differ from the index: [{}] in entry, [{}] in index",
+                    key,
+                    entry_tags.join(", "),
+                    index_tags.join(", ")
+                ),
+                "reindexed",
+            ),
+        };
+        if finding.fixed {
+            println!("{} ({})", description, fix);
+        } else {
+            println!("{}", description);
+        }
+    }
+    if findings.iter().any(|f| !f.fixed) {
+        process::exit(1)
+    }
+}
+
+fn show_log(diary: &CLIDiary, matches: &clap::ArgMatches) {
+    let count = match matches
+        .value_of(args::log::COUNT)
+        .map(|s| s.parse::<usize>())
+    {
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            eprintln!("Invalid number of changes");
+            process::exit(1)
+        }
+        None => usize::MAX,
+    };
+    for change in diary.change_log().iter().take(count) {
+        println!(
+            "{} {} {}",
+            &change.id[..7],
+            change.time.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            change.message
+        );
+    }
+}
+
+fn show_history(diary: &CLIDiary, matches: &clap::ArgMatches) {
+    let key = parse_date_param(matches.value_of(args::history::DATE).unwrap_or_default());
+    let versions = diary.entry_versions(&key);
+    let width = versions
+        .last()
+        .map(|v| v.number.to_string().len())
+        .unwrap_or(0);
+    for version in versions {
+        println!(
+            "{:width$} {}",
+            version.number,
+            version
+                .saved
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            width = width
+        );
+    }
+}
+
+fn show_diff(diary: &CLIDiary, matches: &clap::ArgMatches) {
+    let key = parse_date_param(matches.value_of(args::diff::DATE).unwrap_or_default());
+    let from = match matches.value_of(args::diff::FROM) {
+        Some(s) => Some(parse_version_param(s)),
+        None => match diary.entry_versions(&key).last() {
+            Some(version) => Some(version.number),
+            None => {
+                eprintln!("Entry {} has no earlier versions", key);
+                process::exit(1)
+            }
+        },
+    };
+    let to = matches.value_of(args::diff::TO).map(parse_version_param);
+    print!("{}", diary.diff_entry_versions(&key, from, to));
+}
+
+fn restore_version(diary: &CLIDiary, matches: &clap::ArgMatches) {
+    let key = parse_date_param(matches.value_of(args::restore::DATE).unwrap_or_default());
+    let number = parse_version_param(matches.value_of(args::restore::VERSION).unwrap_or_default());
+    diary.restore_entry_version(&key, number);
+}
+
+fn parse_version_param(s: &str) -> usize {
+    match s.parse::<usize>() {
+        Ok(number) => number,
+        Err(_) => {
+            eprintln!("Invalid version number {}", s);
+            process::exit(1)
+        }
+    }
+}
+
+fn migrate_layout(path: &Path, matches: &clap::ArgMatches, lock_timeout: Duration) {
+    let mut layout = CLIDiary::layout(path);
+    if let Some(extension) = matches
+        .value_of(args::migrate_layout::EXTENSION)
+        .and_then(Extension::parse)
+    {
+        layout.extension = extension;
+    }
+    if let Some(directories) = matches
+        .value_of(args::migrate_layout::DIRECTORIES)
+        .and_then(DirectoryScheme::parse)
+    {
+        layout.directories = directories;
+    }
+    if let Some(file_name) = matches.value_of(args::migrate_layout::FILE_NAME) {
+        layout.file_name = file_name.to_string();
+    }
+    CLIDiary::migrate_layout(path, layout, lock_timeout);
+}
+
 fn reindex(diary: &CLIDiary) {
     diary.reindex()
 }
@@ -432,6 +1364,7 @@ mod args {
     pub mod opts {
         pub static NAME: &str = "name";
         pub static PATH: &str = "path";
+        pub static WAIT: &str = "wait";
     }
 
     pub mod add {
@@ -440,12 +1373,101 @@ mod args {
         pub static DATE: &str = "date";
     }
 
+    pub mod attach {
+        pub static SUBCOMMAND: &str = "attach";
+        pub static DATE: &str = "date";
+        pub static FILES: &str = "files";
+    }
+
     pub mod edit {
         pub static SUBCOMMAND: &str = "edit";
         pub static STDIN: &str = "stdin";
         pub static DATE: &str = "date";
     }
 
+    pub mod encrypt {
+        pub static SUBCOMMAND: &str = "encrypt";
+        pub static KEEP_GIT_HISTORY: &str = "keep-git-history";
+    }
+
+    pub mod decrypt {
+        pub static SUBCOMMAND: &str = "decrypt";
+    }
+
+    pub mod git {
+        pub static SUBCOMMAND: &str = "git";
+        pub static ENABLE: &str = "enable";
+        pub static DISABLE: &str = "disable";
+    }
+
+    pub mod check {
+        pub static SUBCOMMAND: &str = "check";
+        pub static FIX: &str = "fix";
+        pub static CONVERT_UTF8: &str = "convert_utf8";
+    }
+
+    pub mod import {
+        pub static SUBCOMMAND: &str = "import";
+        pub static FROM: &str = "from";
+        pub static FILE: &str = "file";
+        pub static DRY_RUN: &str = "dry_run";
+        pub static ON_COLLISION: &str = "on_collision";
+        pub static DATE_PATTERN: &str = "date_pattern";
+        pub static DEFAULT_TIME: &str = "default_time";
+        pub static SPLIT_REGEX: &str = "split_regex";
+    }
+
+    pub mod export {
+        pub static SUBCOMMAND: &str = "export";
+        pub static FORMAT: &str = "format";
+        pub static FROM: &str = "from";
+        pub static TO: &str = "to";
+        pub static TAG: &str = "tag";
+        pub static TITLE: &str = "title";
+        pub static TIME_ZONE: &str = "time_zone";
+        pub static OUTPUT: &str = "output";
+    }
+
+    pub mod watch {
+        pub static SUBCOMMAND: &str = "watch";
+        pub static DEBOUNCE: &str = "debounce";
+    }
+
+    pub mod convert {
+        pub static SUBCOMMAND: &str = "convert";
+        pub static TO: &str = "to";
+    }
+
+    pub mod migrate_layout {
+        pub static SUBCOMMAND: &str = "migrate-layout";
+        pub static EXTENSION: &str = "extension";
+        pub static DIRECTORIES: &str = "directories";
+        pub static FILE_NAME: &str = "file_name";
+    }
+
+    pub mod log {
+        pub static SUBCOMMAND: &str = "log";
+        pub static COUNT: &str = "count";
+    }
+
+    pub mod history {
+        pub static SUBCOMMAND: &str = "history";
+        pub static DATE: &str = "date";
+    }
+
+    pub mod diff {
+        pub static SUBCOMMAND: &str = "diff";
+        pub static DATE: &str = "date";
+        pub static FROM: &str = "from";
+        pub static TO: &str = "to";
+    }
+
+    pub mod restore {
+        pub static SUBCOMMAND: &str = "restore";
+        pub static DATE: &str = "date";
+        pub static VERSION: &str = "version";
+    }
+
     pub mod list {
         pub static SUBCOMMAND: &str = "list";
         pub static ENUM: &str = "enumerate";
@@ -458,11 +1480,29 @@ mod args {
         pub static DATE: &str = "date";
         pub static NUMBER: &str = "number";
         pub static NUMBER_REVERSE: &str = "number-reverse";
+        pub static PLAIN: &str = "plain";
+    }
+
+    pub mod stats {
+        pub static SUBCOMMAND: &str = "stats";
+        pub static FROM: &str = "from";
+        pub static TO: &str = "to";
+    }
+
+    pub mod sync {
+        pub static SUBCOMMAND: &str = "sync";
+        pub static OTHER_PATH: &str = "other_path";
+        pub static CONFLICTS: &str = "conflicts";
     }
 
     pub mod tags {
         pub static SUBCOMMAND: &str = "tags";
         pub static SEARCH: &str = "search";
+        pub static LIST: &str = "list";
+        pub static RELATED: &str = "related";
+        pub static DOT: &str = "dot";
+        pub static ALIAS: &str = "alias";
+        pub static UNALIAS: &str = "unalias";
         pub static REINDEX: &str = "reindex";
     }
 }
@@ -475,7 +1515,7 @@ mod tests {
     fn plain_empty_entry_list_works() {
         assert_eq!(
             Vec::<String>::new(),
-            make_entry_list(&(vec![]), ListOption::Plain, KeyOrdering::EarliestFirst)
+            make_entry_list(&[], ListOption::Plain, KeyOrdering::EarliestFirst)
         );
     }
 
@@ -483,7 +1523,7 @@ mod tests {
     fn enumerated_empty_entry_list_works() {
         assert_eq!(
             Vec::<String>::new(),
-            make_entry_list(&(vec![]), ListOption::Enumerate, KeyOrdering::EarliestFirst)
+            make_entry_list(&[], ListOption::Enumerate, KeyOrdering::EarliestFirst)
         );
     }
 
@@ -492,7 +1532,7 @@ mod tests {
         assert_eq!(
             Vec::<String>::new(),
             make_entry_list(
-                &(vec![]),
+                &[],
                 ListOption::EnumerateReverse,
                 KeyOrdering::EarliestFirst
             )
@@ -505,11 +1545,7 @@ mod tests {
         let dts2 = "2020-07-10 20:51 +0000";
         let k1 = DiaryEntryKey::parse_from_string(dts1).expect("Parsing dts1 failed");
         let k2 = DiaryEntryKey::parse_from_string(dts2).expect("Parsing dts2 failed");
-        let entry_list = make_entry_list(
-            &(vec![k1, k2]),
-            ListOption::Plain,
-            KeyOrdering::EarliestFirst,
-        );
+        let entry_list = make_entry_list(&[k1, k2], ListOption::Plain, KeyOrdering::EarliestFirst);
 
         assert_eq!(vec![dts1, dts2], entry_list);
     }
@@ -520,11 +1556,8 @@ mod tests {
         let dts2 = "2020-07-10 20:51 +0000";
         let k1 = DiaryEntryKey::parse_from_string(dts1).expect("Parsing dts1 failed");
         let k2 = DiaryEntryKey::parse_from_string(dts2).expect("Parsing dts2 failed");
-        let entry_list = make_entry_list(
-            &(vec![k1, k2]),
-            ListOption::Enumerate,
-            KeyOrdering::EarliestFirst,
-        );
+        let entry_list =
+            make_entry_list(&[k1, k2], ListOption::Enumerate, KeyOrdering::EarliestFirst);
 
         assert_eq!(
             vec![format!("1 {}", dts1), format!("2 {}", dts2)],
@@ -539,7 +1572,7 @@ mod tests {
         let k1 = DiaryEntryKey::parse_from_string(dts1).expect("Parsing dts1 failed");
         let k2 = DiaryEntryKey::parse_from_string(dts2).expect("Parsing dts2 failed");
         let entry_list = make_entry_list(
-            &(vec![k1, k2]),
+            &[k1, k2],
             ListOption::EnumerateReverse,
             KeyOrdering::EarliestFirst,
         );
@@ -557,7 +1590,7 @@ mod tests {
         let k1 = DiaryEntryKey::parse_from_string(dts1).expect("Parsing dts1 failed");
         let k2 = DiaryEntryKey::parse_from_string(dts2).expect("Parsing dts2 failed");
         let entry_list = make_entry_list(
-            &(vec![k1, k2]),
+            &[k1, k2],
             ListOption::EnumerateReverse,
             KeyOrdering::LatestFirst,
         );
@@ -0,0 +1,137 @@
+use crate::crypto::CryptoError;
+use crate::diary::{Diary, DiaryError};
+use crate::diaryentrykey::DiaryEntryKey;
+use crate::index::tags::TagIndex;
+use crate::storage::{StorageError, StrayFile};
+use crate::tagparser;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// Something wrong with the files of a diary or with its index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// A file whose name isn't the name of an entry.
+    StrayFile(PathBuf),
+    /// An entry file in the wrong directory. It's not listed with the other
+    /// entries.
+    MisplacedEntry(PathBuf, DiaryEntryKey),
+    /// An entry whose text isn't valid UTF-8.
+    InvalidUtf8(DiaryEntryKey),
+    /// An entry of an encrypted diary that isn't encrypted.
+    UnencryptedEntry(DiaryEntryKey),
+    /// An entry without any text.
+    EmptyEntry(DiaryEntryKey),
+    /// The index has tags or statistics for an entry that doesn't exist.
+    MissingEntry(DiaryEntryKey),
+    /// The tags in the text of an entry aren't the ones in the index.
+    TagMismatch {
+        key: DiaryEntryKey,
+        entry_tags: Vec<String>,
+        index_tags: Vec<String>,
+    },
+}
+
+/// A problem found by checking a diary, and whether it was fixed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub problem: Problem,
+    pub fixed: bool,
+}
+
+/// Looks for problems in `diary` and `tag_index`. With `fix`, misplaced
+/// entries are moved where they belong, empty entries are deleted and the
+/// index is corrected. Stray files, entries that aren't UTF-8 and entries
+/// of an encrypted diary that aren't encrypted are only reported.
+pub(crate) fn check(
+    diary: &Diary,
+    tag_index: &TagIndex,
+    fix: bool,
+) -> Result<Vec<Finding>, DiaryError> {
+    let _lock = if fix { diary.lock()? } else { None };
+    let storage = diary.storage();
+    let mut findings = Vec::new();
+    if let Some(files) = storage.entry_files() {
+        for stray in files.stray_files()? {
+            let finding = match stray {
+                StrayFile::Unknown(path) => Finding {
+                    problem: Problem::StrayFile(path),
+                    fixed: false,
+                },
+                StrayFile::Misplaced(path, date) => Finding {
+                    fixed: fix && files.move_entry_file(&path, &date)?,
+                    problem: Problem::MisplacedEntry(path, DiaryEntryKey { date }),
+                },
+            };
+            findings.push(finding);
+        }
+    }
+
+    let keys = diary.list_keys()?;
+    for key in keys.iter() {
+        let text = match storage.get(&key.date) {
+            Ok(text) => text,
+            Err(StorageError::EntryContentDecodingError(_)) => {
+                findings.push(Finding {
+                    problem: Problem::InvalidUtf8(key.clone()),
+                    fixed: false,
+                });
+                continue;
+            }
+            Err(StorageError::EntryDecryptionError(_, CryptoError::Unencrypted)) => {
+                findings.push(Finding {
+                    problem: Problem::UnencryptedEntry(key.clone()),
+                    fixed: false,
+                });
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if text.trim().is_empty() {
+            if fix {
+                diary.remove_entry(tag_index, key)?;
+            }
+            findings.push(Finding {
+                problem: Problem::EmptyEntry(key.clone()),
+                fixed: fix,
+            });
+            continue;
+        }
+        let entry_tags: Vec<String> = tagparser::find_tags(&text)
+            .into_iter()
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect();
+        let index_tags = tag_index.tags_for_entry(key)?;
+        if entry_tags != index_tags {
+            if fix {
+                diary.update_index(tag_index, key, &text)?;
+            }
+            findings.push(Finding {
+                problem: Problem::TagMismatch {
+                    key: key.clone(),
+                    entry_tags,
+                    index_tags,
+                },
+                fixed: fix,
+            });
+        }
+    }
+
+    let keys: BTreeSet<DiaryEntryKey> = keys.into_iter().collect();
+    for key in tag_index.indexed_keys()? {
+        if !keys.contains(&key) {
+            if fix {
+                tag_index.remove_entry(&key)?;
+            }
+            findings.push(Finding {
+                problem: Problem::MissingEntry(key),
+                fixed: fix,
+            });
+        }
+    }
+
+    if findings.iter().any(|f| f.fixed) {
+        diary.commit("Fix diary problems")?;
+    }
+    Ok(findings)
+}
@@ -1,15 +1,33 @@
-use diary_core::{Diary, DiaryEntryKey, MatchingDateBehavior, TagIndex};
+use crate::highlight;
+use crate::passphrase;
+use chrono::{Local, TimeZone};
+use diary_core::{
+    Change, Conflict, Diary, DiaryEntryKey, DiaryStats, EntryVersion, ExportFormat, ExportOptions,
+    Finding, Import, ImportReport, Layout, MatchingDateBehavior, RelatedTag, Resolution,
+    StorageFormat, SyncReport, TagCount, TagIndex, WatchEvent,
+};
+use std::fmt;
+use std::fs;
 use std::path::Path;
 use std::process;
+use std::time::Duration;
 
 pub struct CLIDiary<'a> {
     pub diary: Diary<'a>,
 }
 
 impl<'a> CLIDiary<'a> {
-    pub fn open(path: &Path) -> CLIDiary {
-        match Diary::open(path) {
-            Ok(diary) => CLIDiary { diary },
+    pub fn open(path: &Path, lock_timeout: Duration) -> CLIDiary<'_> {
+        let opened = match Diary::is_encrypted(path) {
+            Ok(true) => Diary::open_encrypted(path, &read_passphrase()),
+            Ok(false) => Diary::open(path),
+            Err(err) => Err(err),
+        };
+        match opened {
+            Ok(mut diary) => {
+                diary.set_lock_timeout(lock_timeout);
+                CLIDiary { diary }
+            }
             Err(err) => {
                 eprintln!("Error opening diary: {}", err);
                 process::exit(1)
@@ -17,8 +35,128 @@ impl<'a> CLIDiary<'a> {
         }
     }
 
-    pub fn show_entry(&self, key: &DiaryEntryKey) {
-        println!("{}", self.text_for_entry(key))
+    pub fn encrypt(path: &Path, keep_git_history: bool, lock_timeout: Duration) {
+        match Diary::uses_git(path) {
+            Ok(true) if !keep_git_history => {
+                eprintln!(
+                    "The diary uses git, and encrypting it leaves the entries in plain text in \
+                     its earlier commits. Use --keep-git-history to encrypt it anyway."
+                );
+                process::exit(1)
+            }
+            Ok(true) => eprintln!(
+                "Warning: the earlier git commits of the diary still have the entries in plain \
+                 text"
+            ),
+            Ok(false) => (),
+            Err(err) => {
+                eprintln!("Error encrypting diary: {}", err);
+                process::exit(1)
+            }
+        }
+        let passphrase = match passphrase::read_new_passphrase() {
+            Ok(passphrase) => passphrase,
+            Err(err) => {
+                eprintln!("Error reading passphrase: {}", err);
+                process::exit(1)
+            }
+        };
+        if let Err(err) = Diary::encrypt_diary(path, &passphrase, keep_git_history, lock_timeout) {
+            eprintln!("Error encrypting diary: {}", err);
+            process::exit(1)
+        }
+    }
+
+    pub fn decrypt(path: &Path, lock_timeout: Duration) {
+        if let Err(err) = Diary::decrypt_diary(path, &read_passphrase(), lock_timeout) {
+            eprintln!("Error decrypting diary: {}", err);
+            process::exit(1)
+        }
+    }
+
+    pub fn set_git_enabled(path: &Path, enabled: bool, lock_timeout: Duration) {
+        let result = if enabled {
+            Diary::enable_git(path, lock_timeout)
+        } else {
+            Diary::disable_git(path, lock_timeout)
+        };
+        if let Err(err) = result {
+            eprintln!("Error configuring git: {}", err);
+            process::exit(1)
+        }
+    }
+
+    pub fn convert_storage(path: &Path, format: StorageFormat, lock_timeout: Duration) {
+        match Diary::convert_storage(path, format, lock_timeout) {
+            Ok(count) => println!("Converted {} entries", count),
+            Err(err) => {
+                eprintln!("Error converting diary: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn layout(path: &Path) -> Layout {
+        Diary::layout(path).unwrap_or_else(|err| {
+            eprintln!("Error reading diary layout: {}", err);
+            process::exit(1)
+        })
+    }
+
+    pub fn migrate_layout(path: &Path, layout: Layout, lock_timeout: Duration) {
+        match Diary::migrate_layout(path, layout, lock_timeout) {
+            Ok(moved) => println!("Moved {} entries", moved),
+            Err(err) => {
+                eprintln!("Error changing layout: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn show_entry(&self, key: &DiaryEntryKey, highlight: bool) {
+        let text = self.text_for_entry(key);
+        if highlight {
+            println!("{}", highlight::highlight(&text))
+        } else {
+            println!("{}", text)
+        }
+        let attachments = self.list_attachments(key);
+        if !attachments.is_empty() {
+            println!("Attachments:");
+            for name in attachments {
+                println!("  {}", name);
+            }
+        }
+    }
+
+    pub fn list_attachments(&self, key: &DiaryEntryKey) -> Vec<String> {
+        match self.diary.list_attachments(key) {
+            Ok(names) => names,
+            Err(err) => {
+                eprintln!("Error listing attachments: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn attach_file(&self, key: &DiaryEntryKey, path: &Path) -> String {
+        let tag_index = self.open_index();
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let result = fs::read(path).map_err(|e| e.to_string()).and_then(|data| {
+            self.diary
+                .attach_file(&tag_index, key, name, &data)
+                .map_err(|e| e.to_string())
+        });
+        match result {
+            Ok(name) => name,
+            Err(err) => {
+                eprintln!("Error attaching {}: {}", path.display(), err);
+                process::exit(1)
+            }
+        }
     }
 
     pub fn text_for_entry(&self, key: &DiaryEntryKey) -> String {
@@ -71,6 +209,139 @@ impl<'a> CLIDiary<'a> {
         }
     }
 
+    pub fn entry_versions(&self, key: &DiaryEntryKey) -> Vec<EntryVersion> {
+        match self.diary.entry_versions(key) {
+            Ok(versions) => versions,
+            Err(err) => {
+                eprintln!("Error listing entry versions: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn diff_entry_versions(
+        &self,
+        key: &DiaryEntryKey,
+        from: Option<usize>,
+        to: Option<usize>,
+    ) -> String {
+        match self.diary.diff_entry_versions(key, from, to) {
+            Ok(diff) => diff,
+            Err(err) => {
+                eprintln!("Error comparing entry versions: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn restore_entry_version(&self, key: &DiaryEntryKey, number: usize) {
+        let tag_index = self.open_index();
+        if let Err(err) = self.diary.restore_entry_version(&tag_index, key, number) {
+            eprintln!("Error restoring entry version: {}", err);
+            process::exit(1)
+        }
+    }
+
+    pub fn change_log(&self) -> Vec<Change> {
+        match self.diary.change_log() {
+            Ok(changes) => changes,
+            Err(err) => {
+                eprintln!("Error reading change log: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn sync_with<F>(&self, other: &CLIDiary, resolve: F) -> SyncReport
+    where
+        F: FnMut(&Conflict) -> Resolution,
+    {
+        let tag_index = self.open_index();
+        let other_index = other.open_index();
+        match self
+            .diary
+            .sync_with(&tag_index, &other.diary, &other_index, resolve)
+        {
+            Ok(report) => report,
+            Err(err) => {
+                eprintln!("Error syncing diaries: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn check(&self, fix: bool) -> Vec<Finding> {
+        let tag_index = self.open_index();
+        match self.diary.check(&tag_index, fix) {
+            Ok(findings) => findings,
+            Err(err) => {
+                eprintln!("Error checking diary: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn import(
+        &self,
+        entries: &Import,
+        matching_date_behavior: MatchingDateBehavior,
+        dry_run: bool,
+    ) -> ImportReport {
+        let tag_index = self.open_index();
+        match self
+            .diary
+            .import(&tag_index, entries, matching_date_behavior, dry_run)
+        {
+            Ok(report) => report,
+            Err(err) => {
+                eprintln!("Error importing entries: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn export<Tz>(
+        &self,
+        format: ExportFormat,
+        options: &ExportOptions,
+        tz: &Tz,
+    ) -> (usize, Vec<u8>)
+    where
+        Tz: TimeZone,
+        Tz::Offset: fmt::Display,
+    {
+        let tag_index = self.open_index();
+        match self.diary.export(&tag_index, format, options, tz) {
+            Ok(export) => export,
+            Err(err) => {
+                eprintln!("Error exporting entries: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn watch<F>(&self, debounce: Duration, report: F)
+    where
+        F: FnMut(&WatchEvent) -> bool,
+    {
+        let tag_index = self.open_index();
+        if let Err(err) = self.diary.watch(&tag_index, debounce, report) {
+            eprintln!("Error watching diary: {}", err);
+            process::exit(1)
+        }
+    }
+
+    pub fn convert_to_utf8(&self) -> Vec<(DiaryEntryKey, String)> {
+        let tag_index = self.open_index();
+        match self.diary.convert_to_utf8(&tag_index) {
+            Ok(converted) => converted,
+            Err(err) => {
+                eprintln!("Error converting entries: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
     pub fn search_tags(&self, tags: &[&str]) -> Vec<DiaryEntryKey> {
         let tag_index = self.open_index();
         match self.diary.search_tags(&tag_index, tags) {
@@ -82,10 +353,80 @@ impl<'a> CLIDiary<'a> {
         }
     }
 
+    pub fn list_tags(&self) -> Vec<TagCount> {
+        let tag_index = self.open_index();
+        match self.diary.list_tags(&tag_index) {
+            Ok(tags) => tags,
+            Err(err) => {
+                eprintln!("Error listing tags: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn related_tags(&self, tag: &str) -> Vec<RelatedTag> {
+        let tag_index = self.open_index();
+        match self.diary.related_tags(&tag_index, tag) {
+            Ok(tags) => tags,
+            Err(err) => {
+                eprintln!("Error finding related tags: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn tag_graph_dot(&self) -> String {
+        let tag_index = self.open_index();
+        match self.diary.tag_graph_dot(&tag_index) {
+            Ok(dot) => dot,
+            Err(err) => {
+                eprintln!("Error creating tag graph: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn add_tag_alias(&self, alias: &str, canonical: &str) {
+        let tag_index = self.open_index();
+        if let Err(err) = self.diary.add_tag_alias(&tag_index, alias, canonical) {
+            eprintln!("Error adding tag alias: {}", err);
+            process::exit(1)
+        }
+    }
+
+    pub fn remove_tag_alias(&self, alias: &str) -> bool {
+        let tag_index = self.open_index();
+        match self.diary.remove_tag_alias(&tag_index, alias) {
+            Ok(removed) => removed,
+            Err(err) => {
+                eprintln!("Error removing tag alias: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
+    pub fn stats(&self, from: Option<&DiaryEntryKey>, to: Option<&DiaryEntryKey>) -> DiaryStats {
+        let tag_index = self.open_index();
+        match self.diary.stats(&tag_index, from, to, &Local) {
+            Ok(stats) => stats,
+            Err(err) => {
+                eprintln!("Error collecting statistics: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+
     pub fn reindex(&self) {
         let tag_index = self.open_index();
         match self.diary.reindex(&tag_index) {
-            Ok(_) => (),
+            Ok(skipped) => {
+                for key in skipped {
+                    eprintln!(
+                        "Skipped entry {}: not valid UTF-8, convert it with `ddiary check --convert-utf8`",
+                        key
+                    );
+                }
+            }
             Err(err) => {
                 eprintln!("Error reindexing: {}", err);
                 process::exit(1)
@@ -103,3 +444,13 @@ impl<'a> CLIDiary<'a> {
         }
     }
 }
+
+fn read_passphrase() -> String {
+    match passphrase::read_passphrase("Passphrase: ") {
+        Ok(passphrase) => passphrase,
+        Err(err) => {
+            eprintln!("Error reading passphrase: {}", err);
+            process::exit(1)
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::filerepo::layout::Layout;
+use crate::filerepo::tree::write_atomically;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Settings of a diary, kept in `config.toml` in the diary directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiaryConfig {
+    #[serde(default)]
+    pub storage: StorageFormat,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionConfig>,
+    #[serde(default)]
+    pub git: GitConfig,
+    /// How entry files are named and arranged.
+    #[serde(default)]
+    pub layout: Layout,
+    #[serde(default)]
+    pub encoding: EncodingConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncodingConfig {
+    /// The encoding of entry files that aren't UTF-8 or UTF-16, used when
+    /// converting them to UTF-8.
+    #[serde(default = "default_fallback_encoding")]
+    pub fallback: String,
+}
+
+impl Default for EncodingConfig {
+    fn default() -> Self {
+        EncodingConfig {
+            fallback: default_fallback_encoding(),
+        }
+    }
+}
+
+fn default_fallback_encoding() -> String {
+    "windows-1252".to_string()
+}
+
+/// Where the entries of a diary are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageFormat {
+    /// A file per entry in the diary directory.
+    #[default]
+    Tree,
+    /// A single SQLite database shared with the tag index.
+    Sqlite,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitConfig {
+    /// Commit every change to a git repository in the diary directory.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// What's needed to derive the key of an encrypted diary and to check a
+/// passphrase.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    /// Base64 encoded Argon2 salt.
+    pub salt: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// A known text encrypted with the diary key.
+    pub check: String,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    IoError(io::Error),
+    ParseError(toml::de::Error),
+    SerializeError(toml::ser::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::IoError(e) => write!(f, "IO Error: {}", e),
+            ConfigError::ParseError(e) => write!(f, "Invalid configuration: {}", e),
+            ConfigError::SerializeError(e) => write!(f, "Error writing configuration: {}", e),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self {
+        ConfigError::IoError(error)
+    }
+}
+
+impl DiaryConfig {
+    /// Reads the configuration of the diary in `root`. A diary without a
+    /// configuration file uses the defaults.
+    pub fn load(root: &Path) -> Result<DiaryConfig, ConfigError> {
+        match fs::read_to_string(root.join(CONFIG_FILE_NAME)) {
+            Ok(text) => toml::from_str(&text).map_err(ConfigError::ParseError),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(DiaryConfig::default()),
+            Err(e) => Err(ConfigError::IoError(e)),
+        }
+    }
+
+    pub fn save(&self, root: &Path) -> Result<(), ConfigError> {
+        let text = toml::to_string(self).map_err(ConfigError::SerializeError)?;
+        write_atomically(root, CONFIG_FILE_NAME, text.as_bytes())?;
+        Ok(())
+    }
+}
+
+pub static CONFIG_FILE_NAME: &str = "config.toml";
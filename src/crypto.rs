@@ -0,0 +1,209 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use std::error::Error;
+use std::fmt;
+
+/// Encrypts and decrypts entry text with XChaCha20-Poly1305, using a key
+/// derived from a passphrase with Argon2id.
+///
+/// Encrypted text is stored armored: a header line followed by the base64
+/// encoded nonce and ciphertext.
+#[derive(Clone)]
+pub struct Cipher {
+    aead: XChaCha20Poly1305,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CryptoError {
+    KeyDerivationError(argon2::Error),
+    /// The text couldn't be decrypted: it was encrypted with another key or
+    /// it has been damaged.
+    DecryptionFailed,
+    MalformedText,
+    WrongPassphrase,
+    NotEncrypted,
+    /// The text was expected to be encrypted but has no armor header.
+    Unencrypted,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CryptoError::KeyDerivationError(e) => write!(f, "Error deriving key: {}", e),
+            CryptoError::DecryptionFailed => write!(f, "Decryption failed"),
+            CryptoError::MalformedText => write!(f, "Malformed encrypted text"),
+            CryptoError::WrongPassphrase => write!(f, "Wrong passphrase"),
+            CryptoError::NotEncrypted => write!(f, "Diary is not encrypted"),
+            CryptoError::Unencrypted => write!(f, "Text is not encrypted"),
+        }
+    }
+}
+
+impl Error for CryptoError {}
+
+impl From<argon2::Error> for CryptoError {
+    fn from(error: argon2::Error) -> Self {
+        CryptoError::KeyDerivationError(error)
+    }
+}
+
+pub type CryptoResult<T> = Result<T, CryptoError>;
+
+impl Cipher {
+    pub fn new(passphrase: &str, salt: &[u8], params: KdfParams) -> CryptoResult<Cipher> {
+        let argon2 = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(
+                params.memory_kib,
+                params.iterations,
+                params.parallelism,
+                Some(KEY_LENGTH),
+            )?,
+        );
+        let mut key = [0u8; KEY_LENGTH];
+        argon2.hash_password_into(passphrase.as_bytes(), salt, &mut key)?;
+        Ok(Cipher {
+            aead: XChaCha20Poly1305::new(Key::from_slice(&key)),
+        })
+    }
+
+    pub fn encrypt(&self, text: &str) -> String {
+        self.encrypt_bytes(text.as_bytes())
+    }
+
+    /// Decrypts armored `text`. Text that isn't encrypted is an error, like
+    /// in `decrypt_bytes`.
+    pub fn decrypt(&self, text: &str) -> CryptoResult<String> {
+        let plaintext = self.decrypt_bytes(text.as_bytes())?;
+        String::from_utf8(plaintext).map_err(|_| CryptoError::MalformedText)
+    }
+
+    /// Encrypts `data` into armored text.
+    pub fn encrypt_bytes(&self, data: &[u8]) -> String {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, data)
+            .expect("Encrypting into a Vec can't fail");
+        let mut armored = nonce.to_vec();
+        armored.extend(ciphertext);
+        format!("{}\n{}\n", HEADER, BASE64.encode(armored))
+    }
+
+    /// Decrypts armored `data`. Data without the armor header is an error;
+    /// converting a diary checks `is_encrypted` to tell plain text apart.
+    pub fn decrypt_bytes(&self, data: &[u8]) -> CryptoResult<Vec<u8>> {
+        let body = armored_body(data).ok_or(CryptoError::Unencrypted)?;
+        let decoded = BASE64
+            .decode(body.trim_ascii())
+            .map_err(|_| CryptoError::MalformedText)?;
+        if decoded.len() < NONCE_LENGTH {
+            return Err(CryptoError::MalformedText);
+        }
+        let (nonce, ciphertext) = decoded.split_at(NONCE_LENGTH);
+        self.aead
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CryptoError::DecryptionFailed)
+    }
+}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cipher")
+    }
+}
+
+pub fn is_encrypted<T: AsRef<[u8]>>(data: T) -> bool {
+    armored_body(data.as_ref()).is_some()
+}
+
+pub fn generate_salt() -> Vec<u8> {
+    let mut salt = vec![0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+fn armored_body(data: &[u8]) -> Option<&[u8]> {
+    data.strip_prefix(HEADER.as_bytes())
+        .and_then(|rest| rest.strip_prefix(b"\n"))
+}
+
+static HEADER: &str = "ddiary-encrypted-v1";
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 24;
+const SALT_LENGTH: usize = 16;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(passphrase: &str) -> Cipher {
+        let params = KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        Cipher::new(passphrase, b"saltsaltsalt", params).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cipher = cipher("secret");
+        let encrypted = cipher.encrypt("Dear diary, #secret");
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("secret"));
+        assert_eq!("Dear diary, #secret", cipher.decrypt(&encrypted).unwrap());
+        assert!(matches!(
+            cipher.decrypt("plain text"),
+            Err(CryptoError::Unencrypted)
+        ));
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let cipher = cipher("secret");
+        let data = [0u8, 159, 146, 150, 255];
+        let encrypted = cipher.encrypt_bytes(&data);
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(
+            data.to_vec(),
+            cipher.decrypt_bytes(encrypted.as_bytes()).unwrap()
+        );
+        assert!(matches!(
+            cipher.decrypt_bytes(&data),
+            Err(CryptoError::Unencrypted)
+        ));
+    }
+
+    #[test]
+    fn test_wrong_key_fails() {
+        let encrypted = cipher("secret").encrypt("Dear diary");
+        assert!(matches!(
+            cipher("guess").decrypt(&encrypted),
+            Err(CryptoError::DecryptionFailed)
+        ));
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+
+/// Parses a date and time written in `tz`, like `2020-09-21 13:37`, a date,
+/// like `2020-09-21`, which means noon on that day, or a time, like `1:37pm`,
+/// which means that time on the day `clock` returns.
+pub fn parse_local_datetime_with_clock<Tz, C>(s: &str, tz: &Tz, clock: C) -> Option<DateTime<Tz>>
+where
+    Tz: TimeZone,
+    C: Fn() -> DateTime<Tz>,
+{
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+        .and_then(|ndt| tz.from_local_datetime(&ndt).latest())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|nd| nd.and_hms_opt(12, 0, 0))
+                .and_then(|ndt| tz.from_local_datetime(&ndt).latest())
+        })
+        .or_else(|| {
+            TIME_FORMATS.iter().find_map(|fmt| {
+                NaiveTime::parse_from_str(s, fmt)
+                    .ok()
+                    .map(|nt| clock().date_naive().and_time(nt))
+                    .and_then(|ndt| tz.from_local_datetime(&ndt).latest())
+            })
+        })
+}
+
+/// The end of a date range that a date is parsed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeEnd {
+    From,
+    To,
+}
+
+/// Parses one end of a date range written in `tz` like
+/// `parse_local_datetime_with_clock`, except that a date without a time
+/// means the start of that day for `RangeEnd::From` and the end of it for
+/// `RangeEnd::To`, so that the range covers both days whole.
+pub fn parse_range_end_with_clock<Tz, C>(
+    s: &str,
+    end: RangeEnd,
+    tz: &Tz,
+    clock: C,
+) -> Option<DateTime<Tz>>
+where
+    Tz: TimeZone,
+    C: Fn() -> DateTime<Tz>,
+{
+    match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        Ok(nd) => match end {
+            RangeEnd::From => nd
+                .and_hms_opt(0, 0, 0)
+                .and_then(|ndt| tz.from_local_datetime(&ndt).earliest()),
+            RangeEnd::To => nd
+                .and_hms_nano_opt(23, 59, 59, 999_999_999)
+                .and_then(|ndt| tz.from_local_datetime(&ndt).latest()),
+        },
+        Err(_) => parse_local_datetime_with_clock(s, tz, clock),
+    }
+}
+
+const DATETIME_FORMATS: &[&str] = &["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"];
+
+const TIME_FORMATS: &[&str] = &["%l:%M%P", "%I:%M%P", "%l:%M%p", "%I:%M%p", "%H:%M", "%H%M"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    #[test]
+    fn test_parse_local_datetime_with_clock() {
+        let tz = FixedOffset::east_opt(3 * 3600).unwrap();
+        let clock = || tz.with_ymd_and_hms(2020, 9, 21, 8, 0, 0).unwrap();
+        let parse =
+            |s| parse_local_datetime_with_clock(s, &tz, clock).map(|dt| dt.with_timezone(&Utc));
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2020, 9, 22, 10, 37, 0).unwrap()),
+            parse("2020-09-22 13:37")
+        );
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2020, 9, 22, 9, 0, 0).unwrap()),
+            parse("2020-09-22")
+        );
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2020, 9, 21, 10, 37, 0).unwrap()),
+            parse("1:37pm")
+        );
+        assert_eq!(None, parse("## 2020-09-22"));
+    }
+
+    #[test]
+    fn test_parse_range_end_with_clock() {
+        let tz = FixedOffset::east_opt(3 * 3600).unwrap();
+        let clock = || tz.with_ymd_and_hms(2020, 9, 21, 8, 0, 0).unwrap();
+        let parse = |s, end| {
+            parse_range_end_with_clock(s, end, &tz, clock).map(|dt| dt.with_timezone(&Utc))
+        };
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2020, 9, 21, 21, 0, 0).unwrap()),
+            parse("2020-09-22", RangeEnd::From)
+        );
+        assert_eq!(
+            Some(
+                Utc.with_ymd_and_hms(2020, 9, 22, 20, 59, 59).unwrap()
+                    + chrono::Duration::nanoseconds(999_999_999)
+            ),
+            parse("2020-09-22", RangeEnd::To)
+        );
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2020, 9, 22, 10, 37, 0).unwrap()),
+            parse("2020-09-22 13:37", RangeEnd::To)
+        );
+    }
+}
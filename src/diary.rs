@@ -1,27 +1,95 @@
+use crate::check::{self, Finding};
+use crate::config::{ConfigError, DiaryConfig, EncryptionConfig, StorageFormat};
+use crate::crypto::{self, Cipher, CryptoError, KdfParams};
 use crate::diaryentrykey::DiaryEntryKey;
+use crate::encoding;
+use crate::export::{self, ExportError, ExportFormat, ExportOptions};
 use crate::filerepo;
-use crate::index::tags::{TagIndex, TagIndexError};
+use crate::filerepo::layout::Layout;
+use crate::filerepo::tree::Tree;
+use crate::git::{Change, GitRepo};
+use crate::history::encrypted::EncryptedHistory;
+use crate::history::file::FileHistory;
+use crate::history::memory::MemoryHistory;
+use crate::history::{EntryVersion, History, HistoryError};
+use crate::import::{self, Import, ImportReport};
+use crate::index::dot;
+use crate::index::tags::{RelatedTag, TagCount, TagIndex, TagIndexError};
+use crate::lock::{DiaryLock, LockError};
+use crate::stats::{self, DiaryStats};
+use crate::storage::encrypted::EncryptedStorage;
+use crate::storage::memory::MemoryStorage;
+use crate::storage::sqlite::SqliteStorage;
+use crate::storage::{Storage, StorageError};
+use crate::sync::{self, Conflict, Resolution, SyncReport};
 use crate::tagparser;
-use chrono::{DateTime, Utc};
+use crate::watch::{self, WatchEvent};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, TimeZone, Utc};
+use encoding_rs::{Encoding, UTF_8};
+use similar::TextDiff;
 use std::error::Error;
 use std::fmt;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 pub struct Diary<'a> {
     clock: Box<dyn Fn() -> DateTime<Utc> + 'a>,
-    tree: filerepo::tree::Tree,
+    storage: Box<dyn Storage + 'a>,
+    history: Box<dyn History + 'a>,
+    /// The repository changes are committed to, if the diary uses git.
+    git: Option<GitRepo>,
+    /// The diary directory, if the diary is kept in one.
+    root: Option<PathBuf>,
+    index_location: IndexLocation,
+    lock_timeout: Duration,
+}
+
+#[derive(Debug)]
+enum IndexLocation {
+    Directory(PathBuf),
+    /// An index every `open_index` shares, e.g. one that's kept in memory.
+    Shared(TagIndex),
 }
 
 impl fmt::Debug for Diary<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Diary").field("tree", &self.tree).finish()
+        f.debug_struct("Diary")
+            .field("storage", &self.storage)
+            .field("history", &self.history)
+            .field("git", &self.git)
+            .field("root", &self.root)
+            .field("index_location", &self.index_location)
+            .field("lock_timeout", &self.lock_timeout)
+            .finish()
     }
 }
 
 #[derive(Debug)]
 pub enum DiaryError {
     FileRepoError(filerepo::tree::FileRepoError),
+    StorageError(StorageError),
+    HistoryError(HistoryError),
     TagIndexError(TagIndexError),
+    LockError(LockError),
+    ConfigError(ConfigError),
+    CryptoError(CryptoError),
+    /// The diary is encrypted and has to be opened with `open_encrypted`.
+    PassphraseRequired,
+    GitError(git2::Error),
+    GitNotEnabled,
+    /// Encrypting the diary would leave its entries in plain text in the
+    /// earlier git commits.
+    GitHistoryNotEncrypted,
+    InvalidAttachmentName(String),
+    UnknownEncoding(String),
+    /// The operation can't be done with the storage format or encryption of
+    /// the diary.
+    Unsupported(&'static str),
+    WatchError(notify::Error),
+    ExportError(ExportError),
 }
 
 impl From<filerepo::tree::FileRepoError> for DiaryError {
@@ -30,17 +98,94 @@ impl From<filerepo::tree::FileRepoError> for DiaryError {
     }
 }
 
+impl From<StorageError> for DiaryError {
+    fn from(error: StorageError) -> DiaryError {
+        match error {
+            StorageError::FileRepoError(e) => DiaryError::FileRepoError(e),
+            e => DiaryError::StorageError(e),
+        }
+    }
+}
+
+impl From<HistoryError> for DiaryError {
+    fn from(error: HistoryError) -> DiaryError {
+        DiaryError::HistoryError(error)
+    }
+}
+
 impl From<TagIndexError> for DiaryError {
     fn from(error: TagIndexError) -> DiaryError {
         DiaryError::TagIndexError(error)
     }
 }
 
+impl From<LockError> for DiaryError {
+    fn from(error: LockError) -> DiaryError {
+        DiaryError::LockError(error)
+    }
+}
+
+impl From<ConfigError> for DiaryError {
+    fn from(error: ConfigError) -> DiaryError {
+        DiaryError::ConfigError(error)
+    }
+}
+
+impl From<CryptoError> for DiaryError {
+    fn from(error: CryptoError) -> DiaryError {
+        DiaryError::CryptoError(error)
+    }
+}
+
+impl From<git2::Error> for DiaryError {
+    fn from(error: git2::Error) -> DiaryError {
+        DiaryError::GitError(error)
+    }
+}
+
+impl From<notify::Error> for DiaryError {
+    fn from(error: notify::Error) -> DiaryError {
+        DiaryError::WatchError(error)
+    }
+}
+
+impl From<ExportError> for DiaryError {
+    fn from(error: ExportError) -> DiaryError {
+        DiaryError::ExportError(error)
+    }
+}
+
 impl fmt::Display for DiaryError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             DiaryError::FileRepoError(e) => write!(f, "File repository error: {}", e),
+            DiaryError::StorageError(e) => write!(f, "Storage error: {}", e),
+            DiaryError::HistoryError(e) => write!(f, "History error: {}", e),
             DiaryError::TagIndexError(e) => write!(f, "Tag index error: {}", e),
+            DiaryError::LockError(LockError::Locked(p)) => write!(
+                f,
+                "Diary is being written to by another process (lock file {})",
+                p.to_str().unwrap_or("(no path)")
+            ),
+            DiaryError::LockError(LockError::IoError(e)) => {
+                write!(f, "Error locking diary: {}", e)
+            }
+            DiaryError::ConfigError(e) => write!(f, "Configuration error: {}", e),
+            DiaryError::CryptoError(e) => write!(f, "Encryption error: {}", e),
+            DiaryError::PassphraseRequired => write!(f, "Diary is encrypted"),
+            DiaryError::GitError(e) => write!(f, "Git error: {}", e),
+            DiaryError::GitNotEnabled => write!(f, "Diary doesn't use git"),
+            DiaryError::GitHistoryNotEncrypted => write!(
+                f,
+                "Diary uses git and its earlier commits would keep the entries in plain text"
+            ),
+            DiaryError::InvalidAttachmentName(name) => {
+                write!(f, "Invalid attachment name: {}", name)
+            }
+            DiaryError::UnknownEncoding(label) => write!(f, "Unknown encoding: {}", label),
+            DiaryError::Unsupported(operation) => write!(f, "Can't {}", operation),
+            DiaryError::WatchError(e) => write!(f, "Watch error: {}", e),
+            DiaryError::ExportError(e) => write!(f, "Export error: {}", e),
         }
     }
 }
@@ -51,24 +196,340 @@ type DiaryResult<T> = Result<T, DiaryError>;
 
 impl<'a> Diary<'a> {
     pub fn open(path: &Path) -> Result<Diary<'a>, DiaryError> {
-        Diary::open_custom(path, Utc::now)
+        let config = DiaryConfig::load(path)?;
+        if config.encryption.is_some() {
+            return Err(DiaryError::PassphraseRequired);
+        }
+        match config.storage {
+            StorageFormat::Tree => Diary::open_custom(path, open_tree(path)?, Utc::now),
+            StorageFormat::Sqlite => Diary::open_sqlite(path),
+        }
+    }
+
+    /// Opens a diary whose entries are in the same SQLite database as its
+    /// tag index, so that every change to an entry and its tags is made in
+    /// one transaction.
+    fn open_sqlite(path: &Path) -> Result<Diary<'a>, DiaryError> {
+        let tag_index = open_database(path)?;
+        let diary = Diary {
+            clock: Box::new(Utc::now),
+            storage: Box::new(SqliteStorage::new(&tag_index)?),
+            history: Box::new(FileHistory::new(path)),
+            git: open_git(path)?,
+            root: Some(path.to_path_buf()),
+            index_location: IndexLocation::Shared(tag_index),
+            lock_timeout: Duration::from_secs(0),
+        };
+        Ok(diary)
+    }
+
+    /// Opens a diary that keeps its entries in `storage`. The tag index and
+    /// the earlier versions of entries are kept in the directory `path`.
+    pub fn open_custom<S, C>(path: &Path, storage: S, clock: C) -> Result<Diary<'a>, DiaryError>
+    where
+        S: Storage + 'a,
+        C: 'a,
+        C: Fn() -> DateTime<Utc>,
+    {
+        let diary = Diary {
+            clock: Box::new(clock),
+            storage: Box::new(storage),
+            history: Box::new(FileHistory::new(path)),
+            git: open_git(path)?,
+            root: Some(path.to_path_buf()),
+            index_location: IndexLocation::Directory(path.to_path_buf()),
+            lock_timeout: Duration::from_secs(0),
+        };
+        Ok(diary)
+    }
+
+    /// Opens an encrypted diary. Its tag index is kept only in memory and
+    /// built from the entries when the diary is opened, so tags never reach
+    /// the disk unencrypted. That also means tag aliases last only as long
+    /// as the `Diary`.
+    pub fn open_encrypted(path: &Path, passphrase: &str) -> Result<Diary<'a>, DiaryError> {
+        require_tree(path, "open an encrypted diary kept in SQLite")?;
+        let cipher = unlock(path, passphrase)?;
+        let diary = Diary {
+            clock: Box::new(Utc::now),
+            storage: Box::new(EncryptedStorage::new(open_tree(path)?, cipher.clone())),
+            history: Box::new(EncryptedHistory::new(FileHistory::new(path), cipher)),
+            git: open_git(path)?,
+            root: Some(path.to_path_buf()),
+            index_location: IndexLocation::Shared(TagIndex::in_memory()?),
+            lock_timeout: Duration::from_secs(0),
+        };
+        let tag_index = diary.open_index()?;
+        diary.index_entries(&tag_index)?;
+        Ok(diary)
+    }
+
+    /// Tells if the diary in `path` is encrypted.
+    pub fn is_encrypted(path: &Path) -> DiaryResult<bool> {
+        Ok(DiaryConfig::load(path)?.encryption.is_some())
+    }
+
+    /// Tells if the diary in `path` commits its changes to git.
+    pub fn uses_git(path: &Path) -> DiaryResult<bool> {
+        Ok(DiaryConfig::load(path)?.git.enabled)
+    }
+
+    /// Encrypts the entries of the diary in `path`, their attachments and
+    /// their earlier versions with a key derived from `passphrase` and
+    /// deletes its tag index. Encrypting a diary that's already encrypted
+    /// finishes an interrupted conversion.
+    ///
+    /// The encrypted entries of a diary that uses git are committed on top
+    /// of its history, so the earlier commits still have them in plain
+    /// text. Such a diary is only encrypted with `keep_git_history`, and
+    /// otherwise this fails with `GitHistoryNotEncrypted`.
+    pub fn encrypt_diary(
+        path: &Path,
+        passphrase: &str,
+        keep_git_history: bool,
+        lock_timeout: Duration,
+    ) -> DiaryResult<()> {
+        require_tree(path, "encrypt a diary kept in SQLite")?;
+        if Diary::uses_git(path)? && !keep_git_history {
+            return Err(DiaryError::GitHistoryNotEncrypted);
+        }
+        let tree = open_tree(path)?;
+        let _lock = DiaryLock::acquire(path, lock_timeout)?;
+        let mut config = DiaryConfig::load(path)?;
+        // Save the key parameters before touching any entries, so that the
+        // diary can be opened whatever point the conversion gets to.
+        let cipher = if config.encryption.is_some() {
+            unlock(path, passphrase)?
+        } else {
+            let salt = crypto::generate_salt();
+            let params = KdfParams::default();
+            let cipher = Cipher::new(passphrase, &salt, params)?;
+            config.encryption = Some(EncryptionConfig {
+                salt: BASE64.encode(&salt),
+                memory_kib: params.memory_kib,
+                iterations: params.iterations,
+                parallelism: params.parallelism,
+                check: cipher.encrypt(CHECK_TEXT),
+            });
+            config.save(path)?;
+            cipher
+        };
+        for dt in tree.list()? {
+            let text = tree.get(&dt)?;
+            if !crypto::is_encrypted(&text) {
+                tree.put(&dt, &cipher.encrypt(&text))?;
+            }
+            for name in tree.list_attachments(&dt)? {
+                let data = tree.get_attachment(&dt, &name)?;
+                if !crypto::is_encrypted(&data) {
+                    tree.put_attachment(&dt, &name, cipher.encrypt_bytes(&data).as_bytes())?;
+                }
+            }
+        }
+        FileHistory::new(path).transform_versions(|data| -> DiaryResult<Option<Vec<u8>>> {
+            if crypto::is_encrypted(data) {
+                Ok(None)
+            } else {
+                Ok(Some(cipher.encrypt_bytes(data).into_bytes()))
+            }
+        })?;
+        TagIndex::remove(path)?;
+        if config.git.enabled {
+            GitRepo::init(path)?.commit_all("Encrypt diary")?;
+        }
+        Ok(())
+    }
+
+    /// Decrypts the entries of the encrypted diary in `path`, their
+    /// attachments and their earlier versions, and indexes the entries
+    /// again.
+    pub fn decrypt_diary(path: &Path, passphrase: &str, lock_timeout: Duration) -> DiaryResult<()> {
+        let tree = open_tree(path)?;
+        let _lock = DiaryLock::acquire(path, lock_timeout)?;
+        let cipher = unlock(path, passphrase)?;
+        for dt in tree.list()? {
+            let text = tree.get(&dt)?;
+            if crypto::is_encrypted(&text) {
+                tree.put(&dt, &cipher.decrypt(&text)?)?;
+            }
+            for name in tree.list_attachments(&dt)? {
+                let data = tree.get_attachment(&dt, &name)?;
+                if crypto::is_encrypted(&data) {
+                    tree.put_attachment(&dt, &name, &cipher.decrypt_bytes(&data)?)?;
+                }
+            }
+        }
+        FileHistory::new(path).transform_versions(|data| -> DiaryResult<Option<Vec<u8>>> {
+            if crypto::is_encrypted(data) {
+                Ok(Some(cipher.decrypt_bytes(data)?))
+            } else {
+                Ok(None)
+            }
+        })?;
+        let mut config = DiaryConfig::load(path)?;
+        config.encryption = None;
+        config.save(path)?;
+        if config.git.enabled {
+            GitRepo::init(path)?.commit_all("Decrypt diary")?;
+        }
+        let diary = Diary::open_custom(path, tree, Utc::now)?;
+        let tag_index = diary.open_index()?;
+        diary.index_entries(&tag_index)?;
+        Ok(())
+    }
+
+    /// Makes the diary in `path` commit every change to a git repository in
+    /// its directory. The repository is created if needed, and the current
+    /// state of the diary is committed to it.
+    pub fn enable_git(path: &Path, lock_timeout: Duration) -> DiaryResult<()> {
+        open_tree(path)?;
+        let _lock = DiaryLock::acquire(path, lock_timeout)?;
+        let mut config = DiaryConfig::load(path)?;
+        let git = GitRepo::init(path)?;
+        config.git.enabled = true;
+        config.save(path)?;
+        git.commit_all("Start tracking diary")?;
+        Ok(())
+    }
+
+    /// Stops committing changes to git. The repository is left in place.
+    pub fn disable_git(path: &Path, lock_timeout: Duration) -> DiaryResult<()> {
+        let _lock = DiaryLock::acquire(path, lock_timeout)?;
+        let mut config = DiaryConfig::load(path)?;
+        config.git.enabled = false;
+        config.save(path)?;
+        Ok(())
+    }
+
+    /// Returns the layout of the entry files of the diary in `path`.
+    pub fn layout(path: &Path) -> DiaryResult<Layout> {
+        Ok(DiaryConfig::load(path)?.layout)
+    }
+
+    /// Moves the entry files of the diary in `path` to where `layout` puts
+    /// them. Every file is copied before the new layout is saved, and the
+    /// old files are removed only after that, so an interrupted migration
+    /// leaves the diary readable. Returns the number of entries whose files
+    /// moved.
+    pub fn migrate_layout(
+        path: &Path,
+        layout: Layout,
+        lock_timeout: Duration,
+    ) -> DiaryResult<usize> {
+        require_tree(path, "change the file layout of a diary kept in SQLite")?;
+        let tree = open_tree(path)?;
+        let _lock = DiaryLock::acquire(path, lock_timeout)?;
+        let mut config = DiaryConfig::load(path)?;
+        let moved = tree
+            .list()?
+            .iter()
+            .filter(|dt| tree.layout().file_path(dt) != layout.file_path(dt))
+            .count();
+        let target = tree.copy_to_layout(layout.clone())?;
+        config.layout = layout;
+        config.save(path)?;
+        tree.remove_moved_files(&target)?;
+        if config.git.enabled {
+            GitRepo::init(path)?.commit_all("Change diary layout")?;
+        }
+        Ok(moved)
     }
 
-    pub fn open_custom<C>(path: &Path, clock: C) -> Result<Diary<'a>, DiaryError>
+    /// Moves the entries of the diary in `path`, their attachments and the
+    /// tag aliases to storage in `format`, and indexes the entries again.
+    /// Everything is copied and the new format saved before the old copies
+    /// are removed. Returns the number of entries.
+    pub fn convert_storage(
+        path: &Path,
+        format: StorageFormat,
+        lock_timeout: Duration,
+    ) -> DiaryResult<usize> {
+        let tree = open_tree(path)?;
+        let _lock = DiaryLock::acquire(path, lock_timeout)?;
+        let mut config = DiaryConfig::load(path)?;
+        if config.encryption.is_some() {
+            return Err(DiaryError::Unsupported("convert an encrypted diary"));
+        }
+        if config.storage == format {
+            return Ok(Diary::open(path)?.list_keys()?.len());
+        }
+        let count = {
+            let database = open_database(path)?;
+            let sqlite = SqliteStorage::new(&database)?;
+            let file_index = TagIndex::new(path)?;
+            file_index.initdb()?;
+            let (from, to, from_index, to_index): (&dyn Storage, &dyn Storage, _, _) = match format
+            {
+                StorageFormat::Sqlite => (&tree, &sqlite, &file_index, &database),
+                StorageFormat::Tree => (&sqlite, &tree, &database, &file_index),
+            };
+            let dates = from.list()?;
+            to_index.transaction(|| -> DiaryResult<()> {
+                for dt in dates.iter() {
+                    to.put(dt, &from.get(dt)?)?;
+                    for name in from.list_attachments(dt)? {
+                        to.put_attachment(dt, &name, &from.get_attachment(dt, &name)?)?;
+                    }
+                }
+                for (alias, canonical) in from_index.aliases()? {
+                    to_index.add_alias(&alias, &canonical)?;
+                }
+                Ok(())
+            })?;
+            dates.len()
+        };
+        config.storage = format;
+        config.save(path)?;
+        match format {
+            StorageFormat::Sqlite => {
+                tree.remove_entry_files()?;
+                TagIndex::remove(path)?;
+            }
+            StorageFormat::Tree => {
+                fs::remove_file(path.join(SQLITE_FILE_NAME)).map_err(StorageError::IoError)?
+            }
+        }
+        let diary = Diary::open(path)?;
+        diary.index_entries(&diary.open_index()?)?;
+        if config.git.enabled {
+            GitRepo::init(path)?.commit_all(match format {
+                StorageFormat::Sqlite => "Convert diary to SQLite",
+                StorageFormat::Tree => "Convert diary to files",
+            })?;
+        }
+        Ok(count)
+    }
+
+    /// Opens a diary that keeps its entries and its tag index in memory.
+    pub fn in_memory() -> Result<Diary<'a>, DiaryError> {
+        Diary::in_memory_custom(Utc::now)
+    }
+
+    pub fn in_memory_custom<C>(clock: C) -> Result<Diary<'a>, DiaryError>
     where
         C: 'a,
         C: Fn() -> DateTime<Utc>,
     {
-        let tree = filerepo::tree::Tree::new(&path)?;
         let diary = Diary {
             clock: Box::new(clock),
-            tree,
+            storage: Box::new(MemoryStorage::new()),
+            history: Box::new(MemoryHistory::new()),
+            git: None,
+            root: None,
+            index_location: IndexLocation::Shared(TagIndex::in_memory()?),
+            lock_timeout: Duration::from_secs(0),
         };
         Ok(diary)
     }
 
+    /// Sets how long writes wait for another process to finish writing to
+    /// the same diary before failing. The default is not to wait.
+    pub fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.lock_timeout = timeout;
+    }
+
     pub fn list_keys(&self) -> DiaryResult<Vec<DiaryEntryKey>> {
-        match self.tree.list().map_err(DiaryError::from) {
+        match self.storage.list().map_err(DiaryError::from) {
             Ok(dates) => {
                 let mut mdates = dates;
                 mdates.sort_unstable();
@@ -79,7 +540,7 @@ impl<'a> Diary<'a> {
     }
 
     pub fn get_text_for_entry(&self, key: &DiaryEntryKey) -> DiaryResult<String> {
-        self.tree.get_text(&key.date).map_err(DiaryError::from)
+        self.storage.get(&key.date).map_err(DiaryError::from)
     }
 
     pub fn add_entry(
@@ -88,28 +549,332 @@ impl<'a> Diary<'a> {
         content: &str,
         key: Option<DiaryEntryKey>,
         matching_date_behavior: MatchingDateBehavior,
+    ) -> DiaryResult<DiaryEntryKey> {
+        self.store_entry(tag_index, content, key, matching_date_behavior, None)
+    }
+
+    /// Stores an entry like `add_entry`. If the diary uses git, the change is
+    /// committed with `message`, or with a message describing it if that's
+    /// `None`.
+    pub(crate) fn store_entry(
+        &self,
+        tag_index: &TagIndex,
+        content: &str,
+        key: Option<DiaryEntryKey>,
+        matching_date_behavior: MatchingDateBehavior,
+        message: Option<String>,
+    ) -> DiaryResult<DiaryEntryKey> {
+        let _lock = self.lock()?;
+        self.write_entry(tag_index, content, key, matching_date_behavior, message)
+    }
+
+    /// Stores an entry like `store_entry`, without taking the lock.
+    fn write_entry(
+        &self,
+        tag_index: &TagIndex,
+        content: &str,
+        key: Option<DiaryEntryKey>,
+        matching_date_behavior: MatchingDateBehavior,
+        message: Option<String>,
     ) -> DiaryResult<DiaryEntryKey> {
         let key = key.unwrap_or_else(|| DiaryEntryKey {
             date: (self.clock)(),
         });
+        let existed = self.save_entry(tag_index, content, &key, matching_date_behavior)?;
+        let message = message.unwrap_or_else(|| match (&matching_date_behavior, existed) {
+            (_, false) => format!("Add entry {}", key),
+            (MatchingDateBehavior::Append, true) => format!("Append to entry {}", key),
+            (MatchingDateBehavior::Overwrite, true) => format!("Edit entry {}", key),
+        });
+        self.commit(&message)?;
+        Ok(key)
+    }
+
+    /// Stores the text of an entry and indexes it without committing it.
+    /// Returns true if there already was an entry for `key`.
+    pub(crate) fn save_entry(
+        &self,
+        tag_index: &TagIndex,
+        content: &str,
+        key: &DiaryEntryKey,
+        matching_date_behavior: MatchingDateBehavior,
+    ) -> DiaryResult<bool> {
         let entry_dt = key.date;
-        self.save_tags(tag_index, &key, content)?;
         let formatted_content = format!("{}\n", content.trim_end());
-        match matching_date_behavior {
-            MatchingDateBehavior::Overwrite => {
-                self.tree.add_entry(&entry_dt, &formatted_content)?
-            }
-            MatchingDateBehavior::Append => match self.tree.get_text(&entry_dt) {
-                Ok(old_text) => {
-                    let full_text = format!("{}\n\n{}", old_text.trim_end(), &formatted_content);
-                    self.tree.add_entry(&entry_dt, &full_text)?;
-                }
-                Err(_) => {
-                    self.tree.add_entry(&entry_dt, &formatted_content)?;
+        let old_text = match self.storage.get(&entry_dt) {
+            Ok(text) => Some(text),
+            Err(StorageError::EntryNotFound(_)) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let full_text = match (matching_date_behavior, &old_text) {
+            (MatchingDateBehavior::Append, Some(old_text)) => {
+                format!("{}\n\n{}", old_text.trim_end(), &formatted_content)
+            }
+            _ => formatted_content,
+        };
+        // Update the index first and commit it only once the text is safely
+        // stored, so that a failed write leaves the index unchanged.
+        tag_index.transaction(|| -> DiaryResult<()> {
+            self.update_index(tag_index, key, &full_text)?;
+            if let Some(old_text) = old_text.as_ref().filter(|t| **t != full_text) {
+                self.history.save(&entry_dt, old_text, &(self.clock)())?;
+            }
+            self.storage.put(&entry_dt, &full_text)?;
+            Ok(())
+        })?;
+        Ok(old_text.is_some())
+    }
+
+    /// Attaches a file to the entry for `key` and adds a link to it to the
+    /// end of the entry, creating the entry if there isn't one. Returns the
+    /// name the file was stored with, which differs from `name` if the
+    /// entry already has an attachment called that.
+    pub fn attach_file(
+        &self,
+        tag_index: &TagIndex,
+        key: &DiaryEntryKey,
+        name: &str,
+        data: &[u8],
+    ) -> DiaryResult<String> {
+        let _lock = self.lock()?;
+        let (stored_name, link) = self.store_attachment(key, name, data)?;
+        self.write_entry(
+            tag_index,
+            &link,
+            Some(key.clone()),
+            MatchingDateBehavior::Append,
+            Some(format!("Attach {} to entry {}", stored_name, key)),
+        )?;
+        Ok(stored_name)
+    }
+
+    /// Stores a file attached to the entry for `key` without linking to it
+    /// from the entry. Returns the name it was stored with and a Markdown
+    /// link to it.
+    pub(crate) fn store_attachment(
+        &self,
+        key: &DiaryEntryKey,
+        name: &str,
+        data: &[u8],
+    ) -> DiaryResult<(String, String)> {
+        if !is_valid_attachment_name(name) {
+            return Err(DiaryError::InvalidAttachmentName(name.to_string()));
+        }
+        let existing = self.storage.list_attachments(&key.date)?;
+        let stored_name = unique_name(name, &existing);
+        self.storage.put_attachment(&key.date, &stored_name, data)?;
+        let link = self.attachment_link(key, &stored_name);
+        Ok((stored_name, link))
+    }
+
+    /// The Markdown link to the attachment `name` of the entry for `key`.
+    pub(crate) fn attachment_link(&self, key: &DiaryEntryKey, name: &str) -> String {
+        format!(
+            "[{}]({})",
+            name,
+            self.storage
+                .attachment_link(&key.date, name)
+                .replace(' ', "%20")
+        )
+    }
+
+    /// Lists the names of the files attached to the entry for `key`.
+    pub fn list_attachments(&self, key: &DiaryEntryKey) -> DiaryResult<Vec<String>> {
+        let names = self.storage.list_attachments(&key.date)?;
+        Ok(names)
+    }
+
+    pub fn get_attachment(&self, key: &DiaryEntryKey, name: &str) -> DiaryResult<Vec<u8>> {
+        let data = self.storage.get_attachment(&key.date, name)?;
+        Ok(data)
+    }
+
+    /// Deletes the entry for `key` and its attachments, and removes it from
+    /// the index. The text is kept in the history of the entry.
+    pub fn delete_entry(&self, tag_index: &TagIndex, key: &DiaryEntryKey) -> DiaryResult<()> {
+        let _lock = self.lock()?;
+        self.remove_entry(tag_index, key)?;
+        self.commit(&format!("Delete entry {}", key))
+    }
+
+    /// Deletes an entry like `delete_entry`, without taking the lock or
+    /// committing.
+    pub(crate) fn remove_entry(
+        &self,
+        tag_index: &TagIndex,
+        key: &DiaryEntryKey,
+    ) -> DiaryResult<()> {
+        let old_text = self.storage.get(&key.date)?;
+        tag_index.transaction(|| -> DiaryResult<()> {
+            tag_index.remove_entry(key)?;
+            self.history.save(&key.date, &old_text, &(self.clock)())?;
+            self.storage.delete(&key.date)?;
+            Ok(())
+        })
+    }
+
+    /// Lists the earlier versions of the entry for `key`, oldest first. The
+    /// history of a deleted entry is kept, too.
+    pub fn entry_versions(&self, key: &DiaryEntryKey) -> DiaryResult<Vec<EntryVersion>> {
+        let versions = self.history.versions(&key.date)?;
+        Ok(versions)
+    }
+
+    pub fn get_entry_version(&self, key: &DiaryEntryKey, number: usize) -> DiaryResult<String> {
+        let text = self.history.get(&key.date, number)?;
+        Ok(text)
+    }
+
+    /// Returns the changes between two versions of the entry for `key` as a
+    /// unified diff. `None` stands for the current text, which is empty if
+    /// the entry has been deleted.
+    pub fn diff_entry_versions(
+        &self,
+        key: &DiaryEntryKey,
+        from: Option<usize>,
+        to: Option<usize>,
+    ) -> DiaryResult<String> {
+        let old_text = self.version_text(key, from)?;
+        let new_text = self.version_text(key, to)?;
+        let diff = TextDiff::from_lines(&old_text, &new_text)
+            .unified_diff()
+            .header(&version_name(from), &version_name(to))
+            .to_string();
+        Ok(diff)
+    }
+
+    /// Replaces the text of the entry for `key` with an earlier version. The
+    /// replaced text becomes the newest earlier version, so restoring can be
+    /// undone. A version that isn't valid UTF-8, like the original of an
+    /// entry converted to UTF-8, is restored as it was and left out of the
+    /// index.
+    pub fn restore_entry_version(
+        &self,
+        tag_index: &TagIndex,
+        key: &DiaryEntryKey,
+        number: usize,
+    ) -> DiaryResult<()> {
+        let message = format!("Restore version {} of entry {}", number, key);
+        let data = self.history.get_bytes(&key.date, number)?;
+        let data = match String::from_utf8(data) {
+            Ok(text) => {
+                self.store_entry(
+                    tag_index,
+                    &text,
+                    Some(key.clone()),
+                    MatchingDateBehavior::Overwrite,
+                    Some(message),
+                )?;
+                return Ok(());
+            }
+            Err(e) => e.into_bytes(),
+        };
+        let _lock = self.lock()?;
+        tag_index.transaction(|| -> DiaryResult<()> {
+            match self.storage.get_bytes(&key.date) {
+                Ok(old_data) if old_data != data => {
+                    self.history
+                        .save_bytes(&key.date, &old_data, &(self.clock)())?
                 }
-            },
+                Ok(_) | Err(StorageError::EntryNotFound(_)) => (),
+                Err(e) => return Err(e.into()),
+            }
+            tag_index.remove_entry(key)?;
+            self.storage.put_bytes(&key.date, &data)?;
+            Ok(())
+        })?;
+        self.commit(&message)
+    }
+
+    /// Makes this diary and `other` have the same entries. Entries that are
+    /// missing from one are copied from the other. When the texts of an
+    /// entry differ and one is an earlier version of the other, the later
+    /// one wins; otherwise `resolve` decides. Both diaries are reindexed
+    /// afterwards.
+    pub fn sync_with<F>(
+        &self,
+        tag_index: &TagIndex,
+        other: &Diary,
+        other_index: &TagIndex,
+        resolve: F,
+    ) -> DiaryResult<SyncReport>
+    where
+        F: FnMut(&Conflict) -> Resolution,
+    {
+        sync::sync(self, tag_index, other, other_index, resolve)
+    }
+
+    /// Looks for stray and misplaced files, unreadable and empty entries and
+    /// differences between the entries and the tag index. With `fix`, fixes
+    /// what can be fixed without losing text.
+    pub fn check(&self, tag_index: &TagIndex, fix: bool) -> DiaryResult<Vec<Finding>> {
+        check::check(self, tag_index, fix)
+    }
+
+    /// Adds the entries read from another diary program. Entries at a date
+    /// that already has an entry are appended to it or replace it,
+    /// depending on `matching_date_behavior`. With `dry_run` the report
+    /// tells what would be done without changing anything.
+    pub fn import(
+        &self,
+        tag_index: &TagIndex,
+        entries: &Import,
+        matching_date_behavior: MatchingDateBehavior,
+        dry_run: bool,
+    ) -> DiaryResult<ImportReport> {
+        import::import(self, tag_index, entries, matching_date_behavior, dry_run)
+    }
+
+    /// Writes the entries selected by `options` into one document in
+    /// `format`, with the dates in `tz`. Returns the number of entries
+    /// exported and the document.
+    pub fn export<Tz>(
+        &self,
+        tag_index: &TagIndex,
+        format: ExportFormat,
+        options: &ExportOptions,
+        tz: &Tz,
+    ) -> DiaryResult<(usize, Vec<u8>)>
+    where
+        Tz: TimeZone,
+        Tz::Offset: fmt::Display,
+    {
+        export::export(self, tag_index, format, options, tz)
+    }
+
+    /// Keeps `tag_index` up to date with changes made to the entry files by
+    /// other programs, until `report` returns false. Changes are handled
+    /// once there have been none for `debounce`. Encrypted diaries and
+    /// diaries kept in SQLite can't be watched.
+    pub fn watch<F>(&self, tag_index: &TagIndex, debounce: Duration, report: F) -> DiaryResult<()>
+    where
+        F: FnMut(&WatchEvent) -> bool,
+    {
+        if let Some(root) = &self.root {
+            if Diary::is_encrypted(root)? {
+                return Err(DiaryError::Unsupported("watch an encrypted diary"));
+            }
+            require_tree(root, "watch a diary kept in SQLite")?;
+        }
+        watch::watch(self, tag_index, debounce, report)
+    }
+
+    /// Updates the index for the entries whose files are at `paths`, the
+    /// way `watch` does when they change.
+    pub fn index_files(
+        &self,
+        tag_index: &TagIndex,
+        paths: &[PathBuf],
+    ) -> DiaryResult<Vec<WatchEvent>> {
+        watch::index_files(self, tag_index, &paths.iter().cloned().collect())
+    }
+
+    /// Lists the commits of a diary that uses git, newest first.
+    pub fn change_log(&self) -> DiaryResult<Vec<Change>> {
+        match &self.git {
+            Some(git) => Ok(git.log()?),
+            None => Err(DiaryError::GitNotEnabled),
         }
-        Ok(DiaryEntryKey { date: entry_dt })
     }
 
     pub fn search_tags(
@@ -121,34 +886,290 @@ impl<'a> Diary<'a> {
         Ok(keys)
     }
 
+    pub fn list_tags(&self, tag_index: &TagIndex) -> DiaryResult<Vec<TagCount>> {
+        let tags = tag_index.list_tags()?;
+        Ok(tags)
+    }
+
+    pub fn related_tags(&self, tag_index: &TagIndex, tag: &str) -> DiaryResult<Vec<RelatedTag>> {
+        let related = tag_index.related_tags(tag, self.list_keys()?.len())?;
+        Ok(related)
+    }
+
+    /// Returns the tag co-occurrence graph in Graphviz DOT format.
+    pub fn tag_graph_dot(&self, tag_index: &TagIndex) -> DiaryResult<String> {
+        let pairs = tag_index.cooccurrence()?;
+        Ok(dot::cooccurrence_graph(&pairs))
+    }
+
+    pub fn add_tag_alias(
+        &self,
+        tag_index: &TagIndex,
+        alias: &str,
+        canonical: &str,
+    ) -> DiaryResult<()> {
+        tag_index.add_alias(alias, canonical)?;
+        Ok(())
+    }
+
+    pub fn remove_tag_alias(&self, tag_index: &TagIndex, alias: &str) -> DiaryResult<bool> {
+        let removed = tag_index.remove_alias(alias)?;
+        Ok(removed)
+    }
+
     pub fn open_index(&self) -> DiaryResult<TagIndex> {
-        let tag_index = TagIndex::new(&self.tree.root)?;
+        let tag_index = match &self.index_location {
+            IndexLocation::Directory(root) => TagIndex::new(root)?,
+            IndexLocation::Shared(index) => index.share(),
+        };
         tag_index.initdb()?;
         Ok(tag_index)
     }
 
-    pub fn reindex(&self, tag_index: &TagIndex) -> DiaryResult<()> {
-        let entry_dates = self.tree.list()?;
-        let keys = entry_dates.iter().map(|d| DiaryEntryKey { date: *d }); //.collect();
-        let key_tag_results = keys.map(|key| -> DiaryResult<(DiaryEntryKey, Vec<String>)> {
-            let text = self.get_text_for_entry(&key)?;
-            let tags = tagparser::find_tags(&text);
-            Ok((key, tags))
-        });
-        let keys_tags = key_tag_results
-            .into_iter()
-            .collect::<DiaryResult<Vec<(DiaryEntryKey, Vec<String>)>>>()?;
+    /// Builds the index again from the entries. Entries that aren't valid
+    /// UTF-8 are left out of the index and returned.
+    pub fn reindex(&self, tag_index: &TagIndex) -> DiaryResult<Vec<DiaryEntryKey>> {
+        let _lock = self.lock()?;
+        self.index_entries(tag_index)
+    }
+
+    fn index_entries(&self, tag_index: &TagIndex) -> DiaryResult<Vec<DiaryEntryKey>> {
+        let mut keys_texts = Vec::new();
+        let mut skipped = Vec::new();
+        for key in self.list_keys()? {
+            match self.storage.get(&key.date) {
+                Ok(text) => keys_texts.push((key, text)),
+                Err(StorageError::EntryContentDecodingError(_)) => skipped.push(key),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let keys_tags: Vec<(DiaryEntryKey, Vec<String>)> = keys_texts
+            .iter()
+            .map(|(key, text)| (key.clone(), tagparser::find_tags(text)))
+            .collect();
+        let keys_words: Vec<(DiaryEntryKey, usize)> = keys_texts
+            .iter()
+            .map(|(key, text)| (key.clone(), stats::count_words(text)))
+            .collect();
         tag_index.recreate_index(&keys_tags)?;
+        tag_index.recreate_word_counts(&keys_words)?;
+        Ok(skipped)
+    }
+
+    /// Rewrites entries that aren't in UTF-8 in UTF-8, including UTF-16 text
+    /// that happens to be valid UTF-8. The encoding of each entry is guessed,
+    /// with the fallback encoding in the diary configuration used for text
+    /// that isn't UTF-16. Characters that can't be decoded are replaced. The
+    /// original bytes are kept in the history of the entry, so a wrong guess
+    /// can be undone by restoring them. Returns the converted entries with
+    /// the name of the encoding they were in.
+    pub fn convert_to_utf8(
+        &self,
+        tag_index: &TagIndex,
+    ) -> DiaryResult<Vec<(DiaryEntryKey, String)>> {
+        let fallback = self.fallback_encoding()?;
+        let _lock = self.lock()?;
+        let mut converted = Vec::new();
+        for key in self.list_keys()? {
+            let data = self.storage.get_bytes(&key.date)?;
+            let (text, encoding) = encoding::decode_lossy(&data, fallback);
+            if encoding == UTF_8 && std::str::from_utf8(&data).is_ok() {
+                continue;
+            }
+            tag_index.transaction(|| -> DiaryResult<()> {
+                self.update_index(tag_index, &key, &text)?;
+                self.history.save_bytes(&key.date, &data, &(self.clock)())?;
+                self.storage.put(&key.date, &text)?;
+                Ok(())
+            })?;
+            converted.push((key, encoding.name().to_string()));
+        }
+        if !converted.is_empty() {
+            self.commit("Convert entries to UTF-8")?;
+        }
+        Ok(converted)
+    }
+
+    /// Collects statistics about the entries between `from` and `to`,
+    /// inclusive. Dates are counted in the time zone `tz`. Word counts come
+    /// from the index and are only computed for entries missing from it.
+    pub fn stats<Tz: TimeZone>(
+        &self,
+        tag_index: &TagIndex,
+        from: Option<&DiaryEntryKey>,
+        to: Option<&DiaryEntryKey>,
+        tz: &Tz,
+    ) -> DiaryResult<DiaryStats> {
+        let keys: Vec<DiaryEntryKey> = self
+            .list_keys()?
+            .into_iter()
+            .filter(|k| k.is_between(from, to))
+            .collect();
+        let mut word_counts = tag_index.word_counts()?;
+        for key in &keys {
+            if !word_counts.contains_key(key) {
+                let words = stats::count_words(&self.get_text_for_entry(key)?);
+                tag_index.set_word_count(key, words)?;
+                word_counts.insert(key.clone(), words);
+            }
+        }
+        let entries: Vec<(DiaryEntryKey, usize)> = keys
+            .into_iter()
+            .map(|key| {
+                let words = word_counts[&key];
+                (key, words)
+            })
+            .collect();
+        let tags = tag_index.list_tags_between(from, to)?;
+        let today = (self.clock)().with_timezone(tz).date_naive();
+        Ok(stats::collect_stats(&entries, tags, today, tz))
+    }
+
+    fn fallback_encoding(&self) -> DiaryResult<&'static Encoding> {
+        let config = match &self.root {
+            Some(root) => DiaryConfig::load(root)?,
+            None => DiaryConfig::default(),
+        };
+        encoding::for_label(&config.encoding.fallback)
+            .ok_or(DiaryError::UnknownEncoding(config.encoding.fallback))
+    }
+
+    pub(crate) fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    pub(crate) fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    /// Takes the write lock of the diary directory. Diaries that aren't kept
+    /// in a directory don't need one.
+    pub(crate) fn lock(&self) -> DiaryResult<Option<DiaryLock>> {
+        match &self.root {
+            Some(root) => Ok(Some(DiaryLock::acquire(root, self.lock_timeout)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn commit(&self, message: &str) -> DiaryResult<()> {
+        if let Some(git) = &self.git {
+            git.commit_all(message)?;
+        }
         Ok(())
     }
 
-    fn save_tags(&self, tag_index: &TagIndex, key: &DiaryEntryKey, text: &str) -> DiaryResult<()> {
+    fn version_text(&self, key: &DiaryEntryKey, number: Option<usize>) -> DiaryResult<String> {
+        match number {
+            Some(number) => self.get_entry_version(key, number),
+            None => match self.storage.get(&key.date) {
+                Err(StorageError::EntryNotFound(_)) => Ok(String::new()),
+                result => Ok(result?),
+            },
+        }
+    }
+
+    pub(crate) fn update_index(
+        &self,
+        tag_index: &TagIndex,
+        key: &DiaryEntryKey,
+        text: &str,
+    ) -> DiaryResult<()> {
         let tags = tagparser::find_tags(text);
         tag_index.set_tags(key, &tags)?;
+        tag_index.set_word_count(key, stats::count_words(text))?;
         Ok(())
     }
 }
 
+/// Derives the key of the encrypted diary in `path` and checks that
+/// `passphrase` is the right one.
+fn unlock(path: &Path, passphrase: &str) -> DiaryResult<Cipher> {
+    let encryption = DiaryConfig::load(path)?
+        .encryption
+        .ok_or(CryptoError::NotEncrypted)?;
+    let salt = BASE64
+        .decode(&encryption.salt)
+        .map_err(|_| CryptoError::MalformedText)?;
+    let params = KdfParams {
+        memory_kib: encryption.memory_kib,
+        iterations: encryption.iterations,
+        parallelism: encryption.parallelism,
+    };
+    let cipher = Cipher::new(passphrase, &salt, params)?;
+    match cipher.decrypt(&encryption.check) {
+        Ok(check) if crypto::is_encrypted(&encryption.check) && check == CHECK_TEXT => Ok(cipher),
+        _ => Err(DiaryError::CryptoError(CryptoError::WrongPassphrase)),
+    }
+}
+
+/// Fails with `Unsupported` if the diary in `path` doesn't keep its entries
+/// in files.
+fn require_tree(path: &Path, operation: &'static str) -> DiaryResult<()> {
+    match DiaryConfig::load(path)?.storage {
+        StorageFormat::Tree => Ok(()),
+        StorageFormat::Sqlite => Err(DiaryError::Unsupported(operation)),
+    }
+}
+
+/// Opens the SQLite database of the diary in `path` that has both the
+/// entries and the tag index.
+fn open_database(path: &Path) -> DiaryResult<TagIndex> {
+    let tag_index = TagIndex::open_file(&path.join(SQLITE_FILE_NAME))?;
+    tag_index.initdb()?;
+    Ok(tag_index)
+}
+
+/// Opens the file tree of the diary in `path` with the layout in its
+/// configuration.
+fn open_tree(path: &Path) -> DiaryResult<Tree> {
+    Ok(Tree::with_layout(path, Diary::layout(path)?)?)
+}
+
+fn open_git(path: &Path) -> DiaryResult<Option<GitRepo>> {
+    if DiaryConfig::load(path)?.git.enabled {
+        Ok(Some(GitRepo::init(path)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Attachment names are plain file names that aren't hidden.
+fn is_valid_attachment_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && Path::new(name).file_name().and_then(|n| n.to_str()) == Some(name)
+}
+
+/// Returns `name`, or if it's in `taken`, `name` with a number added before
+/// the extension.
+fn unique_name(name: &str, taken: &[String]) -> String {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| format!(".{}", e))
+        .unwrap_or_default();
+    let mut candidate = name.to_string();
+    let mut number = 1;
+    while taken.contains(&candidate) {
+        candidate = format!("{}-{}{}", stem, number, extension);
+        number += 1;
+    }
+    candidate
+}
+
+fn version_name(number: Option<usize>) -> String {
+    match number {
+        Some(number) => format!("version {}", number),
+        None => "current".to_string(),
+    }
+}
+
+static CHECK_TEXT: &str = "ddiary";
+static SQLITE_FILE_NAME: &str = "diary.sqlite";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MatchingDateBehavior {
     Overwrite,
     Append,
@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc};
 use std::fmt;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct DiaryEntryKey {
     pub date: DateTime<Utc>,
 }
@@ -15,11 +15,17 @@ impl DiaryEntryKey {
             })
             .ok()
     }
+
+    /// Returns true if the key is between `from` and `to`, inclusive. A
+    /// missing end leaves the range open at that end.
+    pub fn is_between(&self, from: Option<&DiaryEntryKey>, to: Option<&DiaryEntryKey>) -> bool {
+        from.is_none_or(|f| self.date >= f.date) && to.is_none_or(|t| self.date <= t.date)
+    }
 }
 
 impl fmt::Display for DiaryEntryKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.date.format(DEFAULT_KEY_FORMAT).to_string())
+        write!(f, "{}", self.date.format(DEFAULT_KEY_FORMAT))
     }
 }
 
@@ -0,0 +1,70 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// Guesses the encoding of `data`: a byte order mark decides it if there is
+/// one, then text with many zero bytes in every other position is taken as
+/// UTF-16 and valid UTF-8 as UTF-8. Anything else is assumed to be in
+/// `fallback`.
+pub fn detect(data: &[u8], fallback: &'static Encoding) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(data) {
+        return encoding;
+    }
+    // UTF-16 comes first: ASCII text in UTF-16 is valid UTF-8, too.
+    if data.len() >= 2 && data.len().is_multiple_of(2) {
+        let pairs = data.len() / 2;
+        let even_zeros = data.iter().step_by(2).filter(|b| **b == 0).count();
+        let odd_zeros = data.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+        // ASCII text in UTF-16 has a zero byte in every code unit.
+        if odd_zeros * 3 >= pairs && even_zeros * 10 < pairs {
+            return UTF_16LE;
+        }
+        if even_zeros * 3 >= pairs && odd_zeros * 10 < pairs {
+            return UTF_16BE;
+        }
+    }
+    if std::str::from_utf8(data).is_ok() {
+        return UTF_8;
+    }
+    fallback
+}
+
+/// Decodes `data` with the encoding `detect` picks, replacing anything that
+/// can't be decoded. Returns the text and the encoding.
+pub fn decode_lossy(data: &[u8], fallback: &'static Encoding) -> (String, &'static Encoding) {
+    let encoding = detect(data, fallback);
+    let (text, encoding, _) = encoding.decode(data);
+    (text.into_owned(), encoding)
+}
+
+/// Looks up an encoding by a label like `windows-1252` or `latin1`.
+pub fn for_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::WINDOWS_1252;
+
+    #[test]
+    fn detects_boms_utf16_and_fallback() {
+        assert_eq!(UTF_8, detect("Café".as_bytes(), WINDOWS_1252));
+        assert_eq!(UTF_16LE, detect(b"\xff\xfeC\x00a\x00", WINDOWS_1252));
+        assert_eq!(UTF_16LE, detect(b"C\x00a\x00f\x00\xe9\x00", WINDOWS_1252));
+        assert_eq!(UTF_16LE, detect(b"C\x00a\x00f\x00e\x00", WINDOWS_1252));
+        assert_eq!(UTF_16BE, detect(b"\x00C\x00a\x00f\x00\xe9", WINDOWS_1252));
+        assert_eq!(WINDOWS_1252, detect(b"Caf\xe9", WINDOWS_1252));
+    }
+
+    #[test]
+    fn decodes_latin1_and_utf16() {
+        assert_eq!("Café", decode_lossy(b"Caf\xe9", WINDOWS_1252).0);
+        assert_eq!(
+            "Café",
+            decode_lossy(b"\xfe\xff\x00C\x00a\x00f\x00\xe9", WINDOWS_1252).0
+        );
+        assert_eq!(
+            "Cafe",
+            decode_lossy(b"C\x00a\x00f\x00e\x00", WINDOWS_1252).0
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use crate::diary::{Diary, DiaryError};
+use crate::export::{ExportError, ExportedEntry};
+use crate::tagparser;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io::{Cursor, Write};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+#[derive(Serialize)]
+struct DayOneJournal {
+    metadata: DayOneMetadata,
+    entries: Vec<DayOneEntry>,
+}
+
+#[derive(Serialize)]
+struct DayOneMetadata {
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DayOneEntry {
+    uuid: String,
+    creation_date: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_zone: Option<String>,
+    text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    photos: Vec<DayOnePhoto>,
+}
+
+#[derive(Serialize)]
+struct DayOnePhoto {
+    identifier: String,
+    md5: String,
+    #[serde(rename = "type")]
+    file_type: String,
+}
+
+/// Writes `entries` as a Day One JSON export, a zip file with the entries
+/// in `Journal.json` and their image attachments in `photos`. The dates are
+/// in UTC, with `time_zone` as the time zone Day One shows them in. The tags
+/// in the text are listed as Day One tags, and links to image attachments
+/// are replaced with Day One's links to the photos.
+pub(crate) fn dayone_export(
+    diary: &Diary,
+    entries: &[ExportedEntry],
+    time_zone: Option<&str>,
+) -> Result<Vec<u8>, DiaryError> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut photo_files = BTreeSet::new();
+    let mut journal = DayOneJournal {
+        metadata: DayOneMetadata { version: "1.0" },
+        entries: Vec::new(),
+    };
+    for entry in entries.iter() {
+        let mut text = entry.text.trim_end().to_string();
+        let mut photos = Vec::new();
+        for name in diary.list_attachments(&entry.key)? {
+            let file_type = match photo_type(&name) {
+                Some(file_type) => file_type,
+                None => continue,
+            };
+            let data = diary.get_attachment(&entry.key, &name)?;
+            let photo = DayOnePhoto {
+                identifier: format!("{:X}", md5::compute(format!("{} {}", entry.key, name))),
+                md5: format!("{:x}", md5::compute(&data)),
+                file_type: file_type.to_string(),
+            };
+            let file_name = format!("photos/{}.{}", photo.md5, photo.file_type);
+            if photo_files.insert(file_name.clone()) {
+                zip.start_file(file_name, FileOptions::default())
+                    .map_err(ExportError::from)?;
+                zip.write_all(&data).map_err(ExportError::from)?;
+            }
+            let link = diary.attachment_link(&entry.key, &name);
+            let moment = format!("![](dayone-moment://{})", photo.identifier);
+            if text.contains(&link) {
+                text = text
+                    .replace(&format!("!{}", link), &link)
+                    .replace(&link, &moment);
+            } else {
+                text = format!("{}\n\n{}", text, moment);
+            }
+            photos.push(photo);
+        }
+        journal.entries.push(DayOneEntry {
+            uuid: format!("{:X}", md5::compute(entry.key.to_string())),
+            creation_date: entry.key.date.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            time_zone: time_zone.map(String::from),
+            tags: tagparser::find_tags(&text),
+            text,
+            photos,
+        });
+    }
+    zip.start_file("Journal.json", FileOptions::default())
+        .map_err(ExportError::from)?;
+    let json = serde_json::to_vec_pretty(&journal).map_err(ExportError::from)?;
+    zip.write_all(&json).map_err(ExportError::from)?;
+    let cursor = zip.finish().map_err(ExportError::from)?;
+    Ok(cursor.into_inner())
+}
+
+/// The Day One type of the photo in the attachment `name`, or None if it
+/// isn't an image.
+fn photo_type(name: &str) -> Option<&'static str> {
+    let extension = name.rsplit_once('.')?.1.to_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => Some("jpeg"),
+        "png" => Some("png"),
+        "gif" => Some("gif"),
+        "heic" => Some("heic"),
+        "tif" | "tiff" => Some("tiff"),
+        "webp" => Some("webp"),
+        _ => None,
+    }
+}
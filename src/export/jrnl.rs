@@ -0,0 +1,95 @@
+use crate::export::ExportedEntry;
+use crate::import::format_tag;
+use crate::import::jrnl::parse_heading;
+use crate::tagparser::{self, is_word_char, SpanKind};
+use chrono::TimeZone;
+use std::fmt;
+
+/// Writes `entries` as a jrnl journal, which `jrnl --import` reads. Each
+/// entry starts with a line with its date and time in `tz` and the first
+/// line of its text, and tags are written as jrnl's `@tags` where jrnl can
+/// have them. Lines that would start a new entry, even after spaces, get a
+/// space in front, which `parse_jrnl` takes away again. `@mentions` are
+/// read back as tags, since jrnl has no mentions.
+pub fn jrnl_document<Tz>(entries: &[ExportedEntry], tz: &Tz) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: fmt::Display,
+{
+    let mut document = String::new();
+    for entry in entries.iter() {
+        if !document.is_empty() {
+            document.push('\n');
+        }
+        let date = entry.key.date.with_timezone(tz);
+        let text = convert_tags(entry.text.trim());
+        document.push_str(&format!("[{}] ", date.format("%Y-%m-%d %H:%M")));
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                document.push('\n');
+                if parse_heading(line.trim_start_matches(' ')).is_some() {
+                    document.push(' ');
+                }
+            }
+            document.push_str(line);
+        }
+        document.push('\n');
+    }
+    document
+}
+
+/// Turns tags into jrnl tags: `#tag` into `@tag` and `#(anna-maria)#` into
+/// `@anna-maria`. Tags that wouldn't be read back as they are, like
+/// `#(multi word)#`, are left alone, and `parse_jrnl` keeps them.
+pub(crate) fn convert_tags(text: &str) -> String {
+    tagparser::parse_spans(text)
+        .into_iter()
+        .map(|span| {
+            let original = &text[span.range.clone()];
+            match span.kind {
+                SpanKind::Tag(tag)
+                    if is_jrnl_tag(&tag)
+                        && format_tag(&tag) == original
+                        && !text[..span.range.start]
+                            .chars()
+                            .last()
+                            .is_some_and(char::is_alphanumeric)
+                        && !text[span.range.end..]
+                            .chars()
+                            .next()
+                            .is_some_and(is_jrnl_tag_char) =>
+                {
+                    format!("@{}", tag)
+                }
+                _ => original.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Whether `tag` can be a jrnl tag: letters, digits, underscores and
+/// hyphens, not ending in the punctuation jrnl drops.
+fn is_jrnl_tag(tag: &str) -> bool {
+    !tag.is_empty() && tag.chars().all(is_jrnl_tag_char) && !tag.ends_with(['_', '-'])
+}
+
+fn is_jrnl_tag_char(c: char) -> bool {
+    is_word_char(c) || c == '_' || c == '-'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_tags() {
+        assert_eq!(
+            "@work and #(multi word)#, @anna-maria at https://example.com/#top",
+            convert_tags("#work and #(multi word)#, #(anna-maria)# at https://example.com/#top")
+        );
+        assert_eq!(
+            "#(c++)# and #(what?)#, #(!!)#, #(work)#",
+            convert_tags("#(c++)# and #(what?)#, #(!!)#, #(work)#")
+        );
+    }
+}
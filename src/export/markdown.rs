@@ -0,0 +1,143 @@
+use crate::export::ExportedEntry;
+use chrono::{DateTime, Datelike, TimeZone};
+use std::fmt;
+
+/// Writes `entries` into one Markdown document titled `title`. Each entry
+/// gets a heading with its key in `tz`, under a heading for its month, and
+/// the document starts with a table of contents of the months and entries.
+/// Headings in the entries are moved below the entry headings.
+pub fn markdown_document<Tz>(entries: &[ExportedEntry], title: &str, tz: &Tz) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: fmt::Display,
+{
+    let months = by_month(entries, tz);
+    let mut document = format!("# {}\n\n", title);
+    if !months.is_empty() {
+        document.push_str("## Contents\n\n");
+        for month in months.iter() {
+            document.push_str(&format!("- [{}](#{})\n", month.name, anchor(&month.name)));
+            for (heading, _) in month.entries.iter() {
+                document.push_str(&format!("  - [{}](#{})\n", heading, anchor(heading)));
+            }
+        }
+    }
+    for month in months.iter() {
+        document.push_str(&format!("\n## {}\n", month.name));
+        for (heading, entry) in month.entries.iter() {
+            document.push_str(&format!("\n### {}\n\n", heading));
+            document.push_str(&demote_headings(entry.text.trim_end(), 3));
+            document.push('\n');
+        }
+    }
+    document
+}
+
+/// Writes `entries` into one text file, each after a line with its date and
+/// time in `tz`, so that `split_text` reads them back.
+pub fn text_document<Tz>(entries: &[ExportedEntry], tz: &Tz) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: fmt::Display,
+{
+    let mut document = String::new();
+    for entry in entries.iter() {
+        if !document.is_empty() {
+            document.push('\n');
+        }
+        let date = entry.key.date.with_timezone(tz);
+        document.push_str(&format!("{}\n\n", date.format("%Y-%m-%d %H:%M")));
+        document.push_str(entry.text.trim_end());
+        document.push('\n');
+    }
+    document
+}
+
+/// The entries of a month, with their headings.
+struct Month<'a> {
+    year_month: (i32, u32),
+    name: String,
+    entries: Vec<(String, &'a ExportedEntry)>,
+}
+
+/// Groups `entries` by month in `tz`. The heading of an entry is its key in
+/// `tz`.
+fn by_month<'a, Tz>(entries: &'a [ExportedEntry], tz: &Tz) -> Vec<Month<'a>>
+where
+    Tz: TimeZone,
+    Tz::Offset: fmt::Display,
+{
+    let mut months: Vec<Month> = Vec::new();
+    for entry in entries.iter() {
+        let date: DateTime<Tz> = entry.key.date.with_timezone(tz);
+        let year_month = (date.year(), date.month());
+        let heading = date.format("%Y-%m-%d %H:%M %z").to_string();
+        match months.last_mut() {
+            Some(month) if month.year_month == year_month => month.entries.push((heading, entry)),
+            _ => months.push(Month {
+                year_month,
+                name: date.format("%B %Y").to_string(),
+                entries: vec![(heading, entry)],
+            }),
+        }
+    }
+    months
+}
+
+/// The anchor of a heading the way GitHub and most other Markdown renderers
+/// make them: lowercase, without punctuation, spaces turned into hyphens.
+fn anchor(heading: &str) -> String {
+    heading
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-' || *c == '_')
+        .flat_map(char::to_lowercase)
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
+
+/// Adds `levels` to the level of the ATX headings in `text`, up to six,
+/// leaving fenced code blocks alone.
+fn demote_headings(text: &str, levels: usize) -> String {
+    let mut fence: Option<&str> = None;
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        let marker = ["```", "~~~"].iter().find(|m| trimmed.starts_with(**m));
+        match (fence, marker) {
+            (Some(open), Some(marker)) if open == *marker => fence = None,
+            (None, Some(marker)) => fence = Some(marker),
+            _ => (),
+        }
+        let level = line.chars().take_while(|c| *c == '#').count();
+        let is_heading = fence.is_none()
+            && marker.is_none()
+            && (1..=6).contains(&level)
+            && line[level..].chars().next().is_none_or(|c| c == ' ');
+        if is_heading {
+            let new_level = (level + levels).min(6);
+            lines.push(format!("{}{}", "#".repeat(new_level), &line[level..]));
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchor() {
+        assert_eq!("2020-09-21-1337-0300", anchor("2020-09-21 13:37 +0300"));
+        assert_eq!("september-2020", anchor("September 2020"));
+    }
+
+    #[test]
+    fn test_demote_headings() {
+        assert_eq!(
+            "#### Title\n#tag\n```\n# comment\n```\n###### Deep",
+            demote_headings("# Title\n#tag\n```\n# comment\n```\n##### Deep", 3)
+        );
+    }
+}
@@ -0,0 +1,148 @@
+pub mod dayone;
+pub mod jrnl;
+pub mod markdown;
+
+use crate::diary::{Diary, DiaryError};
+use crate::diaryentrykey::DiaryEntryKey;
+use crate::index::tags::TagIndex;
+use chrono::TimeZone;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// The formats `Diary::export` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One Markdown document with a table of contents.
+    Markdown,
+    /// One text file with a date heading before each entry.
+    Text,
+    /// A jrnl journal.
+    Jrnl,
+    /// A Day One JSON export, a zip file.
+    DayOne,
+}
+
+/// Which entries to export and how.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// Only entries from this date on.
+    pub from: Option<DiaryEntryKey>,
+    /// Only entries up to this date.
+    pub to: Option<DiaryEntryKey>,
+    /// Only entries with any of these tags, if there are any.
+    pub tags: Vec<String>,
+    /// The title of a Markdown document.
+    pub title: String,
+    /// The name of the time zone Day One shows the dates in, like
+    /// `Europe/Helsinki`.
+    pub time_zone: Option<String>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            from: None,
+            to: None,
+            tags: Vec::new(),
+            title: "Diary".to_string(),
+            time_zone: None,
+        }
+    }
+}
+
+/// An entry selected for export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedEntry {
+    pub key: DiaryEntryKey,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    IoError(io::Error),
+    JsonError(serde_json::Error),
+    ZipError(zip::result::ZipError),
+}
+
+impl From<io::Error> for ExportError {
+    fn from(error: io::Error) -> ExportError {
+        ExportError::IoError(error)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(error: serde_json::Error) -> ExportError {
+        ExportError::JsonError(error)
+    }
+}
+
+impl From<zip::result::ZipError> for ExportError {
+    fn from(error: zip::result::ZipError) -> ExportError {
+        ExportError::ZipError(error)
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::IoError(e) => write!(f, "IO Error: {}", e),
+            ExportError::JsonError(e) => write!(f, "JSON error: {}", e),
+            ExportError::ZipError(e) => write!(f, "Zip error: {}", e),
+        }
+    }
+}
+
+impl Error for ExportError {}
+
+/// Writes the entries of `diary` selected by `options` in `format`, with
+/// the dates in `tz`. Returns the number of entries and the document.
+pub(crate) fn export<Tz>(
+    diary: &Diary,
+    tag_index: &TagIndex,
+    format: ExportFormat,
+    options: &ExportOptions,
+    tz: &Tz,
+) -> Result<(usize, Vec<u8>), DiaryError>
+where
+    Tz: TimeZone,
+    Tz::Offset: fmt::Display,
+{
+    let entries = select_entries(diary, tag_index, options)?;
+    let document = match format {
+        ExportFormat::Markdown => markdown::markdown_document(&entries, &options.title, tz),
+        ExportFormat::Text => markdown::text_document(&entries, tz),
+        ExportFormat::Jrnl => jrnl::jrnl_document(&entries, tz),
+        ExportFormat::DayOne => {
+            let export = dayone::dayone_export(diary, &entries, options.time_zone.as_deref())?;
+            return Ok((entries.len(), export));
+        }
+    };
+    Ok((entries.len(), document.into_bytes()))
+}
+
+/// The entries between `options.from` and `options.to`, inclusive, with
+/// any of `options.tags`, earliest first.
+pub(crate) fn select_entries(
+    diary: &Diary,
+    tag_index: &TagIndex,
+    options: &ExportOptions,
+) -> Result<Vec<ExportedEntry>, DiaryError> {
+    let tagged: Option<BTreeSet<DiaryEntryKey>> = if options.tags.is_empty() {
+        None
+    } else {
+        let tags: Vec<&str> = options.tags.iter().map(String::as_str).collect();
+        Some(tag_index.search_tags(&tags)?.into_iter().collect())
+    };
+    diary
+        .list_keys()?
+        .into_iter()
+        .filter(|k| k.is_between(options.from.as_ref(), options.to.as_ref()))
+        .filter(|k| tagged.as_ref().is_none_or(|tagged| tagged.contains(k)))
+        .map(|key| {
+            let text = diary.get_text_for_entry(&key)?;
+            Ok(ExportedEntry { key, text })
+        })
+        .collect()
+}
@@ -0,0 +1,188 @@
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+/// How the entry files of a diary are named and arranged in directories.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    pub extension: Extension,
+    pub directories: DirectoryScheme,
+    /// A chrono format string for the file name without the extension. It
+    /// has to identify the minute of the entry.
+    pub file_name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Extension {
+    None,
+    Md,
+    Txt,
+    Org,
+}
+
+/// The directories entry files are kept in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DirectoryScheme {
+    /// All files in the diary directory.
+    Flat,
+    /// `YYYY`
+    Year,
+    /// `YYYY/MM`
+    YearMonth,
+    /// `YYYY/MM/DD`
+    YearMonthDay,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            extension: Extension::None,
+            directories: DirectoryScheme::YearMonth,
+            file_name: DEFAULT_FILE_NAME_FORMAT.to_string(),
+        }
+    }
+}
+
+impl Extension {
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Extension::None => "",
+            Extension::Md => ".md",
+            Extension::Txt => ".txt",
+            Extension::Org => ".org",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Extension> {
+        match s.trim_start_matches('.') {
+            "" | "none" => Some(Extension::None),
+            "md" => Some(Extension::Md),
+            "txt" => Some(Extension::Txt),
+            "org" => Some(Extension::Org),
+            _ => None,
+        }
+    }
+}
+
+impl DirectoryScheme {
+    pub fn parse(s: &str) -> Option<DirectoryScheme> {
+        match s {
+            "flat" => Some(DirectoryScheme::Flat),
+            "year" | "YYYY" => Some(DirectoryScheme::Year),
+            "year-month" | "YYYY/MM" => Some(DirectoryScheme::YearMonth),
+            "year-month-day" | "YYYY/MM/DD" => Some(DirectoryScheme::YearMonthDay),
+            _ => None,
+        }
+    }
+}
+
+impl Layout {
+    /// Checks that the file name pattern is valid and that file names made
+    /// with it can be turned back to dates.
+    pub fn validate(&self) -> Result<(), String> {
+        let pattern = &self.file_name;
+        if pattern.is_empty() || pattern.starts_with('.') || pattern.contains(['/', '\\']) {
+            return Err(format!("Invalid file name pattern: {}", pattern));
+        }
+        let samples = [
+            Utc.with_ymd_and_hms(2001, 2, 3, 4, 5, 0).unwrap(),
+            Utc.with_ymd_and_hms(2020, 12, 31, 23, 59, 0).unwrap(),
+        ];
+        for dt in samples.iter() {
+            let name = format_date(dt, pattern)
+                .ok_or_else(|| format!("Invalid file name pattern: {}", pattern))?;
+            if self.parse_file_name(&format!("{}{}", name, self.extension.suffix())) != Some(*dt) {
+                return Err(format!(
+                    "File name pattern {} doesn't identify the minute of an entry",
+                    pattern
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The directory of the entry file for `dt`, relative to the diary
+    /// directory.
+    pub fn file_directory(&self, dt: &DateTime<Utc>) -> PathBuf {
+        let mut path = PathBuf::new();
+        if self.directories != DirectoryScheme::Flat {
+            path.push(format!("{:04}", dt.year()));
+        }
+        if self.directories == DirectoryScheme::YearMonth
+            || self.directories == DirectoryScheme::YearMonthDay
+        {
+            path.push(format!("{:02}", dt.month()));
+        }
+        if self.directories == DirectoryScheme::YearMonthDay {
+            path.push(format!("{:02}", dt.day()));
+        }
+        path
+    }
+
+    pub fn file_name(&self, dt: &DateTime<Utc>) -> String {
+        let name = format_date(dt, &self.file_name).unwrap_or_default();
+        format!("{}{}", name, self.extension.suffix())
+    }
+
+    pub fn file_path(&self, dt: &DateTime<Utc>) -> PathBuf {
+        self.file_directory(dt).join(self.file_name(dt))
+    }
+
+    /// Returns the date of the entry whose file is `name`, or None if `name`
+    /// isn't the name of an entry file.
+    pub fn parse_file_name(&self, name: &str) -> Option<DateTime<Utc>> {
+        let stem = name.strip_suffix(self.extension.suffix())?;
+        NaiveDateTime::parse_from_str(stem, &self.file_name)
+            .ok()
+            .map(|ndt| Utc.from_utc_datetime(&ndt))
+    }
+}
+
+fn format_date(dt: &DateTime<Utc>, pattern: &str) -> Option<String> {
+    let mut name = String::new();
+    write!(name, "{}", dt.format(pattern)).ok()?;
+    Some(name)
+}
+
+pub(crate) static DEFAULT_FILE_NAME_FORMAT: &str = "%Y%m%dT%H%M";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_paths_follow_layout() {
+        let dt = Utc.with_ymd_and_hms(2020, 9, 21, 13, 37, 0).unwrap();
+        assert_eq!(
+            PathBuf::from("2020/09/20200921T1337"),
+            Layout::default().file_path(&dt)
+        );
+        let layout = Layout {
+            extension: Extension::Md,
+            directories: DirectoryScheme::YearMonthDay,
+            file_name: "%Y-%m-%d %H.%M".to_string(),
+        };
+        assert_eq!(
+            PathBuf::from("2020/09/21/2020-09-21 13.37.md"),
+            layout.file_path(&dt)
+        );
+        assert_eq!(Some(dt), layout.parse_file_name("2020-09-21 13.37.md"));
+        assert_eq!(None, layout.parse_file_name("2020-09-21 13.37.txt"));
+        assert!(layout.validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        for pattern in &["%Y%m%d", "%Y/%m/%d %H%M", "%Q", ".%Y%m%dT%H%M", ""] {
+            let layout = Layout {
+                file_name: pattern.to_string(),
+                ..Layout::default()
+            };
+            assert!(layout.validate().is_err(), "{} accepted", pattern);
+        }
+    }
+}
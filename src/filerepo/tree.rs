@@ -1,21 +1,33 @@
+use crate::config::CONFIG_FILE_NAME;
+use crate::filerepo::layout::{Layout, DEFAULT_FILE_NAME_FORMAT};
+use crate::index::tags::INDEX_FILE_NAME;
+use crate::storage::{EntryFiles, EntryStat, Storage, StorageError, StorageResult, StrayFile};
 use std::path::{Path, PathBuf};
 
+use std::collections::HashSet;
 use std::fmt;
 use std::fs;
-use std::io;
-use std::string;
+use std::io::{self, Write};
 
 use chrono::prelude::*;
 
 #[derive(Debug)]
 pub struct Tree {
     pub root: PathBuf,
+    layout: Layout,
 }
 
 pub type FileRepoResult<T> = Result<T, FileRepoError>;
 
 impl Tree {
     pub fn new(root: &Path) -> FileRepoResult<Tree> {
+        Tree::with_layout(root, Layout::default())
+    }
+
+    /// Opens a tree whose entry files are named and arranged as `layout`
+    /// says.
+    pub fn with_layout(root: &Path, layout: Layout) -> FileRepoResult<Tree> {
+        layout.validate().map_err(FileRepoError::InvalidLayout)?;
         let root_exists = root.exists();
         if root_exists && !root.is_dir() {
             FileRepoResult::Err(FileRepoError::BadPathError(root.to_path_buf()))
@@ -25,42 +37,282 @@ impl Tree {
             }
             FileRepoResult::Ok(Tree {
                 root: root.to_path_buf(),
+                layout,
             })
         }
     }
 
-    pub fn list(&self) -> FileRepoResult<Vec<DateTime<Utc>>> {
-        collect_dates(&self.root)
+    pub fn layout(&self) -> &Layout {
+        &self.layout
     }
 
-    pub fn get_text(&self, dt: &DateTime<Utc>) -> FileRepoResult<String> {
-        get_text(&self.root, dt)
+    /// Copies the files of every entry and its attachments to where
+    /// `layout` puts them and returns a tree with that layout. The original
+    /// files are left in place; remove them with `remove_moved_files` once
+    /// the new layout has been saved. The files are copied as they are, so
+    /// this works for encrypted entries too.
+    pub(crate) fn copy_to_layout(&self, layout: Layout) -> StorageResult<Tree> {
+        let target = Tree::with_layout(&self.root, layout)?;
+        let dates = self.list()?;
+        let old_paths: HashSet<PathBuf> = dates.iter().map(|dt| self.file_path(dt)).collect();
+        for dt in dates.iter() {
+            let old_path = self.file_path(dt);
+            let new_path = target.file_path(dt);
+            if old_path == new_path {
+                continue;
+            }
+            // Never write over a file of another entry, or over something
+            // that isn't a copy of this one.
+            if old_paths.contains(&new_path)
+                || (new_path.exists() && fs::read(&new_path)? != fs::read(&old_path)?)
+            {
+                return Err(FileRepoError::LayoutConflict(new_path).into());
+            }
+        }
+        for dt in dates.iter() {
+            if self.file_path(dt) == target.file_path(dt) {
+                continue;
+            }
+            let entry_dir = target.root.join(target.layout.file_directory(dt));
+            fs::create_dir_all(&entry_dir)?;
+            let data = fs::read(self.file_path(dt))?;
+            write_atomically(&entry_dir, &target.layout.file_name(dt), &data)?;
+            if self.attachment_directory(dt) == target.attachment_directory(dt) {
+                continue;
+            }
+            for name in self.list_attachments(dt)? {
+                target.put_attachment(dt, &name, &self.get_attachment(dt, &name)?)?;
+            }
+        }
+        Ok(target)
     }
 
-    pub fn add_entry(&self, dt: &DateTime<Utc>, text: &str) -> FileRepoResult<()> {
-        add_entry(&self.root, dt, text)
+    /// Removes the files of entries that `copy_to_layout` copied to
+    /// `target`, and the directories that become empty.
+    pub(crate) fn remove_moved_files(&self, target: &Tree) -> StorageResult<()> {
+        for dt in self.list()? {
+            let old_path = self.file_path(&dt);
+            if old_path == target.file_path(&dt) || !target.file_path(&dt).exists() {
+                continue;
+            }
+            fs::remove_file(&old_path)?;
+            let attachment_dir = self.root.join(self.attachment_directory(&dt));
+            if attachment_dir.exists()
+                && self.attachment_directory(&dt) != target.attachment_directory(&dt)
+            {
+                fs::remove_dir_all(&attachment_dir)?;
+            }
+            if let Some(dir) = old_path.parent() {
+                self.remove_empty_directories(dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every entry file and attachment, and the directories that
+    /// become empty.
+    pub(crate) fn remove_entry_files(&self) -> StorageResult<()> {
+        for dt in self.list()? {
+            self.delete(&dt)?;
+            if let Some(dir) = self.file_path(&dt).parent() {
+                self.remove_empty_directories(dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_empty_directories(&self, dir: &Path) -> io::Result<()> {
+        let mut dir = dir;
+        while dir != self.root && dir.starts_with(&self.root) {
+            if fs::read_dir(dir)?.next().is_some() {
+                break;
+            }
+            fs::remove_dir(dir)?;
+            dir = match dir.parent() {
+                Some(parent) => parent,
+                None => break,
+            };
+        }
+        Ok(())
+    }
+
+    fn file_path(&self, dt: &DateTime<Utc>) -> PathBuf {
+        self.root.join(self.layout.file_path(dt))
+    }
+
+    /// The directory of the attachments of the entry for `dt`, next to the
+    /// entry file.
+    fn attachment_directory(&self, dt: &DateTime<Utc>) -> PathBuf {
+        self.layout
+            .file_directory(dt)
+            .join(attachment_directory_name(dt))
     }
 }
 
-#[derive(Debug)]
-pub enum FileRepoError {
-    BadPathError(PathBuf),
-    EntryNotFound(DateTime<Utc>),
-    IoError(io::Error),
-    NameParseError(String, chrono::ParseError),
-    EntryContentDecodingError(string::FromUtf8Error),
+impl Storage for Tree {
+    fn list(&self) -> StorageResult<Vec<DateTime<Utc>>> {
+        Ok(collect_dates(&self.root, &self.layout)?)
+    }
+
+    fn get(&self, dt: &DateTime<Utc>) -> StorageResult<String> {
+        let data = self.get_bytes(dt)?;
+        String::from_utf8(data).map_err(StorageError::EntryContentDecodingError)
+    }
+
+    fn get_bytes(&self, dt: &DateTime<Utc>) -> StorageResult<Vec<u8>> {
+        fs::read(self.file_path(dt)).map_err(|e| StorageError::from_ioerror(e, dt))
+    }
+
+    fn put(&self, dt: &DateTime<Utc>, text: &str) -> StorageResult<()> {
+        self.put_bytes(dt, text.as_bytes())
+    }
+
+    fn put_bytes(&self, dt: &DateTime<Utc>, data: &[u8]) -> StorageResult<()> {
+        let entry_dir = self.root.join(self.layout.file_directory(dt));
+        fs::create_dir_all(&entry_dir)?;
+        write_atomically(&entry_dir, &self.layout.file_name(dt), data)?;
+        Ok(())
+    }
+
+    fn delete(&self, dt: &DateTime<Utc>) -> StorageResult<()> {
+        fs::remove_file(self.file_path(dt)).map_err(|e| StorageError::from_ioerror(e, dt))?;
+        match fs::remove_dir_all(self.root.join(self.attachment_directory(dt))) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(StorageError::IoError(e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn stat(&self, dt: &DateTime<Utc>) -> StorageResult<EntryStat> {
+        let metadata =
+            fs::metadata(self.file_path(dt)).map_err(|e| StorageError::from_ioerror(e, dt))?;
+        Ok(EntryStat {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn list_attachments(&self, dt: &DateTime<Utc>) -> StorageResult<Vec<String>> {
+        let entries = match fs::read_dir(self.root.join(self.attachment_directory(dt))) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(StorageError::IoError(e)),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            if let Some(name) = entry?.file_name().to_str() {
+                if !name.starts_with('.') {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort_unstable();
+        Ok(names)
+    }
+
+    fn get_attachment(&self, dt: &DateTime<Utc>, name: &str) -> StorageResult<Vec<u8>> {
+        fs::read(self.root.join(self.attachment_directory(dt)).join(name)).map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                StorageError::AttachmentNotFound(*dt, name.to_string())
+            } else {
+                StorageError::IoError(e)
+            }
+        })
+    }
+
+    fn put_attachment(&self, dt: &DateTime<Utc>, name: &str, data: &[u8]) -> StorageResult<()> {
+        let dir = self.root.join(self.attachment_directory(dt));
+        fs::create_dir_all(&dir)?;
+        write_atomically(&dir, name, data)?;
+        Ok(())
+    }
+
+    /// Attachments are kept in a directory next to the entry file, so the
+    /// link is relative to the entry's directory.
+    fn attachment_link(&self, dt: &DateTime<Utc>, name: &str) -> String {
+        format!("{}/{}", attachment_directory_name(dt), name)
+    }
+
+    fn entry_files(&self) -> Option<&dyn EntryFiles> {
+        Some(self)
+    }
 }
 
-impl FileRepoError {
-    fn from_ioerror(error: io::Error, dt: &DateTime<Utc>) -> FileRepoError {
-        if error.kind() == io::ErrorKind::NotFound {
-            FileRepoError::EntryNotFound(*dt)
+impl EntryFiles for Tree {
+    fn stray_files(&self) -> FileRepoResult<Vec<StrayFile>> {
+        let mut stray = Vec::new();
+        let visitor = &mut |fp: &Path| {
+            let relative = match fp.strip_prefix(&self.root) {
+                Ok(relative) => relative,
+                Err(_) => return,
+            };
+            let file_name = match relative.file_name().and_then(|s| s.to_str()) {
+                Some(name) => name,
+                None => return stray.push(StrayFile::Unknown(relative.to_path_buf())),
+            };
+            let dir = relative.parent().unwrap_or_else(|| Path::new(""));
+            if dir.as_os_str().is_empty() && is_diary_file(file_name) {
+                return;
+            }
+            match self.layout.parse_file_name(file_name) {
+                Some(dt) if dir == self.layout.file_directory(&dt) => (),
+                Some(dt) => stray.push(StrayFile::Misplaced(relative.to_path_buf(), dt)),
+                None => stray.push(StrayFile::Unknown(relative.to_path_buf())),
+            }
+        };
+        visit_dirs(&self.root, visitor)?;
+        stray.sort_unstable_by(|a, b| stray_path(a).cmp(stray_path(b)));
+        Ok(stray)
+    }
+
+    fn move_entry_file(&self, path: &Path, dt: &DateTime<Utc>) -> FileRepoResult<bool> {
+        let target = self.file_path(dt);
+        if target.exists() {
+            return Ok(false);
+        }
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let source = self.root.join(path);
+        fs::rename(&source, &target)?;
+        if let Some(dir) = source.parent() {
+            let attachments = dir.join(attachment_directory_name(dt));
+            let target_attachments = self.root.join(self.attachment_directory(dt));
+            if attachments.is_dir() && !target_attachments.exists() {
+                fs::rename(&attachments, &target_attachments)?;
+            }
+            self.remove_empty_directories(dir)?;
+        }
+        Ok(true)
+    }
+
+    fn entry_for_path(&self, path: &Path) -> Option<DateTime<Utc>> {
+        if path
+            .components()
+            .any(|c| is_hidden(Path::new(c.as_os_str())))
+        {
+            return None;
+        }
+        let dt = self
+            .layout
+            .parse_file_name(path.file_name().and_then(|n| n.to_str())?)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        if dir == self.layout.file_directory(&dt) {
+            Some(dt)
         } else {
-            FileRepoError::IoError(error)
+            None
         }
     }
 }
 
+#[derive(Debug)]
+pub enum FileRepoError {
+    BadPathError(PathBuf),
+    IoError(io::Error),
+    InvalidLayout(String),
+    /// Changing the layout would overwrite the file at this path.
+    LayoutConflict(PathBuf),
+}
+
 impl fmt::Display for FileRepoError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -71,13 +323,12 @@ impl fmt::Display for FileRepoError {
                 write!(f, "IO Error: ")?;
                 e.fmt(f)
             }
-            FileRepoError::EntryNotFound(dt) => write!(f, "Entry for date {} not found", dt),
-            FileRepoError::NameParseError(name, e) => {
-                write!(f, "Date parse error with name {}: {}", name, e)
-            }
-            FileRepoError::EntryContentDecodingError(e) => {
-                write!(f, "Error decoding diary entry content: {}", e)
-            }
+            FileRepoError::InvalidLayout(e) => write!(f, "Invalid layout: {}", e),
+            FileRepoError::LayoutConflict(p) => write!(
+                f,
+                "Changing the layout would overwrite {}",
+                p.to_str().unwrap_or("(no path)")
+            ),
         }
     }
 }
@@ -88,79 +339,59 @@ impl From<io::Error> for FileRepoError {
     }
 }
 
-fn get_text(dir: &Path, dt: &DateTime<Utc>) -> FileRepoResult<String> {
-    let path = file_path(dt);
-    let data = fs::read(dir.join(path)).map_err(|e| FileRepoError::from_ioerror(e, dt))?;
-    match String::from_utf8(data) {
-        Ok(s) => FileRepoResult::Ok(s),
-        Err(e) => FileRepoResult::Err(FileRepoError::EntryContentDecodingError(e)),
-    }
-}
-
-fn add_entry(dir: &Path, dt: &DateTime<Utc>, text: &str) -> FileRepoResult<()> {
-    let path = file_directory(&dt);
-    let mut full_path = dir.join(&path);
-    fs::create_dir_all(&full_path)?;
-    full_path.push(format_file_name(dt));
-    fs::write(&full_path, text)?;
-    Ok(())
+/// Writes `data` to the file `name` in `dir` so that the file has either its
+/// old or its new content even if the write is interrupted: the data goes
+/// to a temporary file in the same directory which is synced and then
+/// renamed over the old file.
+pub(crate) fn write_atomically(dir: &Path, name: &str, data: &[u8]) -> io::Result<()> {
+    let mut temp_file = tempfile::Builder::new().prefix(".tmp-").tempfile_in(dir)?;
+    temp_file.write_all(data)?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(dir.join(name)).map_err(|e| e.error)?;
+    sync_directory(dir)
 }
 
-fn file_path(dt: &DateTime<Utc>) -> PathBuf {
-    let mut path = file_directory(dt);
-    path.push(format_file_name(dt));
-    path
+#[cfg(unix)]
+fn sync_directory(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
 }
 
-fn file_directory(dt: &DateTime<Utc>) -> PathBuf {
-    let mut path = PathBuf::new();
-    path.push(format!("{:04}", dt.year()));
-    path.push(format!("{:02}", dt.month()));
-    path
+#[cfg(not(unix))]
+fn sync_directory(_dir: &Path) -> io::Result<()> {
+    Ok(())
 }
 
-fn format_file_name(dt: &DateTime<Utc>) -> String {
-    dt.format(FILE_NAME_FORMAT).to_string()
+/// The name of the attachment directory of the entry for `dt`. It doesn't
+/// depend on the layout, so links to attachments stay valid when the layout
+/// changes.
+fn attachment_directory_name(dt: &DateTime<Utc>) -> String {
+    format!(
+        "{}{}",
+        dt.format(DEFAULT_FILE_NAME_FORMAT),
+        ATTACHMENT_DIRECTORY_SUFFIX
+    )
 }
 
-fn collect_dates(dir: &Path) -> FileRepoResult<Vec<DateTime<Utc>>> {
-    collect_files(dir).map(|names| {
-        names
-            .iter()
-            .filter_map(|name| Utc.datetime_from_str(name, FILE_NAME_FORMAT).ok())
-            .collect()
-    })
+fn collect_dates(dir: &Path, layout: &Layout) -> FileRepoResult<Vec<DateTime<Utc>>> {
+    collect_files(dir, layout).map(|files| files.into_iter().map(|(dt, _)| dt).collect())
 }
 
-fn collect_files(dir: &Path) -> FileRepoResult<Vec<String>> {
-    let mut files: Vec<String> = Vec::new();
+/// Finds the entry files in `dir`: files whose name `layout` can turn to a
+/// date and that are in the directory `layout` puts that date in.
+fn collect_files(dir: &Path, layout: &Layout) -> FileRepoResult<Vec<(DateTime<Utc>, PathBuf)>> {
+    let mut files = Vec::new();
     let visitor = &mut |fp: &Path| {
-        let parent1 = match fp.parent() {
-            Some(p) => p,
-            None => return,
-        };
-        let parent1_name = match parent1.file_name().and_then(|s| s.to_str()) {
-            Some(p) => p,
-            None => return,
-        };
-        let parent2_name = match parent1
-            .parent()
-            .and_then(|p| p.file_name())
-            .and_then(|s| s.to_str())
-        {
+        let file_name = match fp.file_name().and_then(|s| s.to_str()) {
             Some(p) => p,
             None => return,
         };
-        let file_name = match fp.file_name().and_then(|s| s.to_str()) {
-            Some(p) => p,
+        let dt = match layout.parse_file_name(file_name) {
+            Some(dt) => dt,
             None => return,
         };
-        let file_start1: String = file_name.chars().take(4).collect();
-        let file_start2: String = file_name.chars().skip(4).take(2).collect();
-
-        if parent2_name == file_start1 && parent1_name == file_start2 {
-            let path_str = String::from(file_name);
-            files.push(path_str);
+        let relative_dir = fp.parent().and_then(|p| p.strip_prefix(dir).ok());
+        if relative_dir == Some(layout.file_directory(&dt).as_path()) {
+            files.push((dt, fp.to_path_buf()));
         }
     };
     visit_dirs(dir, visitor)?;
@@ -172,7 +403,13 @@ fn visit_dirs(dir: &Path, cb: &mut dyn FnMut(&Path)) -> io::Result<()> {
         for entry in fs::read_dir(dir)? {
             let entry = entry?;
             let path = entry.path();
+            if is_hidden(&path) {
+                continue;
+            }
             if path.is_dir() {
+                if is_attachment_directory(&path) {
+                    continue;
+                }
                 visit_dirs(&path, cb)?;
             } else {
                 cb(&path);
@@ -182,4 +419,30 @@ fn visit_dirs(dir: &Path, cb: &mut dyn FnMut(&Path)) -> io::Result<()> {
     Ok(())
 }
 
-static FILE_NAME_FORMAT: &str = "%Y%m%dT%H%M";
+fn stray_path(stray: &StrayFile) -> &Path {
+    match stray {
+        StrayFile::Unknown(path) | StrayFile::Misplaced(path, _) => path,
+    }
+}
+
+/// Tells if `name`, in the root of a diary, is one of the files the diary
+/// keeps besides entries.
+fn is_diary_file(name: &str) -> bool {
+    name == CONFIG_FILE_NAME || name.starts_with(INDEX_FILE_NAME)
+}
+
+/// Hidden files and directories, like the entry history, the git
+/// repository and temporary files, are never entries.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with('.'))
+}
+
+fn is_attachment_directory(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.ends_with(ATTACHMENT_DIRECTORY_SUFFIX))
+}
+
+static ATTACHMENT_DIRECTORY_SUFFIX: &str = ".attachments";
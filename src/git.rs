@@ -0,0 +1,134 @@
+use chrono::{DateTime, TimeZone, Utc};
+use git2::{Commit, ErrorCode, IndexAddOption, Repository, Signature, Sort};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A git repository in a diary directory. Changes to the diary are committed
+/// to it as they're made; nothing is ever pushed or fetched.
+pub struct GitRepo {
+    repo: Repository,
+}
+
+/// A commit in the history of a diary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub id: String,
+    pub time: DateTime<Utc>,
+    pub message: String,
+}
+
+impl GitRepo {
+    /// Opens the repository in `root`, creating it if there isn't one yet.
+    /// Files that aren't part of the diary, like the tag index, are ignored.
+    pub fn init(root: &Path) -> Result<GitRepo, git2::Error> {
+        let repo = match Repository::open(root) {
+            Ok(repo) => repo,
+            Err(e) if e.code() == ErrorCode::NotFound => Repository::init(root)?,
+            Err(e) => return Err(e),
+        };
+        add_ignored_files(root).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+        Ok(GitRepo { repo })
+    }
+
+    /// Commits every change in the diary directory with `message`. Returns
+    /// false if there was nothing to commit.
+    pub fn commit_all(&self, message: &str) -> Result<bool, git2::Error> {
+        let mut index = self.repo.index()?;
+        index.add_all(["*"].iter(), IndexAddOption::DEFAULT, None)?;
+        index.update_all(["*"].iter(), None)?;
+        index.write()?;
+        let tree_id = index.write_tree()?;
+        let parent = self.head_commit()?;
+        if parent.as_ref().is_some_and(|p| p.tree_id() == tree_id) {
+            return Ok(false);
+        }
+        let tree = self.repo.find_tree(tree_id)?;
+        let signature = self
+            .repo
+            .signature()
+            .or_else(|_| Signature::now(DEFAULT_NAME, DEFAULT_EMAIL))?;
+        let parents: Vec<&Commit> = parent.iter().collect();
+        self.repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )?;
+        Ok(true)
+    }
+
+    /// Lists the commits of the repository, newest first.
+    pub fn log(&self) -> Result<Vec<Change>, git2::Error> {
+        if self.head_commit()?.is_none() {
+            return Ok(Vec::new());
+        }
+        let mut walk = self.repo.revwalk()?;
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+        walk.push_head()?;
+        walk.map(|oid| {
+            let commit = self.repo.find_commit(oid?)?;
+            Ok(Change {
+                id: commit.id().to_string(),
+                time: Utc
+                    .timestamp_opt(commit.time().seconds(), 0)
+                    .single()
+                    .unwrap_or_default(),
+                message: commit.message().unwrap_or_default().trim_end().to_string(),
+            })
+        })
+        .collect()
+    }
+
+    fn head_commit(&self) -> Result<Option<Commit<'_>>, git2::Error> {
+        match self.repo.head() {
+            Ok(head) => Ok(Some(head.peel_to_commit()?)),
+            Err(e) if e.code() == ErrorCode::UnbornBranch || e.code() == ErrorCode::NotFound => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl fmt::Debug for GitRepo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GitRepo")
+            .field("path", &self.repo.path())
+            .finish()
+    }
+}
+
+/// Adds the patterns in `IGNORED_FILES` that the `.gitignore` in `root`
+/// doesn't have yet to the end of it, creating it if there isn't one.
+fn add_ignored_files(root: &Path) -> io::Result<()> {
+    let path = root.join(".gitignore");
+    let mut text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let missing: Vec<&str> = IGNORED_FILES
+        .lines()
+        .filter(|pattern| !text.lines().any(|line| line.trim() == *pattern))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    for pattern in missing {
+        text.push_str(pattern);
+        text.push('\n');
+    }
+    fs::write(&path, text)
+}
+
+static IGNORED_FILES: &str =
+    "/index.sqlite\n/index.sqlite-journal\n/diary.sqlite-journal\n/.lock\n.tmp-*\n";
+static DEFAULT_NAME: &str = "ddiary";
+static DEFAULT_EMAIL: &str = "ddiary@localhost";
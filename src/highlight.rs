@@ -0,0 +1,35 @@
+use ansi_term::Colour;
+use diary_core::{parse_spans, SpanKind};
+
+pub fn highlight(text: &str) -> String {
+    parse_spans(text)
+        .iter()
+        .map(|span| {
+            let part = &text[span.range.clone()];
+            match span.kind {
+                SpanKind::Text => part.to_string(),
+                SpanKind::Tag(_) => Colour::Cyan.bold().paint(part).to_string(),
+                SpanKind::Mention(_) => Colour::Yellow.paint(part).to_string(),
+                SpanKind::Link => Colour::Blue.underline().paint(part).to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlight_keeps_plain_text() {
+        assert_eq!(highlight("no tags here"), "no tags here");
+    }
+
+    #[test]
+    fn highlight_colours_tags() {
+        assert_eq!(
+            highlight("a #tag"),
+            format!("a {}", Colour::Cyan.bold().paint("#tag"))
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use crate::crypto::Cipher;
+use crate::history::{EntryVersion, History, HistoryError, HistoryResult};
+use chrono::{DateTime, Utc};
+
+/// History that encrypts versions before handing them to another history.
+#[derive(Debug)]
+pub struct EncryptedHistory<H> {
+    inner: H,
+    cipher: Cipher,
+}
+
+impl<H: History> EncryptedHistory<H> {
+    pub fn new(inner: H, cipher: Cipher) -> EncryptedHistory<H> {
+        EncryptedHistory { inner, cipher }
+    }
+}
+
+impl<H: History> History for EncryptedHistory<H> {
+    fn save_bytes(
+        &self,
+        dt: &DateTime<Utc>,
+        data: &[u8],
+        saved: &DateTime<Utc>,
+    ) -> HistoryResult<()> {
+        self.inner.save(dt, &self.cipher.encrypt_bytes(data), saved)
+    }
+
+    fn versions(&self, dt: &DateTime<Utc>) -> HistoryResult<Vec<EntryVersion>> {
+        self.inner.versions(dt)
+    }
+
+    fn get_bytes(&self, dt: &DateTime<Utc>, number: usize) -> HistoryResult<Vec<u8>> {
+        let data = self.inner.get_bytes(dt, number)?;
+        self.cipher
+            .decrypt_bytes(&data)
+            .map_err(|e| HistoryError::VersionDecryptionError(*dt, e))
+    }
+}
@@ -0,0 +1,135 @@
+use crate::filerepo::tree::write_atomically;
+use crate::history::{EntryVersion, History, HistoryError, HistoryResult};
+use chrono::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// History kept in the `.history` directory of a diary. Each entry has a
+/// directory of its own with a file per version, named after the version
+/// number and the time the version was replaced.
+#[derive(Debug)]
+pub struct FileHistory {
+    root: PathBuf,
+}
+
+impl FileHistory {
+    /// Creates a history for the diary in the directory `diary_root`.
+    pub fn new(diary_root: &Path) -> FileHistory {
+        FileHistory {
+            root: diary_root.join(HISTORY_DIRECTORY_NAME),
+        }
+    }
+
+    /// Replaces every version with the result of `transform`, if it
+    /// returns one.
+    pub(crate) fn transform_versions<E, F>(&self, transform: F) -> Result<(), E>
+    where
+        E: From<HistoryError>,
+        F: Fn(&[u8]) -> Result<Option<Vec<u8>>, E>,
+    {
+        for entry_dir in read_dir_paths(&self.root)? {
+            for version_path in read_dir_paths(&entry_dir)? {
+                let data = fs::read(&version_path).map_err(HistoryError::from)?;
+                if let Some(new_data) = transform(&data)? {
+                    let name = version_path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or_default();
+                    write_atomically(&entry_dir, name, &new_data).map_err(HistoryError::from)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn entry_directory(&self, dt: &DateTime<Utc>) -> PathBuf {
+        self.root
+            .join(dt.format(ENTRY_DIRECTORY_FORMAT).to_string())
+    }
+
+    fn version_files(&self, dt: &DateTime<Utc>) -> HistoryResult<Vec<(EntryVersion, PathBuf)>> {
+        let mut versions: Vec<(EntryVersion, PathBuf)> = read_dir_paths(&self.entry_directory(dt))?
+            .into_iter()
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?;
+                parse_version_file_name(name).map(|version| (version, path.clone()))
+            })
+            .collect();
+        versions.sort_unstable_by_key(|(version, _)| version.number);
+        Ok(versions)
+    }
+}
+
+impl History for FileHistory {
+    fn save_bytes(
+        &self,
+        dt: &DateTime<Utc>,
+        data: &[u8],
+        saved: &DateTime<Utc>,
+    ) -> HistoryResult<()> {
+        let number = self
+            .version_files(dt)?
+            .last()
+            .map_or(1, |(version, _)| version.number + 1);
+        let dir = self.entry_directory(dt);
+        fs::create_dir_all(&dir)?;
+        let name = format!("{:04}-{}", number, saved.format(SAVED_FORMAT));
+        write_atomically(&dir, &name, data)?;
+        Ok(())
+    }
+
+    fn versions(&self, dt: &DateTime<Utc>) -> HistoryResult<Vec<EntryVersion>> {
+        Ok(self
+            .version_files(dt)?
+            .into_iter()
+            .map(|(version, _)| version)
+            .collect())
+    }
+
+    fn get_bytes(&self, dt: &DateTime<Utc>, number: usize) -> HistoryResult<Vec<u8>> {
+        let path = self
+            .version_files(dt)?
+            .into_iter()
+            .find(|(version, _)| version.number == number)
+            .map(|(_, path)| path)
+            .ok_or(HistoryError::VersionNotFound(*dt, number))?;
+        Ok(fs::read(path)?)
+    }
+}
+
+fn parse_version_file_name(name: &str) -> Option<EntryVersion> {
+    let mut parts = name.splitn(2, '-');
+    let number = parts.next()?.parse::<usize>().ok()?;
+    let saved = NaiveDateTime::parse_from_str(parts.next()?, SAVED_FORMAT).ok()?;
+    Some(EntryVersion {
+        number,
+        saved: Utc.from_utc_datetime(&saved),
+    })
+}
+
+/// Lists the paths in `dir`, leaving out hidden files such as unfinished
+/// writes. A missing directory is empty.
+fn read_dir_paths(dir: &Path) -> HistoryResult<Vec<PathBuf>> {
+    match fs::read_dir(dir) {
+        Ok(entries) => {
+            let paths = entries
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<io::Result<Vec<PathBuf>>>()?;
+            Ok(paths.into_iter().filter(|p| !is_hidden(p)).collect())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(HistoryError::from(e)),
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with('.'))
+}
+
+pub static HISTORY_DIRECTORY_NAME: &str = ".history";
+
+static ENTRY_DIRECTORY_FORMAT: &str = "%Y%m%dT%H%M";
+static SAVED_FORMAT: &str = "%Y%m%dT%H%M%SZ";
@@ -0,0 +1,61 @@
+use crate::history::{EntryVersion, History, HistoryError, HistoryResult};
+use chrono::{DateTime, Utc};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// History that keeps versions in memory only.
+#[derive(Debug, Default)]
+pub struct MemoryHistory {
+    versions: RefCell<BTreeMap<DateTime<Utc>, Vec<SavedText>>>,
+}
+
+/// The time a text was replaced and the text.
+type SavedText = (DateTime<Utc>, Vec<u8>);
+
+impl MemoryHistory {
+    pub fn new() -> MemoryHistory {
+        MemoryHistory::default()
+    }
+}
+
+impl History for MemoryHistory {
+    fn save_bytes(
+        &self,
+        dt: &DateTime<Utc>,
+        data: &[u8],
+        saved: &DateTime<Utc>,
+    ) -> HistoryResult<()> {
+        self.versions
+            .borrow_mut()
+            .entry(*dt)
+            .or_default()
+            .push((*saved, data.to_vec()));
+        Ok(())
+    }
+
+    fn versions(&self, dt: &DateTime<Utc>) -> HistoryResult<Vec<EntryVersion>> {
+        Ok(self
+            .versions
+            .borrow()
+            .get(dt)
+            .map(|versions| {
+                (1..)
+                    .zip(versions)
+                    .map(|(number, (saved, _))| EntryVersion {
+                        number,
+                        saved: *saved,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn get_bytes(&self, dt: &DateTime<Utc>, number: usize) -> HistoryResult<Vec<u8>> {
+        self.versions
+            .borrow()
+            .get(dt)
+            .and_then(|versions| versions.get(number.wrapping_sub(1)))
+            .map(|(_, text)| text.clone())
+            .ok_or(HistoryError::VersionNotFound(*dt, number))
+    }
+}
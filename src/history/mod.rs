@@ -0,0 +1,86 @@
+pub mod encrypted;
+pub mod file;
+pub mod memory;
+
+use crate::crypto::CryptoError;
+use chrono::{DateTime, Utc};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::string;
+
+pub type HistoryResult<T> = Result<T, HistoryError>;
+
+#[derive(Debug)]
+pub enum HistoryError {
+    VersionNotFound(DateTime<Utc>, usize),
+    IoError(io::Error),
+    VersionContentDecodingError(string::FromUtf8Error),
+    VersionDecryptionError(DateTime<Utc>, CryptoError),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HistoryError::VersionNotFound(dt, number) => {
+                write!(f, "Version {} of entry for date {} not found", number, dt)
+            }
+            HistoryError::IoError(e) => write!(f, "IO Error: {}", e),
+            HistoryError::VersionContentDecodingError(e) => {
+                write!(f, "Error decoding entry version content: {}", e)
+            }
+            HistoryError::VersionDecryptionError(dt, e) => {
+                write!(
+                    f,
+                    "Error decrypting version of entry for date {}: {}",
+                    dt, e
+                )
+            }
+        }
+    }
+}
+
+impl Error for HistoryError {}
+
+impl From<io::Error> for HistoryError {
+    fn from(error: io::Error) -> Self {
+        HistoryError::IoError(error)
+    }
+}
+
+/// A place to keep the earlier versions of diary entries in.
+pub trait History: fmt::Debug {
+    /// Keeps `data` as the newest earlier version of the entry for `dt`,
+    /// even if it's not valid UTF-8. `saved` is the time it was replaced.
+    fn save_bytes(
+        &self,
+        dt: &DateTime<Utc>,
+        data: &[u8],
+        saved: &DateTime<Utc>,
+    ) -> HistoryResult<()>;
+
+    /// Lists the earlier versions of the entry for `dt`, oldest first.
+    fn versions(&self, dt: &DateTime<Utc>) -> HistoryResult<Vec<EntryVersion>>;
+
+    /// Returns version `number` of the entry for `dt` as it was saved.
+    fn get_bytes(&self, dt: &DateTime<Utc>, number: usize) -> HistoryResult<Vec<u8>>;
+
+    /// Keeps `text` as the newest earlier version of the entry for `dt`.
+    /// `saved` is the time the text was replaced.
+    fn save(&self, dt: &DateTime<Utc>, text: &str, saved: &DateTime<Utc>) -> HistoryResult<()> {
+        self.save_bytes(dt, text.as_bytes(), saved)
+    }
+
+    fn get(&self, dt: &DateTime<Utc>, number: usize) -> HistoryResult<String> {
+        String::from_utf8(self.get_bytes(dt, number)?)
+            .map_err(HistoryError::VersionContentDecodingError)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryVersion {
+    /// Versions are numbered from 1, oldest first.
+    pub number: usize,
+    /// When the version was replaced with a newer one.
+    pub saved: DateTime<Utc>,
+}
@@ -0,0 +1,192 @@
+use crate::diaryentrykey::DiaryEntryKey;
+use crate::import::{
+    format_tag, local_key, minute_key, Import, ImportError, ImportedAttachment, ImportedEntry,
+    SkippedRecord,
+};
+use crate::tagparser::{self, normalize_tag};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+#[derive(Deserialize)]
+struct DayOneJournal {
+    entries: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DayOneEntry {
+    creation_date: String,
+    time_zone: Option<String>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    photos: Vec<DayOnePhoto>,
+}
+
+#[derive(Deserialize)]
+struct DayOnePhoto {
+    identifier: String,
+    md5: String,
+    #[serde(rename = "type")]
+    file_type: Option<String>,
+}
+
+/// Reads the entries of the journals in a Day One JSON export. Day One tags
+/// that aren't in the text already are added to the end of it, and photos
+/// become attachments linked from where they were in the text. Photos
+/// missing from the export are skipped, and so are entries that would be
+/// empty without them.
+pub fn read_dayone(path: &Path) -> Result<Import, ImportError> {
+    read_dayone_archive(File::open(path)?)
+}
+
+fn read_dayone_archive<R: Read + Seek>(reader: R) -> Result<Import, ImportError> {
+    let mut archive = ZipArchive::new(reader)?;
+    let names: Vec<String> = archive.file_names().map(String::from).collect();
+    let journals: Vec<&String> = names
+        .iter()
+        .filter(|name| name.ends_with(".json") && !name.starts_with("__MACOSX/"))
+        .collect();
+    if journals.is_empty() {
+        return Err(ImportError::InvalidFormat(
+            "No journals in the export".to_string(),
+        ));
+    }
+    let mut import = Import::default();
+    for journal_name in journals {
+        let mut json = String::new();
+        archive.by_name(journal_name)?.read_to_string(&mut json)?;
+        let journal: DayOneJournal = serde_json::from_str(&json)?;
+        for (i, value) in journal.entries.into_iter().enumerate() {
+            let record = format!("{} entry {}", journal_name, i + 1);
+            match serde_json::from_value(value) {
+                Ok(entry) => add_entry(&mut import, &mut archive, &names, record, entry)?,
+                Err(e) => import.skipped.push(SkippedRecord {
+                    record,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+    }
+    Ok(import)
+}
+
+fn add_entry<R: Read + Seek>(
+    import: &mut Import,
+    archive: &mut ZipArchive<R>,
+    names: &[String],
+    record: String,
+    entry: DayOneEntry,
+) -> Result<(), ImportError> {
+    let key = match entry_key(&entry.creation_date, entry.time_zone.as_deref()) {
+        Some(key) => key,
+        None => {
+            import.skipped.push(SkippedRecord {
+                record,
+                reason: format!("Invalid date {}", entry.creation_date),
+            });
+            return Ok(());
+        }
+    };
+    if entry.text.trim().is_empty() && entry.photos.is_empty() {
+        import.skipped.push(SkippedRecord {
+            record,
+            reason: "Empty entry".to_string(),
+        });
+        return Ok(());
+    }
+
+    let mut attachments = Vec::new();
+    for photo in entry.photos.iter() {
+        let name = match photo_file(names, photo) {
+            Some(name) => name,
+            None => {
+                import.skipped.push(SkippedRecord {
+                    record: format!("{} photo {}", record, photo.identifier),
+                    reason: "Not in the export".to_string(),
+                });
+                continue;
+            }
+        };
+        let mut data = Vec::new();
+        archive.by_name(name)?.read_to_end(&mut data)?;
+        attachments.push(ImportedAttachment {
+            name: name.trim_start_matches("photos/").to_string(),
+            data,
+            placeholder: Some(moment_link(photo)),
+        });
+    }
+
+    let text_tags: BTreeSet<String> = tagparser::find_tags(&entry.text)
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect();
+    let new_tags: Vec<String> = entry
+        .tags
+        .iter()
+        .filter(|tag| !tag.trim().is_empty() && !text_tags.contains(&normalize_tag(tag)))
+        .map(|tag| format_tag(tag.trim()))
+        .collect();
+    let mut text = entry.text.trim_end().to_string();
+    if !new_tags.is_empty() {
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        text.push_str(&new_tags.join(" "));
+    }
+    // Without any of its photos, an entry of only photos would be empty.
+    let without_photos = entry.photos.iter().fold(text.clone(), |text, photo| {
+        text.replace(&moment_link(photo), "")
+    });
+    if attachments.is_empty() && without_photos.trim().is_empty() {
+        import.skipped.push(SkippedRecord {
+            record,
+            reason: "Empty entry without its photos".to_string(),
+        });
+        return Ok(());
+    }
+    import.entries.push(ImportedEntry {
+        key,
+        text,
+        attachments,
+    });
+    Ok(())
+}
+
+/// The key of an entry created at `creation_date`. Day One writes the dates
+/// in UTC; ones without a time zone are taken to be in `time_zone`.
+fn entry_key(creation_date: &str, time_zone: Option<&str>) -> Option<DiaryEntryKey> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(creation_date) {
+        return Some(minute_key(dt.with_timezone(&Utc)));
+    }
+    let ndt = NaiveDateTime::parse_from_str(creation_date, "%Y-%m-%dT%H:%M:%S").ok()?;
+    let tz: Tz = time_zone?.parse().ok()?;
+    local_key(&ndt, &tz)
+}
+
+/// The link Day One puts in the text of an entry where `photo` is.
+fn moment_link(photo: &DayOnePhoto) -> String {
+    format!("![](dayone-moment://{})", photo.identifier)
+}
+
+/// The name of the file of `photo` in the export, `photos/MD5.TYPE`.
+fn photo_file<'a>(names: &'a [String], photo: &DayOnePhoto) -> Option<&'a str> {
+    let prefix = format!("photos/{}.", photo.md5);
+    names
+        .iter()
+        .filter(|name| name.starts_with(&prefix))
+        .find(|name| match &photo.file_type {
+            Some(file_type) => name[prefix.len()..] == **file_type,
+            None => true,
+        })
+        .or_else(|| names.iter().find(|name| name.starts_with(&prefix)))
+        .map(String::as_str)
+}
@@ -0,0 +1,236 @@
+use crate::import::{format_tag, local_key, Import, ImportError, ImportedEntry, SkippedRecord};
+use crate::tagparser::{self, is_word_char, SpanKind};
+use chrono::{NaiveDateTime, TimeZone};
+use serde::Deserialize;
+use std::ops::Range;
+
+#[derive(Deserialize)]
+struct JrnlExport {
+    entries: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct JrnlEntry {
+    title: String,
+    #[serde(default)]
+    body: String,
+    date: String,
+    time: String,
+}
+
+/// Reads the entries of a jrnl journal, either in jrnl's plain text format
+/// or exported with `jrnl --export json`. Times are taken to be in `tz`, and
+/// jrnl's `@tags` are turned into `#tags`. A space is taken away from the
+/// front of a line that would otherwise start a new entry, as written by
+/// `jrnl_document`.
+pub fn parse_jrnl<Tz: TimeZone>(input: &str, tz: &Tz) -> Result<Import, ImportError> {
+    if input.trim_start().starts_with('{') {
+        parse_json(input, tz)
+    } else {
+        Ok(parse_text(input, tz))
+    }
+}
+
+fn parse_text<Tz: TimeZone>(input: &str, tz: &Tz) -> Import {
+    let mut import = Import::default();
+    let mut current: Option<(usize, NaiveDateTime, Vec<&str>)> = None;
+    for (i, line) in input.lines().enumerate() {
+        if let Some((ndt, title)) = parse_heading(line) {
+            if let Some((line_number, ndt, lines)) = current.take() {
+                add_entry(
+                    &mut import,
+                    format!("line {}", line_number),
+                    &ndt,
+                    tz,
+                    &lines,
+                );
+            }
+            current = Some((i + 1, ndt, vec![title]));
+        } else if let Some((_, _, lines)) = current.as_mut() {
+            // `jrnl_document` puts a space in front of lines like headings.
+            match line.strip_prefix(' ') {
+                Some(rest) if parse_heading(rest.trim_start_matches(' ')).is_some() => {
+                    lines.push(rest)
+                }
+                _ => lines.push(line),
+            }
+        } else if !line.trim().is_empty() {
+            import.skipped.push(SkippedRecord {
+                record: format!("line {}", i + 1),
+                reason: "Text before the first entry".to_string(),
+            });
+        }
+    }
+    if let Some((line_number, ndt, lines)) = current {
+        add_entry(
+            &mut import,
+            format!("line {}", line_number),
+            &ndt,
+            tz,
+            &lines,
+        );
+    }
+    import
+}
+
+fn parse_json<Tz: TimeZone>(input: &str, tz: &Tz) -> Result<Import, ImportError> {
+    let export: JrnlExport = serde_json::from_str(input)?;
+    let mut import = Import::default();
+    for (i, value) in export.entries.into_iter().enumerate() {
+        let record = format!("entry {}", i + 1);
+        let entry: JrnlEntry = match serde_json::from_value(value) {
+            Ok(entry) => entry,
+            Err(e) => {
+                import.skipped.push(SkippedRecord {
+                    record,
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let ndt = match parse_date_time(&format!("{} {}", entry.date, entry.time)) {
+            Some(ndt) => ndt,
+            None => {
+                import.skipped.push(SkippedRecord {
+                    record,
+                    reason: format!("Invalid date {} {}", entry.date, entry.time),
+                });
+                continue;
+            }
+        };
+        // A body that continues the line of the title starts with a space.
+        let text = if entry.body.is_empty() || entry.body.starts_with(char::is_whitespace) {
+            format!("{}{}", entry.title, entry.body)
+        } else {
+            format!("{}\n{}", entry.title, entry.body)
+        };
+        add_entry(&mut import, record, &ndt, tz, &[&text]);
+    }
+    Ok(import)
+}
+
+fn add_entry<Tz: TimeZone>(
+    import: &mut Import,
+    record: String,
+    ndt: &NaiveDateTime,
+    tz: &Tz,
+    lines: &[&str],
+) {
+    let text = lines.join("\n");
+    let text = text.trim();
+    if text.is_empty() {
+        import.skipped.push(SkippedRecord {
+            record,
+            reason: "Empty entry".to_string(),
+        });
+        return;
+    }
+    match local_key(ndt, tz) {
+        Some(key) => import.entries.push(ImportedEntry {
+            key,
+            text: convert_tags(text),
+            attachments: Vec::new(),
+        }),
+        None => import.skipped.push(SkippedRecord {
+            record,
+            reason: format!("No such local time: {}", ndt),
+        }),
+    }
+}
+
+/// Splits a line like `[2020-09-21 13:37] Title` into the date and the
+/// title.
+pub(crate) fn parse_heading(line: &str) -> Option<(NaiveDateTime, &str)> {
+    let rest = line.strip_prefix('[')?;
+    let end = rest.find(']')?;
+    let ndt = parse_date_time(&rest[..end])?;
+    Some((ndt, rest[end + 1..].trim()))
+}
+
+fn parse_date_time(s: &str) -> Option<NaiveDateTime> {
+    DATE_TIME_FORMATS
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s.trim(), fmt).ok())
+}
+
+/// Turns jrnl's `@tags` into tags `tagparser` understands: `#tag`, or
+/// `#(tag)#` if the tag has characters other than letters and digits. Tags
+/// and links that are already in the text are left alone, and so are email
+/// addresses.
+pub(crate) fn convert_tags(text: &str) -> String {
+    let protected: Vec<Range<usize>> = tagparser::parse_spans(text)
+        .into_iter()
+        .filter(|span| matches!(span.kind, SpanKind::Tag(_) | SpanKind::Link))
+        .map(|span| span.range)
+        .collect();
+    let mut converted = String::with_capacity(text.len());
+    let mut previous: Option<char> = None;
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        if c == '@'
+            && !previous.is_some_and(char::is_alphanumeric)
+            && !protected.iter().any(|range| range.contains(&i))
+        {
+            let name = tag_name(&text[i + 1..]);
+            if !name.is_empty() {
+                converted.push_str(&format_tag(name));
+                i += 1 + name.len();
+                previous = name.chars().last();
+                continue;
+            }
+        }
+        converted.push(c);
+        previous = Some(c);
+        i += c.len_utf8();
+    }
+    converted
+}
+
+/// The jrnl tag at the start of `s`: letters, digits, underscores and
+/// hyphens, not ending in punctuation.
+fn tag_name(s: &str) -> &str {
+    let end = s
+        .char_indices()
+        .find(|(_, c)| !(is_word_char(*c) || *c == '_' || *c == '-'))
+        .map(|(i, _)| i)
+        .unwrap_or(s.len());
+    s[..end].trim_end_matches(['_', '-'])
+}
+
+const DATE_TIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %I:%M %p",
+    "%Y-%m-%d %I:%M:%S %p",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_tags() {
+        assert_eq!("#work and #home.", convert_tags("@work and @home."));
+        assert_eq!(
+            "#(multi-word)# #(snake_case)#",
+            convert_tags("@multi-word @snake_case")
+        );
+        assert_eq!(
+            "mail me@example.com #(kept tag)# https://example.com/@user",
+            convert_tags("mail me@example.com #(kept tag)# https://example.com/@user")
+        );
+        assert_eq!("@ alone, #café", convert_tags("@ alone, @café"));
+    }
+
+    #[test]
+    fn test_parse_heading() {
+        assert_eq!(
+            Some((
+                NaiveDateTime::parse_from_str("2020-09-21 13:37", "%Y-%m-%d %H:%M").unwrap(),
+                "Title."
+            )),
+            parse_heading("[2020-09-21 01:37 PM] Title.")
+        );
+        assert_eq!(None, parse_heading("[a link](https://example.com)"));
+    }
+}
@@ -0,0 +1,420 @@
+use crate::diaryentrykey::DiaryEntryKey;
+use crate::import::{
+    format_tag, local_key, minute_key, Import, ImportError, ImportedEntry, SkippedRecord,
+};
+use crate::tagparser::{self, normalize_tag};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How to read a folder of Markdown daily notes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkdownOptions {
+    /// A chrono format string for the file names of the notes, without the
+    /// `.md` extension.
+    pub file_name_pattern: String,
+    /// The time of the entries of notes whose date doesn't have one.
+    pub default_time: NaiveTime,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        MarkdownOptions {
+            file_name_pattern: "%Y-%m-%d".to_string(),
+            default_time: NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
+        }
+    }
+}
+
+/// A note with the name of its file, without the extension.
+struct Note {
+    name: String,
+    key: DiaryEntryKey,
+    text: String,
+}
+
+/// Reads the Markdown daily notes in `dir` and its subdirectories, like an
+/// Obsidian or Logseq vault. Each note is dated by its file name, or by a
+/// `date` in its front matter, and `tags` in the front matter are added to
+/// the end of the text. Times are taken to be in `tz`. Links to other notes,
+/// like `[[2020-09-21]]`, are changed to the keys of their entries. Links are
+/// matched to the file names of the notes, so they keep pointing to the note
+/// they were written for even if another note has the same date.
+pub fn read_markdown_notes<Tz: TimeZone>(
+    dir: &Path,
+    options: &MarkdownOptions,
+    tz: &Tz,
+) -> Result<Import, ImportError> {
+    if !dir.is_dir() {
+        return Err(ImportError::InvalidFormat(format!(
+            "Not a directory: {}",
+            dir.display()
+        )));
+    }
+    let mut files = Vec::new();
+    collect_notes(dir, &mut files)?;
+    files.sort();
+
+    let mut import = Import::default();
+    let mut notes = Vec::new();
+    for path in files {
+        let record = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        let input = match fs::read_to_string(&path) {
+            Ok(input) => input,
+            Err(e) => {
+                import.skipped.push(SkippedRecord {
+                    record,
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_string();
+        match read_note(&name, &input, options, tz) {
+            Ok(Some((key, text))) => notes.push(Note { name, key, text }),
+            Ok(None) => import.skipped.push(SkippedRecord {
+                record,
+                reason: "Empty note".to_string(),
+            }),
+            Err(reason) => import.skipped.push(SkippedRecord { record, reason }),
+        }
+    }
+
+    // A link to a name that's used in more than one folder goes to the
+    // first note with that name.
+    let mut keys: BTreeMap<&str, DiaryEntryKey> = BTreeMap::new();
+    for note in notes.iter() {
+        keys.entry(note.name.as_str())
+            .or_insert_with(|| note.key.clone());
+    }
+    for note in notes.iter() {
+        import.entries.push(ImportedEntry {
+            key: note.key.clone(),
+            text: replace_links(&note.text, &options.file_name_pattern, &keys),
+            attachments: Vec::new(),
+        });
+    }
+    Ok(import)
+}
+
+/// Returns the key and text of the note `input` in the file called `name`,
+/// or None if the note is empty.
+fn read_note<Tz: TimeZone>(
+    name: &str,
+    input: &str,
+    options: &MarkdownOptions,
+    tz: &Tz,
+) -> Result<Option<(DiaryEntryKey, String)>, String> {
+    let (front_matter, body) = split_front_matter(input);
+    let key = match front_matter.date.as_deref() {
+        Some(value) => parse_front_matter_date(value, options.default_time, tz)
+            .ok_or_else(|| format!("Invalid date in front matter: {}", value))?,
+        None => {
+            let date = NaiveDate::parse_from_str(name, &options.file_name_pattern)
+                .map_err(|_| "No date in the file name or front matter".to_string())?;
+            local_key(&date.and_time(options.default_time), tz)
+                .ok_or_else(|| format!("No such local time on {}", date))?
+        }
+    };
+
+    let text_tags: BTreeSet<String> = tagparser::find_tags(body)
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect();
+    let new_tags: Vec<String> = front_matter
+        .tags
+        .iter()
+        .filter(|tag| !text_tags.contains(&normalize_tag(tag)))
+        .map(|tag| format_tag(tag))
+        .collect();
+    let mut text = body.trim().to_string();
+    if !new_tags.is_empty() {
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        text.push_str(&new_tags.join(" "));
+    }
+    if text.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some((key, text)))
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct FrontMatter {
+    date: Option<String>,
+    tags: Vec<String>,
+}
+
+/// Splits the YAML front matter between `---` lines at the start of a note
+/// from the rest of it. Only `date` and `tags` are read from it, as a value
+/// or a list; the rest is left out of the entry.
+fn split_front_matter(input: &str) -> (FrontMatter, &str) {
+    let mut front_matter = FrontMatter::default();
+    let input = input.trim_start_matches('\u{feff}');
+    let rest = match input
+        .strip_prefix("---\n")
+        .or_else(|| input.strip_prefix("---\r\n"))
+    {
+        Some(rest) => rest,
+        None => return (front_matter, input),
+    };
+    let mut offset = 0;
+    let mut list_key: Option<String> = None;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        let line = line.trim_end();
+        if line == "---" || line == "..." {
+            return (front_matter, &rest[offset..]);
+        }
+        if let Some(item) = line.trim_start().strip_prefix("- ") {
+            let tag = front_matter_tag(item);
+            if list_key.as_deref() == Some("tags") && !tag.is_empty() {
+                front_matter.tags.push(tag.to_string());
+            }
+            continue;
+        }
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) if !key.starts_with(' ') => (key.trim(), value.trim()),
+            _ => continue,
+        };
+        list_key = Some(key.to_string());
+        match key {
+            "date" if !value.is_empty() => front_matter.date = Some(unquote(value).to_string()),
+            "tags" => front_matter.tags.extend(
+                value
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .split(',')
+                    .map(front_matter_tag)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string),
+            ),
+            _ => (),
+        }
+    }
+    // Without the closing line it's not front matter.
+    (FrontMatter::default(), input)
+}
+
+fn unquote(s: &str) -> &str {
+    s.trim_matches(|c| c == '"' || c == '\'')
+}
+
+/// A tag from the front matter without quotes or a leading `#`.
+fn front_matter_tag(s: &str) -> &str {
+    unquote(s.trim()).trim_start_matches('#')
+}
+
+fn parse_front_matter_date<Tz: TimeZone>(
+    value: &str,
+    default_time: NaiveTime,
+    tz: &Tz,
+) -> Option<DiaryEntryKey> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(minute_key(dt.with_timezone(&Utc)));
+    }
+    let ndt = FRONT_MATTER_DATE_TIME_FORMATS
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(default_time))
+        })?;
+    local_key(&ndt, tz)
+}
+
+/// Replaces links like `[[2020-09-21]]` or `[[2020-09-21|text]]` to notes
+/// with the key of the entry of the note, `[[2020-09-21 10:00 +0000]]`.
+/// `keys` has the entry keys by the file names of the notes. Links to notes
+/// that aren't named after a date in `pattern` keep the name as the text.
+/// Links to names without a note are left alone.
+fn replace_links(text: &str, pattern: &str, keys: &BTreeMap<&str, DiaryEntryKey>) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let end = match after.find("]]") {
+            Some(end) if !after[..end].contains('\n') => end,
+            _ => break,
+        };
+        let (target, label) = match after[..end].split_once('|') {
+            Some((target, label)) => (target, Some(label)),
+            None => (&after[..end], None),
+        };
+        replaced.push_str(&rest[..start]);
+        let target = target.trim();
+        let label = label.or_else(|| {
+            NaiveDate::parse_from_str(target, pattern)
+                .err()
+                .map(|_| target)
+        });
+        match (keys.get(target), label) {
+            (Some(key), Some(label)) => replaced.push_str(&format!("[[{}|{}]]", key, label)),
+            (Some(key), None) => replaced.push_str(&format!("[[{}]]", key)),
+            (None, _) => replaced.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+/// Collects the `.md` files in `dir`, skipping hidden directories like
+/// `.obsidian` and `.trash`.
+fn collect_notes(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), ImportError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            collect_notes(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "md") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+const FRONT_MATTER_DATE_TIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_front_matter() {
+        let (front_matter, body) = split_front_matter(
+            "---\ntitle: Day\ndate: \"2020-09-21 08:30\"\ntags: [a, \"b c\"]\n---\nText\n",
+        );
+        assert_eq!(
+            FrontMatter {
+                date: Some("2020-09-21 08:30".to_string()),
+                tags: vec!["a".to_string(), "b c".to_string()],
+            },
+            front_matter
+        );
+        assert_eq!("Text\n", body);
+
+        let (front_matter, body) = split_front_matter("---\ntags:\n  - one\n  - two\n---\n");
+        assert_eq!(
+            vec!["one".to_string(), "two".to_string()],
+            front_matter.tags
+        );
+        assert_eq!("", body);
+
+        let (front_matter, _) =
+            split_front_matter("---\ntags:\n  - \"#quoted\"\n  - '#single'\n  - ''\n---\n");
+        assert_eq!(
+            vec!["quoted".to_string(), "single".to_string()],
+            front_matter.tags
+        );
+
+        let (front_matter, body) = split_front_matter("---\nNot front matter\n");
+        assert_eq!(FrontMatter::default(), front_matter);
+        assert_eq!("---\nNot front matter\n", body);
+    }
+
+    #[test]
+    fn test_read_note_dates() {
+        let options = MarkdownOptions::default();
+        assert_eq!(
+            Some((key("2020-09-21 12:00 +0000"), "Text".to_string())),
+            read_note("2020-09-21", "Text\n", &options, &Utc).unwrap()
+        );
+        // A date in the front matter takes the place of the file name.
+        assert_eq!(
+            Some((key("2020-09-22 08:30 +0000"), "Text".to_string())),
+            read_note(
+                "2020-09-21",
+                "---\ndate: 2020-09-22 08:30\n---\nText\n",
+                &options,
+                &Utc
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            Some((key("2020-09-22 12:00 +0000"), "Text".to_string())),
+            read_note(
+                "Meeting",
+                "---\ndate: 2020-09-22\n---\nText\n",
+                &options,
+                &Utc
+            )
+            .unwrap()
+        );
+        assert!(read_note("Meeting", "Text\n", &options, &Utc).is_err());
+        assert!(read_note("2020-09-21", "---\ndate: soon\n---\n", &options, &Utc).is_err());
+        assert_eq!(
+            None,
+            read_note("2020-09-21", "---\ntitle: Day\n---\n\n", &options, &Utc).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_note_merges_tags() {
+        let options = MarkdownOptions::default();
+        let note = "---\ntags: [\"#Work\", travel, multi word]\n---\nAt #work today.\n";
+        assert_eq!(
+            Some((
+                key("2020-09-21 12:00 +0000"),
+                "At #work today.\n\n#travel #(multi word)#".to_string()
+            )),
+            read_note("2020-09-21", note, &options, &Utc).unwrap()
+        );
+        assert_eq!(
+            Some((key("2020-09-21 12:00 +0000"), "#travel".to_string())),
+            read_note("2020-09-21", "---\ntags: travel\n---\n", &options, &Utc).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_replace_links() {
+        let mut keys = BTreeMap::new();
+        keys.insert("2020-09-21", key("2020-09-21 12:00 +0000"));
+        // Another note dated the same day, which links must not go to.
+        keys.insert("Meeting", key("2020-09-21 09:00 +0000"));
+        let pattern = "%Y-%m-%d";
+        assert_eq!(
+            "See [[2020-09-21 12:00 +0000]] and [[2020-09-21 12:00 +0000|that day]].",
+            replace_links(
+                "See [[2020-09-21]] and [[ 2020-09-21|that day]].",
+                pattern,
+                &keys
+            )
+        );
+        assert_eq!(
+            "[[2020-09-21 09:00 +0000|Meeting]] [[2020-09-22]] [[Unknown]]",
+            replace_links("[[Meeting]] [[2020-09-22]] [[Unknown]]", pattern, &keys)
+        );
+        assert_eq!(
+            "Open [[2020-09-21 and\n]] closed",
+            replace_links("Open [[2020-09-21 and\n]] closed", pattern, &keys)
+        );
+    }
+
+    fn key(s: &str) -> DiaryEntryKey {
+        DiaryEntryKey::parse_from_string(s).unwrap()
+    }
+}
@@ -0,0 +1,198 @@
+pub mod dayone;
+pub mod jrnl;
+pub mod markdown;
+pub mod text;
+
+use crate::diary::{Diary, DiaryError, MatchingDateBehavior};
+use crate::diaryentrykey::DiaryEntryKey;
+use crate::index::tags::TagIndex;
+use crate::storage::StorageError;
+use crate::tagparser::is_word_char;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Timelike, Utc};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// An entry read from the files of another diary program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedEntry {
+    pub key: DiaryEntryKey,
+    pub text: String,
+    pub attachments: Vec<ImportedAttachment>,
+}
+
+/// A file that belongs to an imported entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedAttachment {
+    pub name: String,
+    pub data: Vec<u8>,
+    /// Text in the entry that's replaced with a link to the attachment. The
+    /// link is added to the end of the entry if this is None or not found.
+    pub placeholder: Option<String>,
+}
+
+/// A record in the imported files that couldn't be turned into an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRecord {
+    /// Where the record is, like `line 12` or `entry 3`.
+    pub record: String,
+    pub reason: String,
+}
+
+/// Entries read from another diary program, to be added to a diary with
+/// `Diary::import`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Import {
+    pub entries: Vec<ImportedEntry>,
+    pub skipped: Vec<SkippedRecord>,
+}
+
+/// What importing did, or would do in a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// The keys of the imported entries, in the order they were imported.
+    pub imported: Vec<DiaryEntryKey>,
+    /// Imported entries whose key already had an entry.
+    pub collisions: Vec<DiaryEntryKey>,
+    pub skipped: Vec<SkippedRecord>,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    IoError(io::Error),
+    JsonError(serde_json::Error),
+    ZipError(zip::result::ZipError),
+    RegexError(regex::Error),
+    InvalidFormat(String),
+}
+
+impl From<io::Error> for ImportError {
+    fn from(error: io::Error) -> ImportError {
+        ImportError::IoError(error)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(error: serde_json::Error) -> ImportError {
+        ImportError::JsonError(error)
+    }
+}
+
+impl From<zip::result::ZipError> for ImportError {
+    fn from(error: zip::result::ZipError) -> ImportError {
+        ImportError::ZipError(error)
+    }
+}
+
+impl From<regex::Error> for ImportError {
+    fn from(error: regex::Error) -> ImportError {
+        ImportError::RegexError(error)
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::IoError(e) => write!(f, "IO Error: {}", e),
+            ImportError::JsonError(e) => write!(f, "Invalid JSON: {}", e),
+            ImportError::ZipError(e) => write!(f, "Invalid zip archive: {}", e),
+            ImportError::RegexError(e) => write!(f, "Invalid regular expression: {}", e),
+            ImportError::InvalidFormat(e) => write!(f, "Invalid format: {}", e),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+/// Adds the entries of `import` to `diary`. Entries whose key already has an
+/// entry, in the diary or earlier in the import, are handled according to
+/// `matching_date_behavior`. With `dry_run` nothing is changed. All the
+/// entries are committed together.
+pub(crate) fn import(
+    diary: &Diary,
+    tag_index: &TagIndex,
+    import: &Import,
+    matching_date_behavior: MatchingDateBehavior,
+    dry_run: bool,
+) -> Result<ImportReport, DiaryError> {
+    let _lock = if dry_run { None } else { diary.lock()? };
+    let mut keys: BTreeSet<DiaryEntryKey> = diary.list_keys()?.into_iter().collect();
+    let mut report = ImportReport {
+        skipped: import.skipped.clone(),
+        ..ImportReport::default()
+    };
+    for entry in import.entries.iter() {
+        if !keys.insert(entry.key.clone()) {
+            report.collisions.push(entry.key.clone());
+        }
+        if !dry_run {
+            let text = store_attachments(diary, entry, &mut report.skipped)?;
+            diary.save_entry(tag_index, &text, &entry.key, matching_date_behavior)?;
+        }
+        report.imported.push(entry.key.clone());
+    }
+    if !dry_run && !report.imported.is_empty() {
+        diary.commit(&format!("Import {} entries", report.imported.len()))?;
+    }
+    Ok(report)
+}
+
+/// Stores the attachments of `entry` and returns its text with links to
+/// them. Attachments the storage of `diary` can't keep are added to
+/// `skipped`.
+fn store_attachments(
+    diary: &Diary,
+    entry: &ImportedEntry,
+    skipped: &mut Vec<SkippedRecord>,
+) -> Result<String, DiaryError> {
+    let mut text = entry.text.clone();
+    for attachment in entry.attachments.iter() {
+        let link = match diary.store_attachment(&entry.key, &attachment.name, &attachment.data) {
+            Ok((_, link)) => link,
+            Err(DiaryError::StorageError(StorageError::AttachmentsNotSupported)) => {
+                skipped.push(SkippedRecord {
+                    record: format!("attachment {} of entry {}", attachment.name, entry.key),
+                    reason: "Attachments aren't supported by this diary".to_string(),
+                });
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        match &attachment.placeholder {
+            Some(placeholder) if text.contains(placeholder.as_str()) => {
+                text = text.replace(placeholder.as_str(), &link);
+            }
+            _ => text = format!("{}\n\n{}", text.trim_end(), link),
+        }
+    }
+    Ok(text)
+}
+
+/// Writes `tag` so that `tagparser` reads it back whole: `#tag`, or
+/// `#(tag)#` if it has characters other than letters and digits.
+pub(crate) fn format_tag(tag: &str) -> String {
+    if tag.chars().all(is_word_char) {
+        format!("#{}", tag)
+    } else {
+        format!("#({})#", tag)
+    }
+}
+
+/// The key of an entry written at the local time `ndt` in `tz`, or None if
+/// there's no such time in `tz`.
+pub(crate) fn local_key<Tz: TimeZone>(ndt: &NaiveDateTime, tz: &Tz) -> Option<DiaryEntryKey> {
+    tz.from_local_datetime(ndt)
+        .latest()
+        .map(|dt| minute_key(dt.with_timezone(&Utc)))
+}
+
+/// The key of an entry at `dt`. Keys don't have seconds.
+pub(crate) fn minute_key(dt: DateTime<Utc>) -> DiaryEntryKey {
+    DiaryEntryKey {
+        date: dt
+            .with_second(0)
+            .and_then(|dt| dt.with_nanosecond(0))
+            .unwrap_or(dt),
+    }
+}
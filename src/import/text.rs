@@ -0,0 +1,127 @@
+use crate::dateparse::parse_local_datetime_with_clock;
+use crate::import::{minute_key, Import, ImportError, ImportedEntry, SkippedRecord};
+use chrono::{DateTime, TimeZone, Utc};
+use regex::Regex;
+
+/// Matches lines like `2019-03-04`, `2019-03-04 09:15` and `## 2019-03-04`.
+pub const DEFAULT_SPLIT_REGEX: &str =
+    r"^(?:#+[ \t]*)?(\d{4}-\d{2}-\d{2}(?:[ T]\d{1,2}:\d{2})?)[ \t]*$";
+
+/// Splits a text file into entries at the lines `split_regex` matches. The
+/// date of an entry is the text of the `date` group of the match, or the
+/// first group, or the whole match, parsed like `ddiary add --date` in `tz`;
+/// times without a date are on the day `clock` returns. The rest of the
+/// heading line after the match starts the entry.
+pub fn split_text<Tz, C>(
+    input: &str,
+    split_regex: &str,
+    tz: &Tz,
+    clock: C,
+) -> Result<Import, ImportError>
+where
+    Tz: TimeZone,
+    C: Fn() -> DateTime<Tz>,
+{
+    let regex = Regex::new(split_regex)?;
+    let mut import = Import::default();
+    let mut current: Option<Section> = None;
+    for (i, line) in input.lines().enumerate() {
+        if let Some((date, rest)) = split_heading(&regex, line) {
+            if let Some(section) = current.take() {
+                add_entry(&mut import, section);
+            }
+            current = Some(Section {
+                line_number: i + 1,
+                date: date.to_string(),
+                dt: parse_local_datetime_with_clock(date, tz, &clock)
+                    .map(|dt| dt.with_timezone(&Utc)),
+                lines: vec![rest],
+            });
+        } else if let Some(section) = current.as_mut() {
+            section.lines.push(line);
+        } else if !line.trim().is_empty() {
+            import.skipped.push(SkippedRecord {
+                record: format!("line {}", i + 1),
+                reason: "Text before the first entry".to_string(),
+            });
+        }
+    }
+    if let Some(section) = current {
+        add_entry(&mut import, section);
+    }
+    Ok(import)
+}
+
+/// The lines from a heading to the next one.
+struct Section<'a> {
+    line_number: usize,
+    /// The date text of the heading, and what it was parsed to.
+    date: String,
+    dt: Option<DateTime<Utc>>,
+    lines: Vec<&'a str>,
+}
+
+/// Splits a heading line into the date text and the rest of the line, or
+/// returns None if `line` isn't a heading.
+fn split_heading<'a>(regex: &Regex, line: &'a str) -> Option<(&'a str, &'a str)> {
+    let captures = regex.captures(line)?;
+    let whole = captures.get(0)?;
+    let date = captures
+        .name("date")
+        .or_else(|| captures.get(1))
+        .unwrap_or(whole);
+    Some((date.as_str().trim(), line[whole.end()..].trim()))
+}
+
+fn add_entry(import: &mut Import, section: Section) {
+    let record = format!("line {}", section.line_number);
+    let dt = match section.dt {
+        Some(dt) => dt,
+        None => {
+            import.skipped.push(SkippedRecord {
+                record,
+                reason: format!("Invalid date {}", section.date),
+            });
+            return;
+        }
+    };
+    let text = section.lines.join("\n");
+    let text = text.trim();
+    if text.is_empty() {
+        import.skipped.push(SkippedRecord {
+            record,
+            reason: "Empty entry".to_string(),
+        });
+        return;
+    }
+    import.entries.push(ImportedEntry {
+        key: minute_key(dt),
+        text: text.to_string(),
+        attachments: Vec::new(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_heading() {
+        let regex = Regex::new(DEFAULT_SPLIT_REGEX).unwrap();
+        assert_eq!(
+            Some(("2019-03-04", "")),
+            split_heading(&regex, "## 2019-03-04")
+        );
+        assert_eq!(
+            Some(("2019-03-04 09:15", "")),
+            split_heading(&regex, "2019-03-04 09:15 ")
+        );
+        assert_eq!(None, split_heading(&regex, "On 2019-03-04 we left."));
+
+        let regex = Regex::new(r"^\[(?P<date>[^\]]+)\]").unwrap();
+        assert_eq!(
+            Some(("9:15am", "Title")),
+            split_heading(&regex, "[9:15am] Title")
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use crate::index::tags::TagPair;
+use std::collections::BTreeMap;
+
+/// Formats the tag co-occurrence graph in Graphviz DOT. Nodes are labelled
+/// with the number of entries using the tag and edges with the number of
+/// entries the tags share.
+pub fn cooccurrence_graph(pairs: &[TagPair]) -> String {
+    let mut nodes: BTreeMap<&str, usize> = BTreeMap::new();
+    for pair in pairs {
+        nodes.insert(&pair.first.tag, pair.first.count);
+        nodes.insert(&pair.second.tag, pair.second.count);
+    }
+    let mut dot = String::from("graph tags {\n");
+    for (tag, count) in nodes {
+        dot.push_str(&format!(
+            "    {} [label={}];\n",
+            quote(tag),
+            quote(&format!("{} ({})", tag, count))
+        ));
+    }
+    for pair in pairs {
+        dot.push_str(&format!(
+            "    {} -- {} [weight={}, label=\"{}\"];\n",
+            quote(&pair.first.tag),
+            quote(&pair.second.tag),
+            pair.count,
+            pair.count
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::tags::TagCount;
+
+    fn count(tag: &str, count: usize) -> TagCount {
+        TagCount {
+            tag: tag.to_string(),
+            count,
+            aliases: vec![],
+        }
+    }
+
+    #[test]
+    fn cooccurrence_graph_lists_nodes_and_edges() {
+        let pairs = vec![TagPair {
+            first: count("say \"hi\"", 2),
+            second: count("work", 3),
+            count: 1,
+        }];
+        assert_eq!(
+            cooccurrence_graph(&pairs),
+            "graph tags {
+    \"say \\\"hi\\\"\" [label=\"say \\\"hi\\\" (2)\"];
+    \"work\" [label=\"work (3)\"];
+    \"say \\\"hi\\\"\" -- \"work\" [weight=1, label=\"1\"];
+}
+"
+        );
+    }
+}
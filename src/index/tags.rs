@@ -1,25 +1,56 @@
+use crate::tagparser::normalize_tag;
 use crate::DiaryEntryKey;
 use chrono::{DateTime, Utc};
-use rusqlite::{params, Connection, NO_PARAMS};
+use rusqlite::{params, Connection, OptionalExtension, NO_PARAMS};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+    /// Aliases of the tag that appear in entries.
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelatedTag {
+    pub tag: String,
+    /// The number of entries that have both tags.
+    pub count: usize,
+    /// How much more often the tags appear together than they would if they
+    /// were independent. Values above 1 mean the tags go together.
+    pub lift: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagPair {
+    pub first: TagCount,
+    pub second: TagCount,
+    /// The number of entries that have both tags.
+    pub count: usize,
+}
 
 #[derive(Debug)]
 pub struct TagIndex {
-    root: PathBuf,
-    conn: Connection,
+    conn: Rc<Connection>,
 }
 
 pub type TagIndexResult<T> = Result<T, TagIndexError>;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum TagIndexError {
     DBError(rusqlite::Error),
     BadPathError(PathBuf),
     IoError(io::Error),
     IndexFormatError(String),
+    InvalidAlias(String),
 }
 
 impl fmt::Display for TagIndexError {
@@ -31,6 +62,7 @@ impl fmt::Display for TagIndexError {
             }
             TagIndexError::IoError(e) => write!(f, "I/O error: {}", e),
             TagIndexError::IndexFormatError(s) => write!(f, "Tag index error: {}", s),
+            TagIndexError::InvalidAlias(s) => write!(f, "Invalid alias: {}", s),
         }
     }
 }
@@ -62,15 +94,50 @@ impl TagIndex {
             if !root_exists {
                 fs::create_dir_all(root)?;
             }
-            let index_path = root.join(PathBuf::from("index.sqlite"));
+            let index_path = root.join(INDEX_FILE_NAME);
             let conn = Connection::open(&index_path)?;
             TagIndexResult::Ok(TagIndex {
-                root: root.to_path_buf(),
-                conn,
+                conn: Rc::new(conn),
             })
         }
     }
 
+    /// Deletes the index file in `root`, if there is one.
+    pub fn remove(root: &Path) -> TagIndexResult<()> {
+        match fs::remove_file(root.join(INDEX_FILE_NAME)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(TagIndexError::IoError(e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Creates an index that's kept in memory. The index lives as long as
+    /// this `TagIndex` or any `TagIndex` made from it with `share`.
+    pub fn in_memory() -> TagIndexResult<TagIndex> {
+        let conn = Connection::open_in_memory()?;
+        Ok(TagIndex {
+            conn: Rc::new(conn),
+        })
+    }
+
+    /// Opens the index in the database file `path`.
+    pub(crate) fn open_file(path: &Path) -> TagIndexResult<TagIndex> {
+        let conn = Connection::open(path)?;
+        Ok(TagIndex {
+            conn: Rc::new(conn),
+        })
+    }
+
+    pub(crate) fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Returns a `TagIndex` that uses the same database connection.
+    pub fn share(&self) -> TagIndex {
+        TagIndex {
+            conn: Rc::clone(&self.conn),
+        }
+    }
+
     pub fn initdb(&self) -> TagIndexResult<()> {
         self.conn.execute(
             "
@@ -82,6 +149,67 @@ impl TagIndex {
             ",
             params![],
         )?;
+        self.migrate()
+    }
+
+    fn migrate(&self) -> TagIndexResult<()> {
+        let version: i64 = self
+            .conn
+            .query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+        if version < 1 {
+            self.in_transaction(|| {
+                self.conn.execute(
+                    "ALTER TABLE tag ADD COLUMN normalized TEXT NOT NULL DEFAULT ''",
+                    NO_PARAMS,
+                )?;
+                let mut select = self.conn.prepare("SELECT DISTINCT tag FROM tag")?;
+                let tags = select
+                    .query_map(NO_PARAMS, |row| row.get(0))?
+                    .collect::<Result<Vec<String>, _>>()?;
+                let mut update = self
+                    .conn
+                    .prepare("UPDATE tag SET normalized = ? WHERE tag = ?")?;
+                for tag in tags {
+                    update.execute(&[&normalize_tag(&tag), &tag])?;
+                }
+                self.conn.execute(
+                    "CREATE INDEX IF NOT EXISTS tag_normalized ON tag (normalized)",
+                    NO_PARAMS,
+                )?;
+                self.conn.execute("PRAGMA user_version = 1", NO_PARAMS)?;
+                Ok(())
+            })?;
+        }
+        if version < 2 {
+            self.in_transaction(|| {
+                self.conn.execute(
+                    "
+                    CREATE TABLE IF NOT EXISTS tag_alias (
+                        alias       TEXT NOT NULL PRIMARY KEY,
+                        canonical   TEXT NOT NULL
+                    )
+                    ",
+                    NO_PARAMS,
+                )?;
+                self.conn.execute("PRAGMA user_version = 2", NO_PARAMS)?;
+                Ok(())
+            })?;
+        }
+        if version < 3 {
+            self.in_transaction(|| {
+                self.conn.execute(
+                    "
+                    CREATE TABLE IF NOT EXISTS entry_stats (
+                        entry_key   TEXT NOT NULL PRIMARY KEY,
+                        words       INTEGER NOT NULL
+                    )
+                    ",
+                    NO_PARAMS,
+                )?;
+                self.conn.execute("PRAGMA user_version = 3", NO_PARAMS)?;
+                Ok(())
+            })?;
+        }
         Ok(())
     }
 
@@ -91,7 +219,7 @@ impl TagIndex {
             self.conn.execute(DELETE_TAG_STATEMENT, &[&db_key])?;
             let mut stmt = self.conn.prepare(INSERT_TAG_STATEMENT)?;
             for tag in tags {
-                stmt.execute(&[tag, &db_key])?;
+                stmt.execute(&[tag, &normalize_tag(tag), &db_key])?;
             }
             Ok(())
         })?;
@@ -105,7 +233,7 @@ impl TagIndex {
             for (key, tags) in keys_tags {
                 let db_key = entry_key_to_db_key(key);
                 for tag in tags.iter() {
-                    insert_stmt.execute(&[tag, &db_key])?;
+                    insert_stmt.execute(&[tag, &normalize_tag(tag), &db_key])?;
                 }
             }
             Ok(())
@@ -116,45 +244,383 @@ impl TagIndex {
         if tags.is_empty() {
             return Ok(vec![]);
         }
-        let placeholders = make_placeholders(tags.len());
+        let normalized = self.expand_aliases(tags)?;
+        let placeholders = make_placeholders(normalized.len());
         let select = format!(
-            "SELECT entry_key FROM tag WHERE tag IN ({}) ORDER BY entry_key",
+            "SELECT DISTINCT entry_key FROM tag WHERE normalized IN ({}) ORDER BY entry_key",
             placeholders
         );
         let mut stmt = self.conn.prepare(&select)?;
-        let rows = stmt.query_map(tags, |row| row.get(0))?;
+        let rows = stmt.query_map(&normalized, |row| row.get(0))?;
         let mut keys: Vec<DiaryEntryKey> = Vec::new();
         for key_result in rows {
             let key_str: String = key_result?;
-            let key =
-                DateTime::parse_from_str(&key_str, KEY_DB_FORMAT).map(|date| DiaryEntryKey {
-                    date: date.with_timezone(&Utc),
-                })?;
-            keys.push(key);
+            keys.push(db_key_to_entry_key(&key_str)?);
         }
         Ok(keys)
     }
 
-    fn in_transaction<F>(&self, mut f: F) -> TagIndexResult<()>
+    /// Removes the tags and statistics of the entry for `key`.
+    pub fn remove_entry(&self, key: &DiaryEntryKey) -> TagIndexResult<()> {
+        let db_key = entry_key_to_db_key(key);
+        self.in_transaction(|| {
+            self.conn.execute(DELETE_TAG_STATEMENT, &[&db_key])?;
+            self.conn
+                .execute("DELETE FROM entry_stats WHERE entry_key = ?", &[&db_key])?;
+            Ok(())
+        })
+    }
+
+    /// Returns the tags indexed for the entry for `key`, sorted.
+    pub fn tags_for_entry(&self, key: &DiaryEntryKey) -> TagIndexResult<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tag FROM tag WHERE entry_key = ? ORDER BY tag")?;
+        let tags = stmt
+            .query_map(&[&entry_key_to_db_key(key)], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(tags)
+    }
+
+    /// Lists the keys of all entries that have tags or statistics in the
+    /// index.
+    pub fn indexed_keys(&self) -> TagIndexResult<Vec<DiaryEntryKey>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT entry_key FROM tag UNION SELECT entry_key FROM entry_stats ORDER BY entry_key",
+        )?;
+        let rows = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
+        let mut keys = Vec::new();
+        for row in rows {
+            let key_str: String = row?;
+            keys.push(db_key_to_entry_key(&key_str)?);
+        }
+        Ok(keys)
+    }
+
+    pub fn set_word_count(&self, key: &DiaryEntryKey, words: usize) -> TagIndexResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO entry_stats (entry_key, words) VALUES (?, ?)",
+            params![entry_key_to_db_key(key), words as i64],
+        )?;
+        Ok(())
+    }
+
+    pub fn recreate_word_counts(
+        &self,
+        keys_words: &[(DiaryEntryKey, usize)],
+    ) -> TagIndexResult<()> {
+        self.in_transaction(|| {
+            self.conn.execute("DELETE FROM entry_stats", NO_PARAMS)?;
+            for (key, words) in keys_words {
+                self.set_word_count(key, *words)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the cached word counts of entries.
+    pub fn word_counts(&self) -> TagIndexResult<HashMap<DiaryEntryKey, usize>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT entry_key, words FROM entry_stats")?;
+        let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut counts = HashMap::new();
+        for row in rows {
+            let (key_str, words): (String, i64) = row?;
+            counts.insert(db_key_to_entry_key(&key_str)?, words as usize);
+        }
+        Ok(counts)
+    }
+
+    /// Lists tags with the number of entries they appear in, most used first.
+    /// Tags that differ only by case or Unicode composition are counted
+    /// together and shown with their most common spelling, and aliases are
+    /// counted under their canonical tag.
+    pub fn list_tags(&self) -> TagIndexResult<Vec<TagCount>> {
+        self.list_tags_between(None, None)
+    }
+
+    /// Like `list_tags`, but only counts entries between `from` and `to`,
+    /// inclusive.
+    pub fn list_tags_between(
+        &self,
+        from: Option<&DiaryEntryKey>,
+        to: Option<&DiaryEntryKey>,
+    ) -> TagIndexResult<Vec<TagCount>> {
+        let mut counts: Vec<TagCount> = self.tag_groups(from, to)?.into_values().collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+        Ok(counts)
+    }
+
+    /// Lists the tags that appear in the same entries as `tag`, with the
+    /// number of shared entries and their lift, most common first. The lift
+    /// is computed against `entry_total`, the number of entries in the diary,
+    /// including the entries without tags.
+    pub fn related_tags(&self, tag: &str, entry_total: usize) -> TagIndexResult<Vec<RelatedTag>> {
+        let canonical = self.resolve_alias(&normalize_tag(tag))?;
+        let groups = self.tag_groups(None, None)?;
+        let tag_entries = match groups.get(&canonical) {
+            Some(count) => count.count,
+            None => return Ok(vec![]),
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "
+            WITH grouped AS ({}),
+            entries AS (SELECT DISTINCT canonical, entry_key FROM grouped)
+            SELECT b.canonical, COUNT(*)
+            FROM entries a JOIN entries b
+                ON a.entry_key = b.entry_key AND a.canonical != b.canonical
+            WHERE a.canonical = ?
+            GROUP BY b.canonical
+            ",
+            CANONICAL_TAGS_QUERY
+        ))?;
+        let rows = stmt.query_map(&[&canonical], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut related: Vec<RelatedTag> = Vec::new();
+        for row in rows {
+            let (other, count): (String, i64) = row?;
+            let other_count = &groups[&other];
+            related.push(RelatedTag {
+                tag: other_count.tag.clone(),
+                count: count as usize,
+                lift: lift(count as usize, tag_entries, other_count.count, entry_total),
+            });
+        }
+        related.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| b.lift.partial_cmp(&a.lift).unwrap_or(Ordering::Equal))
+                .then_with(|| a.tag.cmp(&b.tag))
+        });
+        Ok(related)
+    }
+
+    /// Lists every pair of tags that appear together in at least one entry.
+    pub fn cooccurrence(&self) -> TagIndexResult<Vec<TagPair>> {
+        let groups = self.tag_groups(None, None)?;
+        let mut stmt = self.conn.prepare(&format!(
+            "
+            WITH grouped AS ({}),
+            entries AS (SELECT DISTINCT canonical, entry_key FROM grouped)
+            SELECT a.canonical, b.canonical, COUNT(*)
+            FROM entries a JOIN entries b
+                ON a.entry_key = b.entry_key AND a.canonical < b.canonical
+            GROUP BY a.canonical, b.canonical
+            ORDER BY a.canonical, b.canonical
+            ",
+            CANONICAL_TAGS_QUERY
+        ))?;
+        let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        let mut pairs: Vec<TagPair> = Vec::new();
+        for row in rows {
+            let (a, b, count): (String, String, i64) = row?;
+            pairs.push(TagPair {
+                first: groups[&a].clone(),
+                second: groups[&b].clone(),
+                count: count as usize,
+            });
+        }
+        Ok(pairs)
+    }
+
+    /// Returns the tag counts keyed by the canonical tag of each group.
+    fn tag_groups(
+        &self,
+        from: Option<&DiaryEntryKey>,
+        to: Option<&DiaryEntryKey>,
+    ) -> TagIndexResult<HashMap<String, TagCount>> {
+        let from = from.map(entry_key_to_db_key).unwrap_or_default();
+        let to = to
+            .map(entry_key_to_db_key)
+            .unwrap_or_else(|| "~".to_string());
+        let mut stmt = self.conn.prepare(&format!(
+            "
+            WITH grouped AS ({} WHERE t.entry_key BETWEEN ? AND ?)
+            SELECT g.canonical, g.normalized, g.tag, COUNT(*), n.entries
+            FROM grouped g JOIN (
+                SELECT canonical, COUNT(DISTINCT entry_key) AS entries
+                FROM grouped GROUP BY canonical
+            ) n USING (canonical)
+            GROUP BY g.canonical, g.normalized, g.tag
+            ORDER BY g.canonical, g.normalized, g.tag
+            ",
+            CANONICAL_TAGS_QUERY
+        ))?;
+        let rows = stmt.query_map(&[&from, &to], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })?;
+        let mut groups: HashMap<String, TagCount> = HashMap::new();
+        let mut best_spelling_counts: HashMap<String, i64> = HashMap::new();
+        for row in rows {
+            let (canonical, normalized, tag, spelling_count, entries): (
+                String,
+                String,
+                String,
+                i64,
+                i64,
+            ) = row?;
+            let group = groups.entry(canonical.clone()).or_insert_with(|| TagCount {
+                tag: canonical.clone(),
+                count: entries as usize,
+                aliases: vec![],
+            });
+            if normalized != canonical {
+                if group.aliases.last() != Some(&normalized) {
+                    group.aliases.push(normalized);
+                }
+            } else {
+                let best = best_spelling_counts.entry(canonical).or_insert(0);
+                if spelling_count > *best {
+                    group.tag = tag;
+                    *best = spelling_count;
+                }
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Makes `alias` an alias of `canonical`, so that searching for either of
+    /// them finds entries tagged with any tag in the group.
+    pub fn add_alias(&self, alias: &str, canonical: &str) -> TagIndexResult<()> {
+        let alias = normalize_tag(alias);
+        let canonical = self.resolve_alias(&normalize_tag(canonical))?;
+        if alias == canonical {
+            return Err(TagIndexError::InvalidAlias(format!(
+                "{} can't be an alias of itself",
+                alias
+            )));
+        }
+        self.in_transaction(|| {
+            self.conn.execute(
+                "UPDATE tag_alias SET canonical = ? WHERE canonical = ?",
+                &[&canonical, &alias],
+            )?;
+            self.conn.execute(
+                "INSERT OR REPLACE INTO tag_alias (alias, canonical) VALUES (?, ?)",
+                &[&alias, &canonical],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Lists aliases with their canonical tags.
+    pub fn aliases(&self) -> TagIndexResult<Vec<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT alias, canonical FROM tag_alias ORDER BY alias")?;
+        let aliases = stmt
+            .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(String, String)>, _>>()?;
+        Ok(aliases)
+    }
+
+    /// Removes `alias` from its group. Returns false if it wasn't an alias.
+    pub fn remove_alias(&self, alias: &str) -> TagIndexResult<bool> {
+        let removed = self.conn.execute(
+            "DELETE FROM tag_alias WHERE alias = ?",
+            &[&normalize_tag(alias)],
+        )?;
+        Ok(removed > 0)
+    }
+
+    fn resolve_alias(&self, normalized: &str) -> TagIndexResult<String> {
+        let canonical = self
+            .conn
+            .query_row(
+                "SELECT canonical FROM tag_alias WHERE alias = ?",
+                &[normalized],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(canonical.unwrap_or_else(|| normalized.to_string()))
+    }
+
+    fn expand_aliases(&self, tags: &[&str]) -> TagIndexResult<Vec<String>> {
+        let mut expanded: Vec<String> = Vec::new();
+        let mut stmt = self
+            .conn
+            .prepare("SELECT alias FROM tag_alias WHERE canonical = ?")?;
+        for tag in tags {
+            let canonical = self.resolve_alias(&normalize_tag(tag))?;
+            let aliases = stmt
+                .query_map(&[&canonical], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            expanded.push(canonical);
+            expanded.extend(aliases);
+        }
+        expanded.sort();
+        expanded.dedup();
+        Ok(expanded)
+    }
+
+    /// Runs `f` in a transaction that's committed if `f` succeeds and rolled
+    /// back if it fails. Transactions can be nested.
+    pub fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
     where
-        F: FnMut() -> TagIndexResult<()>,
+        F: FnOnce() -> Result<T, E>,
+        E: From<TagIndexError>,
     {
-        self.conn.execute("BEGIN TRANSACTION", params![])?;
-        f()?;
-        self.conn.execute("COMMIT", params![])?;
-        Ok(())
+        self.conn
+            .execute_batch("SAVEPOINT tag_index")
+            .map_err(TagIndexError::from)?;
+        match f() {
+            Ok(value) => {
+                self.conn
+                    .execute_batch("RELEASE tag_index")
+                    .map_err(TagIndexError::from)?;
+                Ok(value)
+            }
+            Err(err) => {
+                // The error from f is more interesting than a failed rollback.
+                let _ = self
+                    .conn
+                    .execute_batch("ROLLBACK TO tag_index; RELEASE tag_index");
+                Err(err)
+            }
+        }
     }
+
+    fn in_transaction<F>(&self, f: F) -> TagIndexResult<()>
+    where
+        F: FnOnce() -> TagIndexResult<()>,
+    {
+        self.transaction(f)
+    }
+}
+
+fn lift(both: usize, first: usize, second: usize, total: usize) -> f64 {
+    (both as f64 * total as f64) / (first as f64 * second as f64)
 }
 
-fn entry_key_to_db_key(key: &DiaryEntryKey) -> String {
+pub(crate) fn entry_key_to_db_key(key: &DiaryEntryKey) -> String {
     key.date.format(KEY_DB_FORMAT).to_string()
 }
 
+fn db_key_to_entry_key(key_str: &str) -> TagIndexResult<DiaryEntryKey> {
+    let key = DateTime::parse_from_str(key_str, KEY_DB_FORMAT).map(|date| DiaryEntryKey {
+        date: date.with_timezone(&Utc),
+    })?;
+    Ok(key)
+}
+
 fn make_placeholders(times: usize) -> String {
     assert_ne!(times, 0);
     format!("?{}", ", ?".repeat(times - 1))
 }
 
-static KEY_DB_FORMAT: &str = "%Y%m%dT%H%M%z";
+/// Tag rows with the canonical tag of their alias group, or their own
+/// normalized form if they're not an alias.
+static CANONICAL_TAGS_QUERY: &str = "
+    SELECT COALESCE(a.canonical, t.normalized) AS canonical, t.normalized, t.tag, t.entry_key
+    FROM tag t LEFT JOIN tag_alias a ON a.alias = t.normalized
+";
+pub(crate) static INDEX_FILE_NAME: &str = "index.sqlite";
+pub(crate) static KEY_DB_FORMAT: &str = "%Y%m%dT%H%M%z";
 static DELETE_TAG_STATEMENT: &str = "DELETE FROM tag WHERE entry_key = ?";
-static INSERT_TAG_STATEMENT: &str = "INSERT INTO tag (tag, entry_key) VALUES (?, ?)";
+static INSERT_TAG_STATEMENT: &str = "INSERT INTO tag (tag, normalized, entry_key) VALUES (?, ?, ?)";
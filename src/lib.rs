@@ -1,9 +1,49 @@
+mod check;
+mod config;
+mod crypto;
+mod dateparse;
 mod diary;
 mod diaryentrykey;
+mod encoding;
+mod export;
 mod filerepo;
+mod git;
+mod history;
+mod import;
 mod index;
+mod lock;
+mod stats;
+mod storage;
+mod sync;
 mod tagparser;
+mod watch;
 
-pub use diary::{Diary, MatchingDateBehavior};
+pub use check::{Finding, Problem};
+pub use config::{ConfigError, StorageFormat};
+pub use crypto::CryptoError;
+pub use dateparse::{parse_local_datetime_with_clock, parse_range_end_with_clock, RangeEnd};
+pub use diary::{Diary, DiaryError, MatchingDateBehavior};
 pub use diaryentrykey::DiaryEntryKey;
-pub use index::tags::TagIndex;
+pub use export::jrnl::jrnl_document;
+pub use export::markdown::{markdown_document, text_document};
+pub use export::{ExportError, ExportFormat, ExportOptions, ExportedEntry};
+pub use filerepo::layout::{DirectoryScheme, Extension, Layout};
+pub use filerepo::tree::{FileRepoError, FileRepoResult, Tree};
+pub use git::Change;
+pub use history::{EntryVersion, History, HistoryError, HistoryResult};
+pub use import::dayone::read_dayone;
+pub use import::jrnl::parse_jrnl;
+pub use import::markdown::{read_markdown_notes, MarkdownOptions};
+pub use import::text::{split_text, DEFAULT_SPLIT_REGEX};
+pub use import::{
+    Import, ImportError, ImportReport, ImportedAttachment, ImportedEntry, SkippedRecord,
+};
+pub use index::tags::{RelatedTag, TagCount, TagIndex, TagPair};
+pub use lock::LockError;
+pub use stats::DiaryStats;
+pub use storage::memory::MemoryStorage;
+pub use storage::sqlite::SqliteStorage;
+pub use storage::{EntryFiles, EntryStat, Storage, StorageError, StorageResult, StrayFile};
+pub use sync::{Conflict, Resolution, SyncReport};
+pub use tagparser::{normalize_tag, parse_spans, Span, SpanKind};
+pub use watch::WatchEvent;
@@ -0,0 +1,82 @@
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// An advisory lock on a diary directory, held while writing to the diary.
+/// The lock is released when this is dropped.
+#[derive(Debug)]
+pub struct DiaryLock {
+    _file: File,
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    /// Another process held the lock for longer than we were willing to wait.
+    Locked(PathBuf),
+    IoError(io::Error),
+}
+
+impl From<io::Error> for LockError {
+    fn from(error: io::Error) -> Self {
+        LockError::IoError(error)
+    }
+}
+
+impl DiaryLock {
+    /// Takes the lock in the directory `dir`. If another process holds it,
+    /// retries until `timeout` has passed.
+    pub fn acquire(dir: &Path, timeout: Duration) -> Result<DiaryLock, LockError> {
+        let path = dir.join(LOCK_FILE_NAME);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(DiaryLock { _file: file }),
+                Err(e) if is_contended(&e) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(LockError::Locked(path));
+                    }
+                    thread::sleep(RETRY_INTERVAL.min(deadline - now));
+                }
+                Err(e) => return Err(LockError::IoError(e)),
+            }
+        }
+    }
+}
+
+fn is_contended(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::WouldBlock
+        || error.raw_os_error() == fs2::lock_contended_error().raw_os_error()
+}
+
+pub static LOCK_FILE_NAME: &str = ".lock";
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn second_lock_times_out() {
+        let dir = tempdir().unwrap();
+        let _lock = DiaryLock::acquire(dir.path(), Duration::from_secs(0)).unwrap();
+        let second = DiaryLock::acquire(dir.path(), Duration::from_millis(150));
+        assert!(matches!(second, Err(LockError::Locked(_))));
+    }
+
+    #[test]
+    fn lock_is_released_on_drop() {
+        let dir = tempdir().unwrap();
+        drop(DiaryLock::acquire(dir.path(), Duration::from_secs(0)).unwrap());
+        assert!(DiaryLock::acquire(dir.path(), Duration::from_secs(0)).is_ok());
+    }
+}
@@ -0,0 +1,50 @@
+use std::env;
+use std::io;
+use std::process::Command;
+
+/// Reads the passphrase of an encrypted diary. It's taken from the
+/// environment variable `DDIARY_PASSPHRASE`, or from the output of the
+/// command in `DDIARY_PASSPHRASE_COMMAND` (e.g. a password manager or an
+/// agent), and asked for if neither is set.
+pub fn read_passphrase(prompt: &str) -> io::Result<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+    if let Ok(command) = env::var(PASSPHRASE_COMMAND_VAR) {
+        return run_passphrase_command(&command);
+    }
+    rpassword::prompt_password(prompt)
+}
+
+/// Reads a new passphrase, asking for it twice if it's read from the
+/// terminal.
+pub fn read_new_passphrase() -> io::Result<String> {
+    if env::var_os(PASSPHRASE_VAR).is_some() || env::var_os(PASSPHRASE_COMMAND_VAR).is_some() {
+        return read_passphrase("");
+    }
+    let passphrase = rpassword::prompt_password("New passphrase: ")?;
+    let repeated = rpassword::prompt_password("Repeat passphrase: ")?;
+    if passphrase != repeated {
+        Err(io::Error::other("Passphrases don't match"))
+    } else if passphrase.is_empty() {
+        Err(io::Error::other("Passphrase is empty"))
+    } else {
+        Ok(passphrase)
+    }
+}
+
+fn run_passphrase_command(command: &str) -> io::Result<String> {
+    let output = Command::new("sh").arg("-c").arg(command).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} failed: {}",
+            PASSPHRASE_COMMAND_VAR, output.status
+        )));
+    }
+    let passphrase = String::from_utf8(output.stdout)
+        .map_err(|_| io::Error::other("Passphrase is not valid UTF-8"))?;
+    Ok(passphrase.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+static PASSPHRASE_VAR: &str = "DDIARY_PASSPHRASE";
+static PASSPHRASE_COMMAND_VAR: &str = "DDIARY_PASSPHRASE_COMMAND";
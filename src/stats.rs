@@ -0,0 +1,116 @@
+use crate::diaryentrykey::DiaryEntryKey;
+use crate::index::tags::TagCount;
+use chrono::{Datelike, NaiveDate, TimeZone};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiaryStats {
+    pub entries: usize,
+    pub words: usize,
+    /// Average number of words per entry.
+    pub average_words: f64,
+    /// Number of entries per (year, month).
+    pub entries_per_month: BTreeMap<(i32, u32), usize>,
+    /// Number of entries per weekday, starting from Monday.
+    pub entries_per_weekday: [usize; 7],
+    /// Consecutive days with entries, ending today or yesterday.
+    pub current_streak: usize,
+    pub longest_streak: usize,
+    /// Tags used in the entries, most used first.
+    pub tags: Vec<TagCount>,
+}
+
+pub fn count_words(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+pub fn collect_stats<Tz: TimeZone>(
+    entries: &[(DiaryEntryKey, usize)],
+    tags: Vec<TagCount>,
+    today: NaiveDate,
+    tz: &Tz,
+) -> DiaryStats {
+    let words: usize = entries.iter().map(|(_, w)| w).sum();
+    let dates: Vec<NaiveDate> = entries
+        .iter()
+        .map(|(key, _)| key.date.with_timezone(tz).date_naive())
+        .collect();
+    let mut entries_per_month = BTreeMap::new();
+    let mut entries_per_weekday = [0; 7];
+    for date in &dates {
+        *entries_per_month
+            .entry((date.year(), date.month()))
+            .or_insert(0) += 1;
+        entries_per_weekday[date.weekday().num_days_from_monday() as usize] += 1;
+    }
+    let (current_streak, longest_streak) = streaks(&dates, today);
+    DiaryStats {
+        entries: entries.len(),
+        words,
+        average_words: if entries.is_empty() {
+            0.0
+        } else {
+            words as f64 / entries.len() as f64
+        },
+        entries_per_month,
+        entries_per_weekday,
+        current_streak,
+        longest_streak,
+        tags,
+    }
+}
+
+/// Returns the current and the longest run of consecutive days in `dates`.
+/// The current streak counts if it ends today or yesterday.
+fn streaks(dates: &[NaiveDate], today: NaiveDate) -> (usize, usize) {
+    let mut days = dates.to_vec();
+    days.sort_unstable();
+    days.dedup();
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for day in &days {
+        run = match previous {
+            Some(p) if p.succ_opt() == Some(*day) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(*day);
+    }
+    let current = match previous {
+        Some(last) if last == today || last.succ_opt() == Some(today) => run,
+        _ => 0,
+    };
+    (current, longest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn count_words_splits_on_whitespace() {
+        assert_eq!(count_words("one two\n\nthree  #four"), 4);
+        assert_eq!(count_words(""), 0);
+    }
+
+    #[test]
+    fn streaks_finds_current_and_longest() {
+        let dates = vec![
+            date(2020, 8, 1),
+            date(2020, 8, 2),
+            date(2020, 8, 3),
+            date(2020, 8, 3),
+            date(2020, 8, 10),
+            date(2020, 8, 11),
+        ];
+        assert_eq!(streaks(&dates, date(2020, 8, 12)), (2, 3));
+        assert_eq!(streaks(&dates, date(2020, 8, 11)), (2, 3));
+        assert_eq!(streaks(&dates, date(2020, 8, 13)), (0, 3));
+        assert_eq!(streaks(&[], date(2020, 8, 13)), (0, 0));
+    }
+}
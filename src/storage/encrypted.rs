@@ -0,0 +1,77 @@
+use crate::crypto::Cipher;
+use crate::storage::{EntryFiles, EntryStat, Storage, StorageError, StorageResult};
+use chrono::{DateTime, Utc};
+
+/// Storage that encrypts entries before handing them to another storage.
+#[derive(Debug)]
+pub struct EncryptedStorage<S> {
+    inner: S,
+    cipher: Cipher,
+}
+
+impl<S: Storage> EncryptedStorage<S> {
+    pub fn new(inner: S, cipher: Cipher) -> EncryptedStorage<S> {
+        EncryptedStorage { inner, cipher }
+    }
+}
+
+impl<S: Storage> Storage for EncryptedStorage<S> {
+    fn list(&self) -> StorageResult<Vec<DateTime<Utc>>> {
+        self.inner.list()
+    }
+
+    fn get(&self, dt: &DateTime<Utc>) -> StorageResult<String> {
+        let text = self.inner.get(dt)?;
+        self.cipher
+            .decrypt(&text)
+            .map_err(|e| StorageError::EntryDecryptionError(*dt, e))
+    }
+
+    fn get_bytes(&self, dt: &DateTime<Utc>) -> StorageResult<Vec<u8>> {
+        let data = self.inner.get_bytes(dt)?;
+        self.cipher
+            .decrypt_bytes(&data)
+            .map_err(|e| StorageError::EntryDecryptionError(*dt, e))
+    }
+
+    fn put(&self, dt: &DateTime<Utc>, text: &str) -> StorageResult<()> {
+        self.inner.put(dt, &self.cipher.encrypt(text))
+    }
+
+    fn put_bytes(&self, dt: &DateTime<Utc>, data: &[u8]) -> StorageResult<()> {
+        self.inner.put(dt, &self.cipher.encrypt_bytes(data))
+    }
+
+    fn delete(&self, dt: &DateTime<Utc>) -> StorageResult<()> {
+        self.inner.delete(dt)
+    }
+
+    /// Returns the size of the encrypted text.
+    fn stat(&self, dt: &DateTime<Utc>) -> StorageResult<EntryStat> {
+        self.inner.stat(dt)
+    }
+
+    fn list_attachments(&self, dt: &DateTime<Utc>) -> StorageResult<Vec<String>> {
+        self.inner.list_attachments(dt)
+    }
+
+    fn get_attachment(&self, dt: &DateTime<Utc>, name: &str) -> StorageResult<Vec<u8>> {
+        let data = self.inner.get_attachment(dt, name)?;
+        self.cipher
+            .decrypt_bytes(&data)
+            .map_err(|e| StorageError::EntryDecryptionError(*dt, e))
+    }
+
+    fn put_attachment(&self, dt: &DateTime<Utc>, name: &str, data: &[u8]) -> StorageResult<()> {
+        let encrypted = self.cipher.encrypt_bytes(data);
+        self.inner.put_attachment(dt, name, encrypted.as_bytes())
+    }
+
+    fn attachment_link(&self, dt: &DateTime<Utc>, name: &str) -> String {
+        self.inner.attachment_link(dt, name)
+    }
+
+    fn entry_files(&self) -> Option<&dyn EntryFiles> {
+        self.inner.entry_files()
+    }
+}
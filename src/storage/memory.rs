@@ -0,0 +1,86 @@
+use crate::storage::{EntryStat, Storage, StorageError, StorageResult};
+use chrono::{DateTime, Utc};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// Storage that keeps entries in memory only.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: RefCell<BTreeMap<DateTime<Utc>, String>>,
+    attachments: RefCell<BTreeMap<DateTime<Utc>, Attachments>>,
+}
+
+/// Attachment data by name.
+type Attachments = BTreeMap<String, Vec<u8>>;
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn list(&self) -> StorageResult<Vec<DateTime<Utc>>> {
+        Ok(self.entries.borrow().keys().cloned().collect())
+    }
+
+    fn get(&self, dt: &DateTime<Utc>) -> StorageResult<String> {
+        self.entries
+            .borrow()
+            .get(dt)
+            .cloned()
+            .ok_or(StorageError::EntryNotFound(*dt))
+    }
+
+    fn put(&self, dt: &DateTime<Utc>, text: &str) -> StorageResult<()> {
+        self.entries.borrow_mut().insert(*dt, text.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, dt: &DateTime<Utc>) -> StorageResult<()> {
+        self.attachments.borrow_mut().remove(dt);
+        self.entries
+            .borrow_mut()
+            .remove(dt)
+            .map(|_| ())
+            .ok_or(StorageError::EntryNotFound(*dt))
+    }
+
+    fn stat(&self, dt: &DateTime<Utc>) -> StorageResult<EntryStat> {
+        self.entries
+            .borrow()
+            .get(dt)
+            .map(|text| EntryStat {
+                size: text.len() as u64,
+                modified: None,
+            })
+            .ok_or(StorageError::EntryNotFound(*dt))
+    }
+
+    fn list_attachments(&self, dt: &DateTime<Utc>) -> StorageResult<Vec<String>> {
+        Ok(self
+            .attachments
+            .borrow()
+            .get(dt)
+            .map(|files| files.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn get_attachment(&self, dt: &DateTime<Utc>, name: &str) -> StorageResult<Vec<u8>> {
+        self.attachments
+            .borrow()
+            .get(dt)
+            .and_then(|files| files.get(name))
+            .cloned()
+            .ok_or_else(|| StorageError::AttachmentNotFound(*dt, name.to_string()))
+    }
+
+    fn put_attachment(&self, dt: &DateTime<Utc>, name: &str, data: &[u8]) -> StorageResult<()> {
+        self.attachments
+            .borrow_mut()
+            .entry(*dt)
+            .or_default()
+            .insert(name.to_string(), data.to_vec());
+        Ok(())
+    }
+}
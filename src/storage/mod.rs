@@ -0,0 +1,233 @@
+pub mod encrypted;
+pub mod memory;
+pub mod sqlite;
+
+use crate::crypto::CryptoError;
+use crate::filerepo::tree::{FileRepoError, FileRepoResult};
+use chrono::{DateTime, Utc};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::string;
+use std::time::SystemTime;
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+#[derive(Debug)]
+pub enum StorageError {
+    EntryNotFound(DateTime<Utc>),
+    IoError(io::Error),
+    NameParseError(String, chrono::ParseError),
+    EntryContentDecodingError(string::FromUtf8Error),
+    EntryDecryptionError(DateTime<Utc>, CryptoError),
+    AttachmentNotFound(DateTime<Utc>, String),
+    AttachmentsNotSupported,
+    DatabaseError(rusqlite::Error),
+    /// An error from the files of a `Tree`.
+    FileRepoError(FileRepoError),
+}
+
+impl StorageError {
+    /// Turns `error` from reading or writing the entry for `dt` into
+    /// `EntryNotFound` if the file doesn't exist.
+    pub(crate) fn from_ioerror(error: io::Error, dt: &DateTime<Utc>) -> StorageError {
+        if error.kind() == io::ErrorKind::NotFound {
+            StorageError::EntryNotFound(*dt)
+        } else {
+            StorageError::IoError(error)
+        }
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::EntryNotFound(dt) => write!(f, "Entry for date {} not found", dt),
+            StorageError::IoError(e) => write!(f, "IO Error: {}", e),
+            StorageError::NameParseError(name, e) => {
+                write!(f, "Date parse error with name {}: {}", name, e)
+            }
+            StorageError::EntryContentDecodingError(e) => {
+                write!(f, "Error decoding diary entry content: {}", e)
+            }
+            StorageError::EntryDecryptionError(dt, e) => {
+                write!(f, "Error decrypting entry for date {}: {}", dt, e)
+            }
+            StorageError::AttachmentNotFound(dt, name) => {
+                write!(f, "Attachment {} of entry for date {} not found", name, dt)
+            }
+            StorageError::AttachmentsNotSupported => {
+                write!(f, "Attachments are not supported by this storage")
+            }
+            StorageError::DatabaseError(e) => write!(f, "Database error: {}", e),
+            StorageError::FileRepoError(e) => write!(f, "File repository error: {}", e),
+        }
+    }
+}
+
+impl Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(error: io::Error) -> Self {
+        StorageError::IoError(error)
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(error: rusqlite::Error) -> Self {
+        StorageError::DatabaseError(error)
+    }
+}
+
+impl From<FileRepoError> for StorageError {
+    fn from(error: FileRepoError) -> Self {
+        match error {
+            FileRepoError::IoError(e) => StorageError::IoError(e),
+            e => StorageError::FileRepoError(e),
+        }
+    }
+}
+
+/// A place to keep diary entries in. Entries are identified by their date
+/// with the precision of one minute.
+pub trait Storage: fmt::Debug {
+    /// Lists the dates of all the entries, in no particular order.
+    fn list(&self) -> StorageResult<Vec<DateTime<Utc>>>;
+
+    fn get(&self, dt: &DateTime<Utc>) -> StorageResult<String>;
+
+    /// Returns the text of the entry for `dt` as it's stored, even if it's
+    /// not valid UTF-8.
+    fn get_bytes(&self, dt: &DateTime<Utc>) -> StorageResult<Vec<u8>> {
+        self.get(dt).map(String::into_bytes)
+    }
+
+    /// Stores `text` as the entry for `dt`, replacing any earlier text.
+    fn put(&self, dt: &DateTime<Utc>, text: &str) -> StorageResult<()>;
+
+    /// Stores `data` as the entry for `dt` as it is, even if it's not valid
+    /// UTF-8. Storage that only keeps text fails on anything else.
+    fn put_bytes(&self, dt: &DateTime<Utc>, data: &[u8]) -> StorageResult<()> {
+        let text =
+            String::from_utf8(data.to_vec()).map_err(StorageError::EntryContentDecodingError)?;
+        self.put(dt, &text)
+    }
+
+    fn delete(&self, dt: &DateTime<Utc>) -> StorageResult<()>;
+
+    fn stat(&self, dt: &DateTime<Utc>) -> StorageResult<EntryStat>;
+
+    /// Lists the names of the files attached to the entry for `dt`.
+    /// Storage that doesn't support attachments has none.
+    fn list_attachments(&self, _dt: &DateTime<Utc>) -> StorageResult<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    fn get_attachment(&self, dt: &DateTime<Utc>, name: &str) -> StorageResult<Vec<u8>> {
+        Err(StorageError::AttachmentNotFound(*dt, name.to_string()))
+    }
+
+    /// Stores `data` as the attachment `name` of the entry for `dt`,
+    /// replacing any earlier attachment with the same name. Deleting the
+    /// entry deletes its attachments.
+    fn put_attachment(&self, _dt: &DateTime<Utc>, _name: &str, _data: &[u8]) -> StorageResult<()> {
+        Err(StorageError::AttachmentsNotSupported)
+    }
+
+    /// Returns a link to the attachment `name` that works from the text of
+    /// the entry for `dt`.
+    fn attachment_link(&self, _dt: &DateTime<Utc>, name: &str) -> String {
+        name.to_string()
+    }
+
+    /// Returns the entry files of storage that keeps each entry in a file,
+    /// or None for other storage.
+    fn entry_files(&self) -> Option<&dyn EntryFiles> {
+        None
+    }
+}
+
+/// Storage that keeps each entry in a file of its own, where the layout of
+/// the files can get out of order.
+pub trait EntryFiles {
+    /// Lists files that aren't entries, or are entries in the wrong place
+    /// and so don't show up in `Storage::list`.
+    fn stray_files(&self) -> FileRepoResult<Vec<StrayFile>>;
+
+    /// Moves a misplaced entry file at `path` to where the entry for `dt`
+    /// belongs. Returns false if there's already a file there.
+    fn move_entry_file(&self, path: &Path, dt: &DateTime<Utc>) -> FileRepoResult<bool>;
+
+    /// Returns the date of the entry whose file is at `path`, relative to
+    /// the root of the storage, or None if `path` isn't an entry file in
+    /// its place.
+    fn entry_for_path(&self, path: &Path) -> Option<DateTime<Utc>>;
+}
+
+/// A file found by `Storage::stray_files`. Paths are relative to the root of
+/// the storage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StrayFile {
+    /// A file whose name isn't the name of an entry.
+    Unknown(PathBuf),
+    /// An entry file in the wrong directory.
+    Misplaced(PathBuf, DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryStat {
+    /// Size of the entry text in bytes.
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl<S: Storage + ?Sized> Storage for &S {
+    fn list(&self) -> StorageResult<Vec<DateTime<Utc>>> {
+        (**self).list()
+    }
+
+    fn get(&self, dt: &DateTime<Utc>) -> StorageResult<String> {
+        (**self).get(dt)
+    }
+
+    fn get_bytes(&self, dt: &DateTime<Utc>) -> StorageResult<Vec<u8>> {
+        (**self).get_bytes(dt)
+    }
+
+    fn put(&self, dt: &DateTime<Utc>, text: &str) -> StorageResult<()> {
+        (**self).put(dt, text)
+    }
+
+    fn put_bytes(&self, dt: &DateTime<Utc>, data: &[u8]) -> StorageResult<()> {
+        (**self).put_bytes(dt, data)
+    }
+
+    fn delete(&self, dt: &DateTime<Utc>) -> StorageResult<()> {
+        (**self).delete(dt)
+    }
+
+    fn stat(&self, dt: &DateTime<Utc>) -> StorageResult<EntryStat> {
+        (**self).stat(dt)
+    }
+
+    fn list_attachments(&self, dt: &DateTime<Utc>) -> StorageResult<Vec<String>> {
+        (**self).list_attachments(dt)
+    }
+
+    fn get_attachment(&self, dt: &DateTime<Utc>, name: &str) -> StorageResult<Vec<u8>> {
+        (**self).get_attachment(dt, name)
+    }
+
+    fn put_attachment(&self, dt: &DateTime<Utc>, name: &str, data: &[u8]) -> StorageResult<()> {
+        (**self).put_attachment(dt, name, data)
+    }
+
+    fn attachment_link(&self, dt: &DateTime<Utc>, name: &str) -> String {
+        (**self).attachment_link(dt, name)
+    }
+
+    fn entry_files(&self) -> Option<&dyn EntryFiles> {
+        (**self).entry_files()
+    }
+}
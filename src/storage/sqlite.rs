@@ -0,0 +1,146 @@
+use crate::diaryentrykey::DiaryEntryKey;
+use crate::index::tags::{entry_key_to_db_key, TagIndex, KEY_DB_FORMAT};
+use crate::storage::{EntryStat, Storage, StorageError, StorageResult};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension, NO_PARAMS};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Storage that keeps entries and their attachments in the database of a
+/// `TagIndex`. Writes to the storage and to the index share the connection,
+/// so a transaction of the index covers both.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    index: TagIndex,
+}
+
+impl SqliteStorage {
+    /// Creates the tables for entries in the database of `tag_index`, if
+    /// they're not there yet.
+    pub fn new(tag_index: &TagIndex) -> StorageResult<SqliteStorage> {
+        let index = tag_index.share();
+        index.connection().execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS entry (
+                entry_key   TEXT NOT NULL PRIMARY KEY,
+                text        TEXT NOT NULL,
+                modified    INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS attachment (
+                entry_key   TEXT NOT NULL,
+                name        TEXT NOT NULL,
+                data        BLOB NOT NULL,
+                PRIMARY KEY (entry_key, name)
+            );
+            ",
+        )?;
+        Ok(SqliteStorage { index })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn list(&self) -> StorageResult<Vec<DateTime<Utc>>> {
+        let mut stmt = self
+            .index
+            .connection()
+            .prepare("SELECT entry_key FROM entry")?;
+        let rows = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
+        let mut dates = Vec::new();
+        for row in rows {
+            let key_str: String = row?;
+            match DateTime::parse_from_str(&key_str, KEY_DB_FORMAT) {
+                Ok(date) => dates.push(date.with_timezone(&Utc)),
+                Err(e) => return Err(StorageError::NameParseError(key_str, e)),
+            }
+        }
+        Ok(dates)
+    }
+
+    fn get(&self, dt: &DateTime<Utc>) -> StorageResult<String> {
+        self.index
+            .connection()
+            .query_row(
+                "SELECT text FROM entry WHERE entry_key = ?",
+                &[&db_key(dt)],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(StorageError::EntryNotFound(*dt))
+    }
+
+    fn put(&self, dt: &DateTime<Utc>, text: &str) -> StorageResult<()> {
+        self.index.connection().execute(
+            "INSERT OR REPLACE INTO entry (entry_key, text, modified) VALUES (?, ?, ?)",
+            params![db_key(dt), text, now_seconds()],
+        )?;
+        Ok(())
+    }
+
+    fn delete(&self, dt: &DateTime<Utc>) -> StorageResult<()> {
+        let key = db_key(dt);
+        let conn = self.index.connection();
+        conn.execute("DELETE FROM attachment WHERE entry_key = ?", &[&key])?;
+        match conn.execute("DELETE FROM entry WHERE entry_key = ?", &[&key])? {
+            0 => Err(StorageError::EntryNotFound(*dt)),
+            _ => Ok(()),
+        }
+    }
+
+    fn stat(&self, dt: &DateTime<Utc>) -> StorageResult<EntryStat> {
+        let (size, modified): (i64, i64) = self
+            .index
+            .connection()
+            .query_row(
+                "SELECT length(CAST(text AS BLOB)), modified FROM entry WHERE entry_key = ?",
+                &[&db_key(dt)],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .ok_or(StorageError::EntryNotFound(*dt))?;
+        Ok(EntryStat {
+            size: size as u64,
+            modified: Some(UNIX_EPOCH + Duration::from_secs(modified.max(0) as u64)),
+        })
+    }
+
+    fn list_attachments(&self, dt: &DateTime<Utc>) -> StorageResult<Vec<String>> {
+        let mut stmt = self
+            .index
+            .connection()
+            .prepare("SELECT name FROM attachment WHERE entry_key = ? ORDER BY name")?;
+        let names = stmt
+            .query_map(&[&db_key(dt)], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
+    fn get_attachment(&self, dt: &DateTime<Utc>, name: &str) -> StorageResult<Vec<u8>> {
+        self.index
+            .connection()
+            .query_row(
+                "SELECT data FROM attachment WHERE entry_key = ? AND name = ?",
+                &[&db_key(dt), name],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| StorageError::AttachmentNotFound(*dt, name.to_string()))
+    }
+
+    fn put_attachment(&self, dt: &DateTime<Utc>, name: &str, data: &[u8]) -> StorageResult<()> {
+        self.index.connection().execute(
+            "INSERT OR REPLACE INTO attachment (entry_key, name, data) VALUES (?, ?, ?)",
+            params![db_key(dt), name, data],
+        )?;
+        Ok(())
+    }
+}
+
+fn db_key(dt: &DateTime<Utc>) -> String {
+    entry_key_to_db_key(&DiaryEntryKey { date: *dt })
+}
+
+fn now_seconds() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
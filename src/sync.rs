@@ -0,0 +1,196 @@
+use crate::diary::{Diary, DiaryError, MatchingDateBehavior};
+use crate::diaryentrykey::DiaryEntryKey;
+use crate::index::tags::TagIndex;
+use crate::storage::StorageError;
+use chrono::Duration;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+
+/// An entry that has been changed differently in two diaries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub key: DiaryEntryKey,
+    /// The text in the diary that's being synced.
+    pub this_text: String,
+    /// The text in the other diary.
+    pub other_text: String,
+}
+
+/// How to resolve a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    KeepThis,
+    KeepOther,
+    /// Keep this text in the entry and add the other text to both diaries as
+    /// a new entry in the next free minute.
+    KeepBoth,
+    /// Replace the text in both diaries with both texts between conflict
+    /// markers.
+    Markers,
+}
+
+/// What syncing did, by entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Entries copied from the other diary to this one.
+    pub copied_here: Vec<DiaryEntryKey>,
+    /// Entries copied from this diary to the other one.
+    pub copied_there: Vec<DiaryEntryKey>,
+    pub conflicts: Vec<(DiaryEntryKey, Resolution)>,
+}
+
+pub(crate) fn sync<F>(
+    this: &Diary,
+    this_index: &TagIndex,
+    other: &Diary,
+    other_index: &TagIndex,
+    mut resolve: F,
+) -> Result<SyncReport, DiaryError>
+where
+    F: FnMut(&Conflict) -> Resolution,
+{
+    let this_keys: BTreeSet<DiaryEntryKey> = this.list_keys()?.into_iter().collect();
+    let other_keys: BTreeSet<DiaryEntryKey> = other.list_keys()?.into_iter().collect();
+    let mut taken_keys: BTreeSet<DiaryEntryKey> = this_keys.union(&other_keys).cloned().collect();
+    let mut report = SyncReport::default();
+    for key in this_keys.union(&other_keys) {
+        let this_text = entry_text(this, key)?;
+        let other_text = entry_text(other, key)?;
+        match (this_text, other_text) {
+            (Some(this_text), None) => {
+                copy_entry(this, key, other, other_index, key, &this_text)?;
+                report.copied_there.push(key.clone());
+            }
+            (None, Some(other_text)) => {
+                copy_entry(other, key, this, this_index, key, &other_text)?;
+                report.copied_here.push(key.clone());
+            }
+            (Some(this_text), Some(other_text)) => {
+                let this_hash = content_hash(&this_text);
+                let other_hash = content_hash(&other_text);
+                if this_hash == other_hash {
+                    continue;
+                }
+                // An entry whose history has the text of the other side is
+                // a later version of it.
+                if has_version(this, key, &other_hash)? {
+                    copy_entry(this, key, other, other_index, key, &this_text)?;
+                    report.copied_there.push(key.clone());
+                } else if has_version(other, key, &this_hash)? {
+                    copy_entry(other, key, this, this_index, key, &other_text)?;
+                    report.copied_here.push(key.clone());
+                } else {
+                    let conflict = Conflict {
+                        key: key.clone(),
+                        this_text,
+                        other_text,
+                    };
+                    let resolution = resolve(&conflict);
+                    match resolution {
+                        Resolution::KeepThis => {
+                            copy_entry(this, key, other, other_index, key, &conflict.this_text)?
+                        }
+                        Resolution::KeepOther => {
+                            copy_entry(other, key, this, this_index, key, &conflict.other_text)?
+                        }
+                        Resolution::KeepBoth => {
+                            // The other entry moves to the free key with its
+                            // attachments before this one replaces it.
+                            let free_key = next_free_key(key, &taken_keys);
+                            let other_text = &conflict.other_text;
+                            copy_entry(other, key, this, this_index, &free_key, other_text)?;
+                            copy_entry(other, key, other, other_index, &free_key, other_text)?;
+                            other.delete_entry(other_index, key)?;
+                            copy_entry(this, key, other, other_index, key, &conflict.this_text)?;
+                            taken_keys.insert(free_key);
+                        }
+                        Resolution::Markers => {
+                            let merged = with_conflict_markers(&conflict);
+                            copy_entry(other, key, this, this_index, key, &merged)?;
+                            copy_entry(this, key, other, other_index, key, &merged)?;
+                        }
+                    }
+                    report.conflicts.push((key.clone(), resolution));
+                }
+            }
+            (None, None) => (),
+        }
+    }
+    this.reindex(this_index)?;
+    other.reindex(other_index)?;
+    Ok(report)
+}
+
+/// Returns the SHA-256 hash of `text` as a hex string. Trailing whitespace
+/// doesn't count, as it's not kept when entries are written.
+fn content_hash(text: &str) -> String {
+    Sha256::digest(text.trim_end().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn with_conflict_markers(conflict: &Conflict) -> String {
+    format!(
+        "<<<<<<< this\n{}\n=======\n{}\n>>>>>>> other\n",
+        conflict.this_text.trim_end(),
+        conflict.other_text.trim_end()
+    )
+}
+
+fn entry_text(diary: &Diary, key: &DiaryEntryKey) -> Result<Option<String>, DiaryError> {
+    match diary.get_text_for_entry(key) {
+        Ok(text) => Ok(Some(text)),
+        Err(DiaryError::StorageError(StorageError::EntryNotFound(_))) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn has_version(diary: &Diary, key: &DiaryEntryKey, hash: &str) -> Result<bool, DiaryError> {
+    for version in diary.entry_versions(key)? {
+        if content_hash(&diary.get_entry_version(key, version.number)?) == hash {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Stores `text` as the entry for `to_key` in `to`, with the attachments of
+/// the entry for `from_key` in `from`. Links to the attachments in `text`
+/// are changed to where they are in `to`.
+fn copy_entry(
+    from: &Diary,
+    from_key: &DiaryEntryKey,
+    to: &Diary,
+    to_index: &TagIndex,
+    to_key: &DiaryEntryKey,
+    text: &str,
+) -> Result<(), DiaryError> {
+    let mut text = text.to_string();
+    for name in from.list_attachments(from_key)? {
+        let data = from.get_attachment(from_key, &name)?;
+        to.storage().put_attachment(&to_key.date, &name, &data)?;
+        text = text.replace(
+            &from.attachment_link(from_key, &name),
+            &to.attachment_link(to_key, &name),
+        );
+    }
+    to.store_entry(
+        to_index,
+        &text,
+        Some(to_key.clone()),
+        MatchingDateBehavior::Overwrite,
+        Some(format!("Sync entry {}", to_key)),
+    )?;
+    Ok(())
+}
+
+fn next_free_key(key: &DiaryEntryKey, taken_keys: &BTreeSet<DiaryEntryKey>) -> DiaryEntryKey {
+    let mut candidate = key.clone();
+    while taken_keys.contains(&candidate) {
+        candidate = DiaryEntryKey {
+            date: candidate.date + Duration::minutes(1),
+        };
+    }
+    candidate
+}
@@ -1,38 +1,85 @@
 extern crate pom;
+extern crate unicode_normalization;
 
 use pom::parser::*;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 use std::iter::FromIterator;
+use std::ops::Range;
 
 pub fn find_tags(s: &str) -> Vec<String> {
-    let chars: Vec<char> = s.chars().collect();
-    let res = text_parts().parse(&chars);
-    let mut tags = res
-        .map(|parts| {
-            parts
-                .iter()
-                .filter_map(|p| match p {
-                    TextPart::Str(_) => None,
-                    TextPart::Tag(t) => Some(t.to_string()),
-                })
-                .collect()
+    let mut tags: Vec<String> = parse_spans(s)
+        .into_iter()
+        .filter_map(|span| match span.kind {
+            SpanKind::Tag(t) => Some(t),
+            _ => None,
         })
-        .unwrap_or_else(|_| vec![]);
+        .collect();
     tags.sort();
     tags.dedup();
     tags
 }
 
-#[derive(Debug, PartialEq)]
-enum TextPart {
-    Str(String),
+/// Returns the form of `tag` used for matching: case-folded and in Unicode NFC,
+/// so that `#Café`, `#café` and `#CAFE\u{301}` are all the same tag.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.nfd().collect::<String>().to_lowercase().nfc().collect()
+}
+
+/// Splits `s` into spans of plain text, tags, mentions and links. The ranges
+/// are byte ranges into `s` and together they cover the whole string. If `s`
+/// can't be parsed, it is all one span of plain text.
+pub fn parse_spans(s: &str) -> Vec<Span> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut byte_offsets: Vec<usize> = s.char_indices().map(|(i, _)| i).collect();
+    byte_offsets.push(s.len());
+    let res = collected_parts().parse(&chars);
+    res.map(|parts| {
+        parts
+            .into_iter()
+            .map(|(part, range)| Span {
+                kind: match part {
+                    CollectedPart::Chars => SpanKind::Text,
+                    CollectedPart::Tag(t) => SpanKind::Tag(t),
+                    CollectedPart::Mention(m) => SpanKind::Mention(m),
+                    CollectedPart::Link => SpanKind::Link,
+                },
+                range: byte_offsets[range.start]..byte_offsets[range.end],
+            })
+            .collect()
+    })
+    .unwrap_or_else(|_| {
+        vec![Span {
+            kind: SpanKind::Text,
+            range: 0..s.len(),
+        }]
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub kind: SpanKind,
+    pub range: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpanKind {
+    Text,
+    /// A tag, with the tag name without the hash marks and parentheses.
     Tag(String),
+    /// A mention like `@name`, with the name without the at sign.
+    Mention(String),
+    /// A `http://` or `https://` link.
+    Link,
 }
 
 #[derive(Debug, PartialEq)]
 enum ParsedPart {
     Char(char),
     Tag(String),
+    Mention(String),
+    Link(String),
 }
 
 fn phrase_start<'a>() -> Parser<'a, char, usize> {
@@ -70,8 +117,22 @@ fn phrase_hash<'a>() -> Parser<'a, char, String> {
 }
 
 fn word_hash<'a>() -> Parser<'a, char, String> {
-    (sym('#') * (is_a(|c: char| c.is_alphanumeric()).repeat(1..)))
-        .map(|chars| chars.into_iter().collect())
+    (sym('#') * (is_a(is_word_char).repeat(1..))).map(|chars| chars.into_iter().collect())
+}
+
+// Combining marks keep decomposed letters like `e\u{301}` inside the word.
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || is_combining_mark(c)
+}
+
+fn mention<'a>() -> Parser<'a, char, String> {
+    (sym('@') * (is_a(is_word_char).repeat(1..))).map(|chars| chars.into_iter().collect())
+}
+
+fn link<'a>() -> Parser<'a, char, String> {
+    let scheme = tag("https://") | tag("http://");
+    let rest = is_a(|c: char| !c.is_whitespace()).repeat(1..);
+    (scheme + rest).map(|(s, r)| format!("{}{}", s, String::from_iter(r)))
 }
 
 fn word_hash_as_parsed_part<'a>() -> Parser<'a, char, ParsedPart> {
@@ -86,52 +147,86 @@ fn phrase_hash_as_parsed_part<'a>() -> Parser<'a, char, ParsedPart> {
     phrase_hash().map(ParsedPart::Tag)
 }
 
+fn mention_as_parsed_part<'a>() -> Parser<'a, char, ParsedPart> {
+    mention().map(ParsedPart::Mention)
+}
+
+fn link_as_parsed_part<'a>() -> Parser<'a, char, ParsedPart> {
+    link().map(ParsedPart::Link)
+}
+
 fn char_or_hash<'a>() -> Parser<'a, char, ParsedPart> {
-    phrase_hash_as_parsed_part() | word_hash_as_parsed_part() | char_as_parsed_part()
+    link_as_parsed_part()
+        | phrase_hash_as_parsed_part()
+        | word_hash_as_parsed_part()
+        | mention_as_parsed_part()
+        | char_as_parsed_part()
 }
 
-fn parsed_parts<'a>() -> Parser<'a, char, Vec<ParsedPart>> {
-    char_or_hash().repeat(0..)
+fn parsed_parts<'a>() -> Parser<'a, char, Vec<(ParsedPart, Range<usize>)>> {
+    let positioned = empty().pos() + char_or_hash() + empty().pos();
+    positioned
+        .map(|((start, part), end)| (part, start..end))
+        .repeat(0..)
 }
 
 enum CollectedPart {
-    Chars(Vec<char>),
+    Chars,
     Tag(String),
+    Mention(String),
+    Link,
 }
 
-fn collected_parts<'a>() -> Parser<'a, char, Vec<CollectedPart>> {
+/// Characters that end a sentence rather than a link when they trail one.
+const LINK_TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', ')', '\'', '"'];
+
+fn collected_parts<'a>() -> Parser<'a, char, Vec<(CollectedPart, Range<usize>)>> {
     parsed_parts().map(|pps| {
-        let mut cps: Vec<CollectedPart> = Vec::with_capacity(pps.len() / 4); // guess!
-        let mut current: Vec<char> = Vec::new();
-        for pp in pps {
+        let mut cps: Vec<(CollectedPart, Range<usize>)> = Vec::with_capacity(pps.len() / 4); // guess!
+        let mut current: Range<usize> = 0..0;
+        let mut previous_char: Option<char> = None;
+        for (pp, range) in pps {
+            if current.start == current.end {
+                current = range.start..range.start;
+            }
             match pp {
-                ParsedPart::Char(c) => current.push(c),
+                ParsedPart::Char(c) => {
+                    current.end = range.end;
+                    previous_char = Some(c);
+                    continue;
+                }
+                // An at sign inside a word is part of an email address, not a mention.
+                ParsedPart::Mention(_) if previous_char.is_some_and(|c| c.is_alphanumeric()) => {
+                    current.end = range.end;
+                }
+                ParsedPart::Mention(m) => {
+                    flush_chars(&mut cps, &mut current);
+                    cps.push((CollectedPart::Mention(m), range));
+                }
+                ParsedPart::Link(l) => {
+                    flush_chars(&mut cps, &mut current);
+                    let trimmed = l.trim_end_matches(LINK_TRAILING_PUNCTUATION);
+                    let link_end = range.start + trimmed.chars().count();
+                    cps.push((CollectedPart::Link, range.start..link_end));
+                    current = link_end..range.end;
+                }
                 ParsedPart::Tag(t) => {
-                    if !current.is_empty() {
-                        cps.push(CollectedPart::Chars(current.clone()));
-                        current.truncate(0);
-                    }
-                    cps.push(CollectedPart::Tag(t));
+                    flush_chars(&mut cps, &mut current);
+                    cps.push((CollectedPart::Tag(t), range));
                 }
             }
+            previous_char = None;
         }
-        if !current.is_empty() {
-            cps.push(CollectedPart::Chars(current));
-        }
+        flush_chars(&mut cps, &mut current);
         cps
     })
 }
 
-fn text_parts<'a>() -> Parser<'a, char, Vec<TextPart>> {
-    collected_parts().map(|parts| {
-        parts
-            .iter()
-            .map(|cp| match cp {
-                CollectedPart::Chars(chars) => TextPart::Str(chars.iter().collect()),
-                CollectedPart::Tag(tag) => TextPart::Tag(tag.to_string()),
-            })
-            .collect()
-    })
+fn flush_chars(cps: &mut Vec<(CollectedPart, Range<usize>)>, current: &mut Range<usize>) {
+    if current.start != current.end {
+        cps.push((CollectedPart::Chars, current.clone()));
+        *current = current.end..current.end;
+    }
 }
 
 #[cfg(test)]
@@ -201,18 +296,15 @@ mod tests {
     }
 
     #[test]
-    fn text_parts_collects_all() {
-        let output = text_parts().parse(&[
-            'a', 'b', ' ', '#', '#', '(', 'c', 'd', ')', '#', '#', '#', 'z', ' ', 'q', 'w',
-        ]);
+    fn parse_spans_collects_all() {
         assert_eq!(
-            output,
-            Ok(vec![
-                TextPart::Str("ab ".to_string()),
-                TextPart::Tag("cd".to_string()),
-                TextPart::Tag("z".to_string()),
-                TextPart::Str(" qw".to_string())
-            ])
+            spans_with_text("ab ##(cd)###z qw"),
+            vec![
+                (SpanKind::Text, "ab "),
+                (SpanKind::Tag("cd".to_string()), "##(cd)##"),
+                (SpanKind::Tag("z".to_string()), "#z"),
+                (SpanKind::Text, " qw"),
+            ]
         )
     }
 
@@ -227,4 +319,61 @@ mod tests {
         let tags = find_tags("#a #b #a #b #c #a #c");
         assert_eq!(tags, vec!["a", "b", "c"]);
     }
+
+    #[test]
+    fn parse_spans_collects_mentions_and_links() {
+        assert_eq!(
+            spans_with_text("hi @bob, see https://example.com/#x."),
+            vec![
+                (SpanKind::Text, "hi "),
+                (SpanKind::Mention("bob".to_string()), "@bob"),
+                (SpanKind::Text, ", see "),
+                (SpanKind::Link, "https://example.com/#x"),
+                (SpanKind::Text, "."),
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_spans_ignores_email_addresses() {
+        assert_eq!(
+            spans_with_text("mail me@example.com"),
+            vec![(SpanKind::Text, "mail me@example.com")]
+        )
+    }
+
+    fn spans_with_text(text: &str) -> Vec<(SpanKind, &str)> {
+        parse_spans(text)
+            .into_iter()
+            .map(|s| (s.kind, &text[s.range]))
+            .collect()
+    }
+
+    #[test]
+    fn parse_spans_returns_byte_ranges() {
+        assert_eq!(
+            spans_with_text("café #tag ##(ä b)## @x"),
+            vec![
+                (SpanKind::Text, "café "),
+                (SpanKind::Tag("tag".to_string()), "#tag"),
+                (SpanKind::Text, " "),
+                (SpanKind::Tag("ä b".to_string()), "##(ä b)##"),
+                (SpanKind::Text, " "),
+                (SpanKind::Mention("x".to_string()), "@x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_tags_skips_link_fragments() {
+        let tags = find_tags("#real http://example.com/#fragment");
+        assert_eq!(tags, vec!["real"]);
+    }
+
+    #[test]
+    fn normalize_tag_folds_case_and_composition() {
+        assert_eq!(normalize_tag("Café"), "café");
+        assert_eq!(normalize_tag("CAFE\u{301}"), "café");
+        assert_eq!(normalize_tag("WORK"), normalize_tag("work"));
+    }
 }
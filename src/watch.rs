@@ -0,0 +1,148 @@
+use crate::diary::{Diary, DiaryError};
+use crate::diaryentrykey::DiaryEntryKey;
+use crate::index::tags::TagIndex;
+use crate::storage::StorageError;
+use notify::{RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+/// What watching a diary did after its files changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// The entry was indexed again.
+    Indexed(DiaryEntryKey),
+    /// The entry file is gone and the entry was removed from the index.
+    Removed(DiaryEntryKey),
+    /// The entry isn't valid UTF-8, so its index wasn't updated.
+    Skipped(DiaryEntryKey),
+    /// Watching or indexing failed. Watching goes on.
+    Failed(String),
+}
+
+/// Watches the directory of `diary` and updates `tag_index` for the entry
+/// files that change. Changes are collected until there have been none for
+/// `debounce`, and then each changed entry is indexed once. If the diary
+/// can't be locked, the changes are indexed with the next batch, which comes
+/// at most `debounce` later. `report` is called with everything that's done;
+/// watching stops when it returns false.
+pub(crate) fn watch<F>(
+    diary: &Diary,
+    tag_index: &TagIndex,
+    debounce: Duration,
+    mut report: F,
+) -> Result<(), DiaryError>
+where
+    F: FnMut(&WatchEvent) -> bool,
+{
+    let root = diary.root().ok_or(DiaryError::Unsupported(
+        "watch a diary that isn't in a directory",
+    ))?;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(root, RecursiveMode::Recursive)?;
+
+    let mut pending = BTreeSet::new();
+    loop {
+        let mut paths = std::mem::take(&mut pending);
+        let mut events = Vec::new();
+        if paths.is_empty() {
+            match receiver.recv() {
+                Ok(result) => collect_paths(result, &mut paths, &mut events),
+                Err(_) => return Ok(()),
+            }
+        }
+        loop {
+            match receiver.recv_timeout(debounce) {
+                Ok(result) => collect_paths(result, &mut paths, &mut events),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+        match index_files(diary, tag_index, &paths) {
+            Ok(indexed) => events.extend(indexed),
+            Err(e) => {
+                events.push(WatchEvent::Failed(e.to_string()));
+                pending = paths;
+            }
+        }
+        for event in events.iter() {
+            if !report(event) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Updates the index for the entries whose files are at `paths`. Paths
+/// that aren't entry files are ignored. Entries that can't be indexed are
+/// reported as failed and the others are still indexed; an error is only
+/// returned if the diary can't be locked.
+pub(crate) fn index_files(
+    diary: &Diary,
+    tag_index: &TagIndex,
+    paths: &BTreeSet<PathBuf>,
+) -> Result<Vec<WatchEvent>, DiaryError> {
+    let storage = diary.storage();
+    let files = match storage.entry_files() {
+        Some(files) => files,
+        None => return Ok(Vec::new()),
+    };
+    let keys: BTreeSet<DiaryEntryKey> = paths
+        .iter()
+        .filter_map(|path| files.entry_for_path(relative_path(diary.root(), path)))
+        .map(|date| DiaryEntryKey { date })
+        .collect();
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    let _lock = diary.lock()?;
+    let mut events = Vec::new();
+    for key in keys {
+        let event = match index_entry(diary, tag_index, &key) {
+            Ok(event) => event,
+            Err(e) => WatchEvent::Failed(format!("Entry {}: {}", key, e)),
+        };
+        events.push(event);
+    }
+    Ok(events)
+}
+
+fn index_entry(
+    diary: &Diary,
+    tag_index: &TagIndex,
+    key: &DiaryEntryKey,
+) -> Result<WatchEvent, DiaryError> {
+    let event = match diary.storage().get(&key.date) {
+        Ok(text) => {
+            diary.update_index(tag_index, key, &text)?;
+            WatchEvent::Indexed(key.clone())
+        }
+        Err(StorageError::EntryNotFound(_)) => {
+            tag_index.remove_entry(key)?;
+            WatchEvent::Removed(key.clone())
+        }
+        Err(StorageError::EntryContentDecodingError(_)) => WatchEvent::Skipped(key.clone()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(event)
+}
+
+fn collect_paths(
+    result: notify::Result<notify::Event>,
+    paths: &mut BTreeSet<PathBuf>,
+    events: &mut Vec<WatchEvent>,
+) {
+    match result {
+        // Reading the entries to index them shows up as access.
+        Ok(event) if event.kind.is_access() => (),
+        Ok(event) => paths.extend(event.paths),
+        Err(e) => events.push(WatchEvent::Failed(e.to_string())),
+    }
+}
+
+fn relative_path<'p>(root: Option<&Path>, path: &'p Path) -> &'p Path {
+    root.and_then(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path)
+}
@@ -0,0 +1,88 @@
+use chrono::Utc;
+use diary_core::{Diary, DiaryEntryKey, DiaryError, MatchingDateBehavior, Tree};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+#[test]
+fn test_attachments_are_stored_next_to_entry() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open_custom(&path, Tree::new(&path).unwrap(), Utc::now).unwrap();
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string("2020-09-21 13:37 +0000").unwrap();
+    diary
+        .add_entry(
+            &index,
+            "At the #beach",
+            Some(key.clone()),
+            MatchingDateBehavior::Append,
+        )
+        .unwrap();
+
+    let name = diary
+        .attach_file(&index, &key, "sea view.jpg", b"jpeg")
+        .unwrap();
+    assert_eq!("sea view.jpg", name);
+    let name = diary
+        .attach_file(&index, &key, "sea view.jpg", b"another jpeg")
+        .unwrap();
+    assert_eq!("sea view-1.jpg", name);
+
+    let attachment_dir = path.join("2020/09/20200921T1337.attachments");
+    assert_eq!(
+        b"jpeg".to_vec(),
+        fs::read(attachment_dir.join("sea view.jpg")).unwrap()
+    );
+    assert_eq!(
+        "At the #beach\n\n\
+         [sea view.jpg](20200921T1337.attachments/sea%20view.jpg)\n\n\
+         [sea view-1.jpg](20200921T1337.attachments/sea%20view-1.jpg)\n",
+        diary.get_text_for_entry(&key).unwrap()
+    );
+    assert_eq!(
+        vec!["sea view-1.jpg", "sea view.jpg"],
+        diary.list_attachments(&key).unwrap()
+    );
+    assert_eq!(vec![key.clone()], diary.list_keys().unwrap());
+    assert_eq!(
+        vec![key.clone()],
+        diary.search_tags(&index, &["beach"]).unwrap()
+    );
+
+    diary.delete_entry(&index, &key).unwrap();
+    assert!(!attachment_dir.exists());
+    assert!(diary.list_keys().unwrap().is_empty());
+}
+
+#[test]
+fn test_attaching_creates_entry() {
+    let diary = Diary::in_memory().unwrap();
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string("2020-09-21 13:37 +0000").unwrap();
+    diary
+        .attach_file(&index, &key, "memo.m4a", b"audio")
+        .unwrap();
+    assert_eq!(
+        "[memo.m4a](memo.m4a)\n",
+        diary.get_text_for_entry(&key).unwrap()
+    );
+    assert_eq!(
+        b"audio".to_vec(),
+        diary.get_attachment(&key, "memo.m4a").unwrap()
+    );
+}
+
+#[test]
+fn test_invalid_attachment_names() {
+    let diary = Diary::in_memory().unwrap();
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string("2020-09-21 13:37 +0000").unwrap();
+    for name in &["", ".hidden", "../escape.txt", "dir/file.txt"] {
+        assert!(matches!(
+            diary.attach_file(&index, &key, name, b"data"),
+            Err(DiaryError::InvalidAttachmentName(_))
+        ));
+    }
+    assert!(diary.list_keys().unwrap().is_empty());
+}
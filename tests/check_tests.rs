@@ -0,0 +1,120 @@
+use diary_core::{Diary, DiaryEntryKey, Finding, MatchingDateBehavior, Problem};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+fn key(s: &str) -> DiaryEntryKey {
+    DiaryEntryKey::parse_from_string(s).unwrap()
+}
+
+fn problems(findings: &[Finding]) -> Vec<Problem> {
+    findings.iter().map(|f| f.problem.clone()).collect()
+}
+
+#[test]
+fn test_check_finds_and_fixes_problems() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    for (text, date) in [
+        ("Fine #ok", "2020-09-21 13:37 +0000"),
+        ("Tagged #one", "2020-09-22 13:37 +0000"),
+        ("Deleted #gone", "2020-09-23 13:37 +0000"),
+    ] {
+        diary
+            .add_entry(&index, text, Some(key(date)), MatchingDateBehavior::Append)
+            .unwrap();
+    }
+    fs::write(path.join("2020/09/20200922T1337"), "Tagged #two\n").unwrap();
+    fs::remove_file(path.join("2020/09/20200923T1337")).unwrap();
+    fs::write(path.join("2020/09/notes.txt"), "Not an entry").unwrap();
+    fs::create_dir_all(path.join("2020/10")).unwrap();
+    fs::write(path.join("2020/10/20200924T1337"), "Misplaced #moved").unwrap();
+    fs::write(path.join("2020/09/20200925T1337"), "").unwrap();
+    fs::write(path.join("2020/09/20200926T1337"), b"Caf\xe9").unwrap();
+
+    let expected = vec![
+        Problem::StrayFile(PathBuf::from("2020/09/notes.txt")),
+        Problem::MisplacedEntry(
+            PathBuf::from("2020/10/20200924T1337"),
+            key("2020-09-24 13:37 +0000"),
+        ),
+        Problem::TagMismatch {
+            key: key("2020-09-22 13:37 +0000"),
+            entry_tags: vec!["two".to_string()],
+            index_tags: vec!["one".to_string()],
+        },
+        Problem::EmptyEntry(key("2020-09-25 13:37 +0000")),
+        Problem::InvalidUtf8(key("2020-09-26 13:37 +0000")),
+        Problem::MissingEntry(key("2020-09-23 13:37 +0000")),
+    ];
+    let findings = diary.check(&index, false).unwrap();
+    assert_eq!(expected, problems(&findings));
+    assert!(findings.iter().all(|f| !f.fixed));
+
+    // Entries that are moved into place are checked like the others.
+    let findings = diary.check(&index, true).unwrap();
+    assert_eq!(
+        Problem::TagMismatch {
+            key: key("2020-09-24 13:37 +0000"),
+            entry_tags: vec!["moved".to_string()],
+            index_tags: vec![],
+        },
+        findings[3].problem
+    );
+    let fixed: Vec<bool> = findings.iter().map(|f| f.fixed).collect();
+    assert_eq!(vec![false, true, true, true, true, false, true], fixed);
+    assert!(path.join("2020/09/20200924T1337").exists());
+    assert!(!path.join("2020/10").exists());
+    assert!(!path.join("2020/09/20200925T1337").exists());
+    // The deleted entry is kept in the history like any other.
+    assert_eq!(
+        1,
+        diary
+            .entry_versions(&key("2020-09-25 13:37 +0000"))
+            .unwrap()
+            .len()
+    );
+    assert_eq!(
+        vec![key("2020-09-24 13:37 +0000")],
+        diary.search_tags(&index, &["moved"]).unwrap()
+    );
+
+    let findings = diary.check(&index, false).unwrap();
+    assert_eq!(
+        vec![
+            Problem::StrayFile(PathBuf::from("2020/09/notes.txt")),
+            Problem::InvalidUtf8(key("2020-09-26 13:37 +0000")),
+        ],
+        problems(&findings)
+    );
+}
+
+#[test]
+fn test_check_ignores_diary_files() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    Diary::enable_git(&path, std::time::Duration::from_secs(0)).unwrap();
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    let k = key("2020-09-21 13:37 +0000");
+    diary
+        .add_entry(
+            &index,
+            "Entry #a",
+            Some(k.clone()),
+            MatchingDateBehavior::Append,
+        )
+        .unwrap();
+    diary
+        .add_entry(
+            &index,
+            "More",
+            Some(k.clone()),
+            MatchingDateBehavior::Overwrite,
+        )
+        .unwrap();
+    diary.attach_file(&index, &k, "a.txt", b"data").unwrap();
+    assert_eq!(Vec::<Finding>::new(), diary.check(&index, false).unwrap());
+}
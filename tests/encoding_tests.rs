@@ -0,0 +1,145 @@
+use diary_core::{Diary, DiaryEntryKey, MatchingDateBehavior};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+fn key(s: &str) -> DiaryEntryKey {
+    DiaryEntryKey::parse_from_string(s).unwrap()
+}
+
+#[test]
+fn test_reindex_skips_and_convert_fixes_other_encodings() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    diary
+        .add_entry(
+            &index,
+            "Plain #utf8",
+            Some(key("2020-09-21 13:37 +0000")),
+            MatchingDateBehavior::Append,
+        )
+        .unwrap();
+    fs::write(path.join("2020/09/20200922T1337"), b"Caf\xe9 #latin").unwrap();
+    let utf16: Vec<u8> = "\u{feff}Na\u{ef}ve #utf16"
+        .encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect();
+    fs::write(path.join("2020/09/20200923T1337"), utf16).unwrap();
+
+    assert_eq!(
+        vec![key("2020-09-22 13:37 +0000"), key("2020-09-23 13:37 +0000")],
+        diary.reindex(&index).unwrap()
+    );
+    assert_eq!(
+        vec![key("2020-09-21 13:37 +0000")],
+        diary.search_tags(&index, &["utf8"]).unwrap()
+    );
+
+    assert_eq!(
+        vec![
+            (key("2020-09-22 13:37 +0000"), "windows-1252".to_string()),
+            (key("2020-09-23 13:37 +0000"), "UTF-16LE".to_string()),
+        ],
+        diary.convert_to_utf8(&index).unwrap()
+    );
+    assert_eq!(
+        "Café #latin",
+        diary
+            .get_text_for_entry(&key("2020-09-22 13:37 +0000"))
+            .unwrap()
+    );
+    assert_eq!(
+        "Naïve #utf16",
+        diary
+            .get_text_for_entry(&key("2020-09-23 13:37 +0000"))
+            .unwrap()
+    );
+    assert_eq!(
+        vec![key("2020-09-23 13:37 +0000")],
+        diary.search_tags(&index, &["utf16"]).unwrap()
+    );
+    assert!(diary.reindex(&index).unwrap().is_empty());
+    assert!(diary.convert_to_utf8(&index).unwrap().is_empty());
+}
+
+#[test]
+fn test_fallback_encoding_is_configurable() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    fs::write(
+        path.join("config.toml"),
+        "[encoding]\nfallback = \"windows-1251\"\n",
+    )
+    .unwrap();
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    fs::create_dir_all(path.join("2020/09")).unwrap();
+    fs::write(
+        path.join("2020/09/20200922T1337"),
+        b"\xcf\xf0\xe8\xe2\xe5\xf2",
+    )
+    .unwrap();
+
+    assert_eq!(
+        vec![(key("2020-09-22 13:37 +0000"), "windows-1251".to_string())],
+        diary.convert_to_utf8(&index).unwrap()
+    );
+    assert_eq!(
+        "Привет",
+        diary
+            .get_text_for_entry(&key("2020-09-22 13:37 +0000"))
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_convert_finds_utf16_without_byte_order_mark() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    fs::create_dir_all(path.join("2020/09")).unwrap();
+    // ASCII text in UTF-16 is valid UTF-8, with a zero byte after each letter.
+    let utf16: Vec<u8> = "Plain #ascii"
+        .encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect();
+    fs::write(path.join("2020/09/20200922T1337"), utf16).unwrap();
+
+    assert_eq!(
+        vec![(key("2020-09-22 13:37 +0000"), "UTF-16LE".to_string())],
+        diary.convert_to_utf8(&index).unwrap()
+    );
+    assert_eq!(
+        "Plain #ascii",
+        diary
+            .get_text_for_entry(&key("2020-09-22 13:37 +0000"))
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_converted_entry_can_be_restored() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    let k = key("2020-09-22 13:37 +0000");
+    fs::create_dir_all(path.join("2020/09")).unwrap();
+    // Meant to be windows-1251, but converted with the default fallback.
+    let original = b"\xcf\xf0\xe8\xe2\xe5\xf2 #hello";
+    fs::write(path.join("2020/09/20200922T1337"), original).unwrap();
+    diary.convert_to_utf8(&index).unwrap();
+    assert_eq!("Ïðèâåò #hello", diary.get_text_for_entry(&k).unwrap());
+    assert_eq!(1, diary.entry_versions(&k).unwrap().len());
+
+    diary.restore_entry_version(&index, &k, 1).unwrap();
+    assert_eq!(
+        original.to_vec(),
+        fs::read(path.join("2020/09/20200922T1337")).unwrap()
+    );
+    assert!(diary.search_tags(&index, &["hello"]).unwrap().is_empty());
+    assert_eq!("Ïðèâåò #hello", diary.get_entry_version(&k, 2).unwrap());
+}
@@ -0,0 +1,154 @@
+use chrono::Utc;
+use diary_core::{
+    CryptoError, Diary, DiaryEntryKey, DiaryError, MatchingDateBehavior, Problem, StorageError,
+    Tree,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn add_entry(diary: &Diary, text: &str, date: &str) -> DiaryEntryKey {
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string(date).unwrap();
+    diary
+        .add_entry(&index, text, Some(key), MatchingDateBehavior::Overwrite)
+        .unwrap()
+}
+
+fn entry_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for year in fs::read_dir(path).unwrap() {
+        let year = year.unwrap().path();
+        if year.is_dir() && !year.ends_with(".history") {
+            for month in fs::read_dir(year).unwrap() {
+                for file in fs::read_dir(month.unwrap().path()).unwrap() {
+                    let file = file.unwrap().path();
+                    if file.is_file() {
+                        files.push(file);
+                    }
+                }
+            }
+        }
+    }
+    files
+}
+
+#[test]
+fn test_encrypt_and_decrypt_diary() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let key = {
+        let diary = Diary::open_custom(&path, Tree::new(&path).unwrap(), Utc::now).unwrap();
+        let key = add_entry(&diary, "A #secret entry", "2020-09-21 13:37 +03:00");
+        let index = diary.open_index().unwrap();
+        diary
+            .attach_file(&index, &key, "secret.txt", b"secret attachment")
+            .unwrap();
+        key
+    };
+    assert!(path.join("index.sqlite").exists());
+
+    Diary::encrypt_diary(&path, "hunter2", false, Duration::from_secs(0)).unwrap();
+    assert!(Diary::is_encrypted(&path).unwrap());
+    assert!(!path.join("index.sqlite").exists());
+    for file in entry_files(&path) {
+        assert!(!fs::read_to_string(file).unwrap().contains("secret"));
+    }
+    assert!(matches!(
+        Diary::open(&path),
+        Err(DiaryError::PassphraseRequired)
+    ));
+    assert!(matches!(
+        Diary::open_encrypted(&path, "hunter3"),
+        Err(DiaryError::CryptoError(CryptoError::WrongPassphrase))
+    ));
+
+    let attachment = path.join("2020/09/20200921T1037.attachments/secret.txt");
+    assert!(!fs::read_to_string(&attachment).unwrap().contains("secret"));
+
+    let diary = Diary::open_encrypted(&path, "hunter2").unwrap();
+    assert_eq!(
+        b"secret attachment".to_vec(),
+        diary.get_attachment(&key, "secret.txt").unwrap()
+    );
+    let index = diary.open_index().unwrap();
+    assert_eq!(
+        vec![key.clone()],
+        diary.search_tags(&index, &["secret"]).unwrap()
+    );
+    drop(diary);
+    assert!(!path.join("index.sqlite").exists());
+
+    Diary::decrypt_diary(&path, "hunter2", Duration::from_secs(0)).unwrap();
+    assert!(!Diary::is_encrypted(&path).unwrap());
+    let diary = Diary::open(&path).unwrap();
+    assert!(diary
+        .get_text_for_entry(&key)
+        .unwrap()
+        .starts_with("A #secret entry\n"));
+    assert_eq!(
+        b"secret attachment".to_vec(),
+        fs::read(&attachment).unwrap()
+    );
+    let index = diary.open_index().unwrap();
+    assert_eq!(vec![key], diary.search_tags(&index, &["secret"]).unwrap());
+}
+
+#[test]
+fn test_encrypted_diary_writes_encrypted_entries() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    Diary::encrypt_diary(&path, "hunter2", false, Duration::from_secs(0)).unwrap();
+    let key = {
+        let diary = Diary::open_encrypted(&path, "hunter2").unwrap();
+        add_entry(&diary, "Another #secret", "2020-09-22 08:00 +03:00");
+        add_entry(&diary, "A #changed secret", "2020-09-22 08:00 +03:00")
+    };
+    assert!(!path.join("index.sqlite").exists());
+    let files = entry_files(&path);
+    assert_eq!(1, files.len());
+    assert!(!fs::read_to_string(&files[0]).unwrap().contains("secret"));
+    for version in fs::read_dir(path.join(".history/20200922T0500")).unwrap() {
+        let text = fs::read_to_string(version.unwrap().path()).unwrap();
+        assert!(!text.contains("secret"));
+    }
+
+    let diary = Diary::open_encrypted(&path, "hunter2").unwrap();
+    let index = diary.open_index().unwrap();
+    assert_eq!(
+        vec![key.clone()],
+        diary.search_tags(&index, &["changed"]).unwrap()
+    );
+    assert_eq!(
+        "Another #secret\n",
+        diary.get_entry_version(&key, 1).unwrap()
+    );
+}
+
+#[test]
+fn test_unencrypted_entry_in_encrypted_diary_is_an_error() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    Diary::encrypt_diary(&path, "hunter2", false, Duration::from_secs(0)).unwrap();
+    let diary = Diary::open_encrypted(&path, "hunter2").unwrap();
+    let key = add_entry(&diary, "A #secret", "2020-09-22 08:00 +03:00");
+    let plain = DiaryEntryKey::parse_from_string("2020-09-23 08:00 +03:00").unwrap();
+    fs::write(path.join("2020/09/20200923T0500"), "Not encrypted\n").unwrap();
+
+    assert!(diary.get_text_for_entry(&key).is_ok());
+    assert!(matches!(
+        diary.get_text_for_entry(&plain),
+        Err(DiaryError::StorageError(
+            StorageError::EntryDecryptionError(_, CryptoError::Unencrypted)
+        ))
+    ));
+    let index = diary.open_index().unwrap();
+    let problems: Vec<Problem> = diary
+        .check(&index, false)
+        .unwrap()
+        .into_iter()
+        .map(|f| f.problem)
+        .collect();
+    assert_eq!(vec![Problem::UnencryptedEntry(plain)], problems);
+}
@@ -0,0 +1,278 @@
+use chrono::{FixedOffset, Utc};
+use diary_core::{
+    parse_jrnl, parse_range_end_with_clock, read_dayone, split_text, Diary, DiaryEntryKey,
+    ExportFormat, ExportOptions, MatchingDateBehavior, RangeEnd, TagIndex, DEFAULT_SPLIT_REGEX,
+};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+fn key(s: &str) -> DiaryEntryKey {
+    DiaryEntryKey::parse_from_string(s).unwrap()
+}
+
+fn add(diary: &Diary, index: &TagIndex, date: &str, text: &str) {
+    diary
+        .add_entry(index, text, Some(key(date)), MatchingDateBehavior::Append)
+        .unwrap();
+}
+
+#[test]
+fn test_export_markdown() {
+    let dir = tempdir().unwrap();
+    let diary = Diary::open(&PathBuf::from(dir.path())).unwrap();
+    let index = diary.open_index().unwrap();
+    add(
+        &diary,
+        &index,
+        "2020-08-31 22:30 +0000",
+        "Late #project work",
+    );
+    add(
+        &diary,
+        &index,
+        "2020-09-21 10:37 +0000",
+        "# Kickoff\n\nMet the team. #project",
+    );
+    add(
+        &diary,
+        &index,
+        "2020-09-22 08:00 +0000",
+        "Nothing to do with it",
+    );
+    let tz = FixedOffset::east_opt(3 * 3600).unwrap();
+
+    let options = ExportOptions {
+        tags: vec!["project".to_string()],
+        title: "Project log".to_string(),
+        ..ExportOptions::default()
+    };
+    let (count, document) = diary
+        .export(&index, ExportFormat::Markdown, &options, &tz)
+        .unwrap();
+    assert_eq!(2, count);
+    assert_eq!(
+        "# Project log
+
+## Contents
+
+- [September 2020](#september-2020)
+  - [2020-09-01 01:30 +0300](#2020-09-01-0130-0300)
+  - [2020-09-21 13:37 +0300](#2020-09-21-1337-0300)
+
+## September 2020
+
+### 2020-09-01 01:30 +0300
+
+Late #project work
+
+### 2020-09-21 13:37 +0300
+
+#### Kickoff
+
+Met the team. #project
+",
+        String::from_utf8(document).unwrap()
+    );
+
+    let options = ExportOptions {
+        from: Some(key("2020-09-21 00:00 +0000")),
+        to: Some(key("2020-09-30 00:00 +0000")),
+        ..ExportOptions::default()
+    };
+    let (count, _) = diary
+        .export(&index, ExportFormat::Markdown, &options, &tz)
+        .unwrap();
+    assert_eq!(2, count);
+
+    let day = |end| {
+        parse_range_end_with_clock("2020-08-31", end, &Utc, Utc::now)
+            .map(|date| DiaryEntryKey { date })
+    };
+    let options = ExportOptions {
+        from: day(RangeEnd::From),
+        to: day(RangeEnd::To),
+        ..ExportOptions::default()
+    };
+    let (count, _) = diary
+        .export(&index, ExportFormat::Markdown, &options, &tz)
+        .unwrap();
+    assert_eq!(1, count);
+}
+
+#[test]
+fn test_export_text_reads_back() {
+    let dir = tempdir().unwrap();
+    let diary = Diary::open(&PathBuf::from(dir.path())).unwrap();
+    let index = diary.open_index().unwrap();
+    add(
+        &diary,
+        &index,
+        "2020-09-21 10:37 +0000",
+        "First\n\nwith two paragraphs",
+    );
+    add(&diary, &index, "2020-09-22 08:00 +0000", "Second #tag");
+    let tz = FixedOffset::east_opt(3 * 3600).unwrap();
+
+    let (count, document) = diary
+        .export(&index, ExportFormat::Text, &ExportOptions::default(), &tz)
+        .unwrap();
+    assert_eq!(2, count);
+    let document = String::from_utf8(document).unwrap();
+    assert_eq!(
+        "2020-09-21 13:37\n\nFirst\n\nwith two paragraphs\n\n2020-09-22 11:00\n\nSecond #tag\n",
+        document
+    );
+    let import = split_text(&document, DEFAULT_SPLIT_REGEX, &tz, || {
+        chrono::Utc::now().with_timezone(&tz)
+    })
+    .unwrap();
+    let entries: Vec<(DiaryEntryKey, String)> = import
+        .entries
+        .into_iter()
+        .map(|e| (e.key, e.text))
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                key("2020-09-21 10:37 +0000"),
+                "First\n\nwith two paragraphs".to_string()
+            ),
+            (key("2020-09-22 08:00 +0000"), "Second #tag".to_string()),
+        ],
+        entries
+    );
+}
+
+#[test]
+fn test_export_jrnl_round_trip() {
+    let dir = tempdir().unwrap();
+    let diary = Diary::open(&PathBuf::from(dir.path())).unwrap();
+    let index = diary.open_index().unwrap();
+    let texts = [
+        (
+            "2020-09-21 10:37 +0000",
+            "Started the project. #work\nMet #(anna-maria)# at the office.",
+        ),
+        (
+            "2020-09-23 18:15 +0000",
+            "Evening #home\n\nmail me@example.com",
+        ),
+        ("2020-09-24 07:00 +0000", "A #(multi word)# tag, #(c++)#"),
+        (
+            "2020-09-25 07:00 +0000",
+            "Minutes\n[2020-09-22 10:00] Kickoff\n  [2020-09-23 10:00] Review",
+        ),
+    ];
+    for (date, text) in texts.iter() {
+        add(&diary, &index, date, text);
+    }
+    let tz = FixedOffset::east_opt(3 * 3600).unwrap();
+
+    let (count, document) = diary
+        .export(&index, ExportFormat::Jrnl, &ExportOptions::default(), &tz)
+        .unwrap();
+    assert_eq!(4, count);
+    let document = String::from_utf8(document).unwrap();
+    assert_eq!(
+        "[2020-09-21 13:37] Started the project. @work\nMet @anna-maria at the office.\n\n\
+         [2020-09-23 21:15] Evening @home\n\nmail me@example.com\n\n\
+         [2020-09-24 10:00] A #(multi word)# tag, #(c++)#\n\n\
+         [2020-09-25 10:00] Minutes\n [2020-09-22 10:00] Kickoff\n   [2020-09-23 10:00] Review\n",
+        document
+    );
+
+    let import = parse_jrnl(&document, &tz).unwrap();
+    assert!(import.skipped.is_empty());
+    let entries: Vec<(DiaryEntryKey, &str)> = import
+        .entries
+        .iter()
+        .map(|e| (e.key.clone(), e.text.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            (key(texts[0].0), texts[0].1),
+            (key(texts[1].0), texts[1].1),
+            (key(texts[2].0), texts[2].1),
+            (key(texts[3].0), texts[3].1),
+        ],
+        entries
+    );
+}
+
+#[test]
+fn test_export_dayone_round_trip() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path.join("diary")).unwrap();
+    let index = diary.open_index().unwrap();
+    let k1 = key("2020-09-21 10:37 +0000");
+    let k2 = key("2020-09-22 08:00 +0000");
+    add(
+        &diary,
+        &index,
+        "2020-09-21 10:37 +0000",
+        "Walk in the #park",
+    );
+    diary
+        .attach_file(&index, &k1, "sea view.jpg", b"jpeg data")
+        .unwrap();
+    diary
+        .attach_file(&index, &k1, "memo.m4a", b"audio")
+        .unwrap();
+    add(
+        &diary,
+        &index,
+        "2020-09-22 08:00 +0000",
+        "With #(multi word)# and #Park",
+    );
+
+    let options = ExportOptions {
+        time_zone: Some("Europe/Helsinki".to_string()),
+        ..ExportOptions::default()
+    };
+    let (count, export) = diary
+        .export(&index, ExportFormat::DayOne, &options, &Utc)
+        .unwrap();
+    assert_eq!(2, count);
+    let export_path = path.join("export.zip");
+    fs::write(&export_path, export).unwrap();
+
+    let import = read_dayone(&export_path).unwrap();
+    assert!(import.skipped.is_empty());
+    assert_eq!(2, import.entries.len());
+    assert_eq!(k1, import.entries[0].key);
+    assert_eq!(1, import.entries[0].attachments.len());
+    assert_eq!(b"jpeg data".to_vec(), import.entries[0].attachments[0].data);
+    assert_eq!(k2, import.entries[1].key);
+    assert_eq!(
+        diary.get_text_for_entry(&k2).unwrap().trim_end(),
+        import.entries[1].text
+    );
+
+    let copy = Diary::open(&path.join("copy")).unwrap();
+    let copy_index = copy.open_index().unwrap();
+    copy.import(&copy_index, &import, MatchingDateBehavior::Append, false)
+        .unwrap();
+    let photo = &import.entries[0].attachments[0].name;
+    assert_eq!(
+        format!(
+            "Walk in the #park\n\n[{}](20200921T1037.attachments/{})\n\n\
+             [memo.m4a](20200921T1037.attachments/memo.m4a)\n",
+            photo, photo
+        ),
+        copy.get_text_for_entry(&k1).unwrap()
+    );
+    assert_eq!(
+        b"jpeg data".to_vec(),
+        copy.get_attachment(&k1, photo).unwrap()
+    );
+    assert_eq!(
+        vec![k1.clone(), k2.clone()],
+        copy.search_tags(&copy_index, &["park"]).unwrap()
+    );
+    assert_eq!(
+        vec![k2],
+        copy.search_tags(&copy_index, &["multi word"]).unwrap()
+    );
+}
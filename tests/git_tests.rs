@@ -0,0 +1,134 @@
+use chrono::Utc;
+use diary_core::{Diary, DiaryEntryKey, DiaryError, MatchingDateBehavior, Tree};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn messages(diary: &Diary) -> Vec<String> {
+    diary
+        .change_log()
+        .unwrap()
+        .into_iter()
+        .map(|c| c.message)
+        .collect()
+}
+
+#[test]
+fn test_changes_are_committed() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    Diary::enable_git(&path, Duration::from_secs(0)).unwrap();
+    let diary = Diary::open_custom(&path, Tree::new(&path).unwrap(), Utc::now).unwrap();
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string("2020-09-21 13:37 +0000").unwrap();
+    for (text, behavior) in [
+        ("First", MatchingDateBehavior::Append),
+        ("Second", MatchingDateBehavior::Append),
+        ("Third", MatchingDateBehavior::Overwrite),
+    ] {
+        diary
+            .add_entry(&index, text, Some(key.clone()), behavior)
+            .unwrap();
+    }
+    diary.restore_entry_version(&index, &key, 1).unwrap();
+    diary.delete_entry(&index, &key).unwrap();
+
+    assert_eq!(
+        vec![
+            "Delete entry 2020-09-21 13:37 +0000",
+            "Restore version 1 of entry 2020-09-21 13:37 +0000",
+            "Edit entry 2020-09-21 13:37 +0000",
+            "Append to entry 2020-09-21 13:37 +0000",
+            "Add entry 2020-09-21 13:37 +0000",
+            "Start tracking diary",
+        ],
+        messages(&diary)
+    );
+
+    let repo = git2::Repository::open(&path).unwrap();
+    let mut options = git2::StatusOptions::new();
+    options.include_ignored(false);
+    let statuses = repo.statuses(Some(&mut options)).unwrap();
+    assert!(statuses.is_empty(), "Uncommitted files in the diary");
+}
+
+#[test]
+fn test_git_is_optional() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open_custom(&path, Tree::new(&path).unwrap(), Utc::now).unwrap();
+    let index = diary.open_index().unwrap();
+    diary
+        .add_entry(&index, "Text", None, MatchingDateBehavior::Append)
+        .unwrap();
+    assert!(!path.join(".git").exists());
+    assert!(matches!(diary.change_log(), Err(DiaryError::GitNotEnabled)));
+
+    Diary::enable_git(&path, Duration::from_secs(0)).unwrap();
+    Diary::disable_git(&path, Duration::from_secs(0)).unwrap();
+    let diary = Diary::open_custom(&path, Tree::new(&path).unwrap(), Utc::now).unwrap();
+    diary
+        .add_entry(&index, "More text", None, MatchingDateBehavior::Append)
+        .unwrap();
+    let repo = git2::Repository::open(&path).unwrap();
+    assert_eq!(
+        1,
+        repo.revwalk()
+            .and_then(|mut w| {
+                w.push_head()?;
+                Ok(w.count())
+            })
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_encrypting_keeps_git_history_only_when_asked() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    Diary::enable_git(&path, Duration::from_secs(0)).unwrap();
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    let key = diary
+        .add_entry(&index, "Plain #secret", None, MatchingDateBehavior::Append)
+        .unwrap();
+
+    assert!(matches!(
+        Diary::encrypt_diary(&path, "hunter2", false, Duration::from_secs(0)),
+        Err(DiaryError::GitHistoryNotEncrypted)
+    ));
+    assert!(!Diary::is_encrypted(&path).unwrap());
+    assert_eq!("Plain #secret\n", diary.get_text_for_entry(&key).unwrap());
+
+    Diary::encrypt_diary(&path, "hunter2", true, Duration::from_secs(0)).unwrap();
+    assert!(Diary::is_encrypted(&path).unwrap());
+    let diary = Diary::open_encrypted(&path, "hunter2").unwrap();
+    assert_eq!(
+        Some("Encrypt diary".to_string()),
+        messages(&diary).into_iter().next()
+    );
+}
+
+#[test]
+fn test_enabling_git_adds_to_an_existing_gitignore() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    fs::write(path.join(".gitignore"), "/notes\n/.lock").unwrap();
+    Diary::enable_git(&path, Duration::from_secs(0)).unwrap();
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    diary
+        .add_entry(&index, "Text", None, MatchingDateBehavior::Append)
+        .unwrap();
+
+    let ignored = fs::read_to_string(path.join(".gitignore")).unwrap();
+    assert!(ignored.starts_with("/notes\n/.lock\n"));
+    assert_eq!(1, ignored.lines().filter(|l| *l == "/.lock").count());
+    assert!(ignored.lines().any(|l| l == "/index.sqlite"));
+    let repo = git2::Repository::open(&path).unwrap();
+    assert!(repo
+        .status_should_ignore(Path::new("index.sqlite"))
+        .unwrap());
+    assert!(repo.status_should_ignore(Path::new(".lock")).unwrap());
+}
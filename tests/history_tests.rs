@@ -0,0 +1,101 @@
+use chrono::{TimeZone, Utc};
+use diary_core::{Diary, DiaryEntryKey, DiaryError, HistoryError, MatchingDateBehavior, Tree};
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+fn check_history(diary: &Diary) {
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string("2020-09-21 13:37 +03:00").unwrap();
+    for (text, behavior) in [
+        ("First #draft", MatchingDateBehavior::Overwrite),
+        ("More text", MatchingDateBehavior::Append),
+        ("Oops", MatchingDateBehavior::Overwrite),
+    ] {
+        diary
+            .add_entry(&index, text, Some(key.clone()), behavior)
+            .unwrap();
+    }
+
+    let versions = diary.entry_versions(&key).unwrap();
+    assert_eq!(
+        vec![1, 2],
+        versions.iter().map(|v| v.number).collect::<Vec<_>>()
+    );
+    assert_eq!("First #draft\n", diary.get_entry_version(&key, 1).unwrap());
+    assert_eq!(
+        "First #draft\n\nMore text\n",
+        diary.get_entry_version(&key, 2).unwrap()
+    );
+    assert!(matches!(
+        diary.get_entry_version(&key, 3),
+        Err(DiaryError::HistoryError(HistoryError::VersionNotFound(
+            _,
+            3
+        )))
+    ));
+    assert_eq!(
+        "--- version 2\n+++ current\n@@ -1,3 +1 @@\n-First #draft\n-\n-More text\n+Oops\n",
+        diary.diff_entry_versions(&key, Some(2), None).unwrap()
+    );
+
+    diary.restore_entry_version(&index, &key, 1).unwrap();
+    assert_eq!("First #draft\n", diary.get_text_for_entry(&key).unwrap());
+    assert_eq!(
+        vec![key.clone()],
+        diary.search_tags(&index, &["draft"]).unwrap()
+    );
+    assert_eq!(3, diary.entry_versions(&key).unwrap().len());
+    assert_eq!("Oops\n", diary.get_entry_version(&key, 3).unwrap());
+
+    diary.delete_entry(&index, &key).unwrap();
+    assert_eq!(4, diary.entry_versions(&key).unwrap().len());
+    diary.restore_entry_version(&index, &key, 4).unwrap();
+    assert_eq!("First #draft\n", diary.get_text_for_entry(&key).unwrap());
+}
+
+#[test]
+fn test_in_memory_history() {
+    let diary = Diary::in_memory().unwrap();
+    check_history(&diary);
+}
+
+#[test]
+fn test_file_history() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open_custom(&path, Tree::new(&path).unwrap(), Utc::now).unwrap();
+    check_history(&diary);
+    assert!(path.join(".history").is_dir());
+    // The history isn't mistaken for entries.
+    assert_eq!(1, diary.list_keys().unwrap().len());
+}
+
+#[test]
+fn test_unchanged_text_is_not_a_new_version() {
+    let clock = || Utc.with_ymd_and_hms(2020, 9, 21, 10, 0, 0).unwrap();
+    let diary = Diary::in_memory_custom(clock).unwrap();
+    let index = diary.open_index().unwrap();
+    let key = diary
+        .add_entry(&index, "Same", None, MatchingDateBehavior::Overwrite)
+        .unwrap();
+    diary
+        .add_entry(
+            &index,
+            "Same",
+            Some(key.clone()),
+            MatchingDateBehavior::Overwrite,
+        )
+        .unwrap();
+    assert!(diary.entry_versions(&key).unwrap().is_empty());
+    diary
+        .add_entry(
+            &index,
+            "Changed",
+            Some(key.clone()),
+            MatchingDateBehavior::Overwrite,
+        )
+        .unwrap();
+    let versions = diary.entry_versions(&key).unwrap();
+    assert_eq!(1, versions.len());
+    assert_eq!(clock(), versions[0].saved);
+}
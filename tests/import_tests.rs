@@ -0,0 +1,362 @@
+use chrono::{FixedOffset, NaiveTime, TimeZone, Utc};
+use diary_core::{
+    parse_jrnl, read_dayone, read_markdown_notes, split_text, Diary, DiaryEntryKey,
+    MarkdownOptions, MatchingDateBehavior, SkippedRecord, DEFAULT_SPLIT_REGEX,
+};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+fn key(s: &str) -> DiaryEntryKey {
+    DiaryEntryKey::parse_from_string(s).unwrap()
+}
+
+static JRNL_TEXT: &str = "\
+[2020-09-21 13:37] Started the project. @work
+Met @anna-maria at the office.
+
+[2020-09-21 13:37] Same minute.
+
+[2020-09-22 08:00]
+
+[2020-09-23 09:15 PM] Evening @home
+";
+
+static JRNL_JSON: &str = r#"{
+  "tags": {"@work": 1},
+  "entries": [
+    {"title": "Started the project.", "body": " @work\nMet @anna-maria.", "date": "2020-09-21", "time": "13:37", "tags": ["@work"], "starred": false},
+    {"title": "No time", "body": "", "date": "2020-09-22"},
+    {"title": "Evening", "body": "At @home", "date": "2020-09-23", "time": "21:15"}
+  ]
+}"#;
+
+#[test]
+fn test_parse_jrnl_text() {
+    let tz = FixedOffset::east_opt(3 * 3600).unwrap();
+    let import = parse_jrnl(JRNL_TEXT, &tz).unwrap();
+    let entries: Vec<(DiaryEntryKey, &str)> = import
+        .entries
+        .iter()
+        .map(|e| (e.key.clone(), e.text.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                key("2020-09-21 10:37 +0000"),
+                "Started the project. #work\nMet #(anna-maria)# at the office."
+            ),
+            (key("2020-09-21 10:37 +0000"), "Same minute."),
+            (key("2020-09-23 18:15 +0000"), "Evening #home"),
+        ],
+        entries
+    );
+    assert_eq!(
+        vec![SkippedRecord {
+            record: "line 6".to_string(),
+            reason: "Empty entry".to_string()
+        }],
+        import.skipped
+    );
+}
+
+#[test]
+fn test_parse_jrnl_json() {
+    let import = parse_jrnl(JRNL_JSON, &Utc).unwrap();
+    assert_eq!(2, import.entries.len());
+    assert_eq!(key("2020-09-21 13:37 +0000"), import.entries[0].key);
+    assert_eq!(
+        "Started the project. #work\nMet #(anna-maria)#.",
+        import.entries[0].text
+    );
+    assert_eq!("Evening\nAt #home", import.entries[1].text);
+    assert_eq!(1, import.skipped.len());
+    assert_eq!("entry 2", import.skipped[0].record);
+    assert!(parse_jrnl("{\"entries\": 1}", &Utc).is_err());
+}
+
+#[test]
+fn test_import_dry_run_and_collisions() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    diary
+        .add_entry(
+            &index,
+            "Already here",
+            Some(key("2020-09-23 21:15 +0000")),
+            MatchingDateBehavior::Append,
+        )
+        .unwrap();
+    let import = parse_jrnl(JRNL_TEXT, &Utc).unwrap();
+
+    let report = diary
+        .import(&index, &import, MatchingDateBehavior::Append, true)
+        .unwrap();
+    assert_eq!(3, report.imported.len());
+    assert_eq!(
+        vec![key("2020-09-21 13:37 +0000"), key("2020-09-23 21:15 +0000")],
+        report.collisions
+    );
+    assert_eq!(1, report.skipped.len());
+    assert_eq!(1, diary.list_keys().unwrap().len());
+
+    diary
+        .import(&index, &import, MatchingDateBehavior::Append, false)
+        .unwrap();
+    assert_eq!(
+        "Started the project. #work\nMet #(anna-maria)# at the office.\n\nSame minute.\n",
+        diary
+            .get_text_for_entry(&key("2020-09-21 13:37 +0000"))
+            .unwrap()
+    );
+    assert_eq!(
+        "Already here\n\nEvening #home\n",
+        diary
+            .get_text_for_entry(&key("2020-09-23 21:15 +0000"))
+            .unwrap()
+    );
+    assert_eq!(
+        vec![key("2020-09-21 13:37 +0000")],
+        diary.search_tags(&index, &["anna-maria"]).unwrap()
+    );
+
+    diary
+        .import(&index, &import, MatchingDateBehavior::Overwrite, false)
+        .unwrap();
+    assert_eq!(
+        "Evening #home\n",
+        diary
+            .get_text_for_entry(&key("2020-09-23 21:15 +0000"))
+            .unwrap()
+    );
+}
+
+static DAYONE_JSON: &str = r#"{
+  "metadata": {"version": "1.0"},
+  "entries": [
+    {
+      "creationDate": "2020-09-21T13:37:42Z",
+      "timeZone": "Europe/Helsinki",
+      "text": "Walk in the park\n\n![](dayone-moment://P1)\n\n#Outdoors",
+      "tags": ["outdoors", "multi word", "family"],
+      "photos": [
+        {"identifier": "P1", "md5": "0a1b", "type": "jpeg"},
+        {"identifier": "P2", "md5": "ffff", "type": "png"}
+      ]
+    },
+    {"creationDate": "2020-09-22T08:00:00", "timeZone": "Europe/Helsinki", "text": "Local time"},
+    {"timeZone": "Europe/Helsinki", "text": "No date"},
+    {"creationDate": "2020-09-23T08:00:00Z", "text": " "},
+    {
+      "creationDate": "2020-09-24T08:00:00Z",
+      "text": "![](dayone-moment://P3)\n",
+      "photos": [{"identifier": "P3", "md5": "eeee", "type": "jpeg"}]
+    }
+  ]
+}"#;
+
+fn write_dayone_export(path: &Path) {
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    zip.start_file("Journal.json", FileOptions::default())
+        .unwrap();
+    zip.write_all(DAYONE_JSON.as_bytes()).unwrap();
+    zip.start_file("photos/0a1b.jpeg", FileOptions::default())
+        .unwrap();
+    zip.write_all(b"jpeg data").unwrap();
+    zip.finish().unwrap();
+}
+
+#[test]
+fn test_import_dayone() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let export = path.join("export.zip");
+    write_dayone_export(&export);
+    let diary = Diary::open(&path.join("diary")).unwrap();
+    let index = diary.open_index().unwrap();
+
+    let import = read_dayone(&export).unwrap();
+    let skipped: Vec<&str> = import.skipped.iter().map(|s| s.record.as_str()).collect();
+    assert_eq!(
+        vec![
+            "Journal.json entry 1 photo P2",
+            "Journal.json entry 3",
+            "Journal.json entry 4",
+            "Journal.json entry 5 photo P3",
+            "Journal.json entry 5",
+        ],
+        skipped
+    );
+    let report = diary
+        .import(&index, &import, MatchingDateBehavior::Append, false)
+        .unwrap();
+    let k1 = key("2020-09-21 13:37 +0000");
+    assert_eq!(
+        vec![k1.clone(), key("2020-09-22 05:00 +0000")],
+        report.imported
+    );
+    assert_eq!(
+        "Walk in the park\n\n\
+         [0a1b.jpeg](20200921T1337.attachments/0a1b.jpeg)\n\n\
+         #Outdoors\n\n\
+         #(multi word)# #family\n",
+        diary.get_text_for_entry(&k1).unwrap()
+    );
+    assert_eq!(
+        b"jpeg data".to_vec(),
+        diary.get_attachment(&k1, "0a1b.jpeg").unwrap()
+    );
+    assert_eq!(
+        vec![k1],
+        diary
+            .search_tags(&index, &["multi word", "outdoors"])
+            .unwrap()
+    );
+    assert!(read_dayone(&path.join("missing.zip")).is_err());
+}
+
+#[test]
+fn test_read_markdown_notes() {
+    let dir = tempdir().unwrap();
+    let vault = dir.path().join("vault");
+    fs::create_dir_all(vault.join("daily")).unwrap();
+    fs::create_dir_all(vault.join(".obsidian")).unwrap();
+    fs::write(
+        vault.join("daily/2020-09-21.md"),
+        "Started the #project. See [[2020-09-22|tomorrow]] and [[2020-09-30]].\n",
+    )
+    .unwrap();
+    fs::write(
+        vault.join("daily/2020-09-22.md"),
+        "---\ndate: 2020-09-22 08:30\ntags:\n  - project\n  - multi word\n---\n\nBack to [[2020-09-21]].\n",
+    )
+    .unwrap();
+    fs::write(
+        vault.join("Notes about nothing.md"),
+        "---\ndate: 2020-09-23\n---\nDated by front matter.\n",
+    )
+    .unwrap();
+    fs::write(vault.join("Ideas.md"), "No date").unwrap();
+    fs::write(vault.join(".obsidian/2020-09-24.md"), "Hidden").unwrap();
+    fs::write(vault.join("daily/photo.png"), "png").unwrap();
+
+    let options = MarkdownOptions {
+        default_time: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+        ..MarkdownOptions::default()
+    };
+    let tz = FixedOffset::east_opt(3 * 3600).unwrap();
+    let import = read_markdown_notes(&vault, &options, &tz).unwrap();
+    let entries: Vec<(DiaryEntryKey, &str)> = import
+        .entries
+        .iter()
+        .map(|e| (e.key.clone(), e.text.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            (key("2020-09-23 06:00 +0000"), "Dated by front matter."),
+            (
+                key("2020-09-21 06:00 +0000"),
+                "Started the #project. See [[2020-09-22 05:30 +0000|tomorrow]] and [[2020-09-30]]."
+            ),
+            (
+                key("2020-09-22 05:30 +0000"),
+                "Back to [[2020-09-21 06:00 +0000]].\n\n#project #(multi word)#"
+            ),
+        ],
+        entries
+    );
+    assert_eq!(
+        vec![SkippedRecord {
+            record: "Ideas.md".to_string(),
+            reason: "No date in the file name or front matter".to_string()
+        }],
+        import.skipped
+    );
+
+    let logseq = dir.path().join("journals");
+    fs::create_dir(&logseq).unwrap();
+    fs::write(logseq.join("2020_09_21.md"), "- Logseq [[2020_09_21]]").unwrap();
+    let options = MarkdownOptions {
+        file_name_pattern: "%Y_%m_%d".to_string(),
+        ..MarkdownOptions::default()
+    };
+    let import = read_markdown_notes(&logseq, &options, &Utc).unwrap();
+    assert_eq!(key("2020-09-21 12:00 +0000"), import.entries[0].key);
+    assert_eq!(
+        "- Logseq [[2020-09-21 12:00 +0000]]",
+        import.entries[0].text
+    );
+    assert!(read_markdown_notes(&dir.path().join("missing"), &options, &Utc).is_err());
+}
+
+static DIARY_TXT: &str = "\
+Notes from before.
+
+2019-03-04 09:15
+Flew to #Helsinki.
+
+## 2019-03-05
+
+Rain all day.
+## 2019-03-32
+Not a date.
+2019-03-06
+";
+
+#[test]
+fn test_split_text() {
+    let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+    let clock = || tz.with_ymd_and_hms(2019, 3, 7, 8, 0, 0).unwrap();
+    let import = split_text(DIARY_TXT, DEFAULT_SPLIT_REGEX, &tz, clock).unwrap();
+    let entries: Vec<(DiaryEntryKey, &str)> = import
+        .entries
+        .iter()
+        .map(|e| (e.key.clone(), e.text.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            (key("2019-03-04 07:15 +0000"), "Flew to #Helsinki."),
+            (key("2019-03-05 10:00 +0000"), "Rain all day."),
+        ],
+        entries
+    );
+    let skipped: Vec<(&str, &str)> = import
+        .skipped
+        .iter()
+        .map(|s| (s.record.as_str(), s.reason.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("line 1", "Text before the first entry"),
+            ("line 9", "Invalid date 2019-03-32"),
+            ("line 11", "Empty entry"),
+        ],
+        skipped
+    );
+
+    let import = split_text(
+        "Day: 9:30\nMorning\nDay: 21:00 Evening\n",
+        r"^Day: (?P<date>\S+)",
+        &tz,
+        clock,
+    )
+    .unwrap();
+    let entries: Vec<(DiaryEntryKey, &str)> = import
+        .entries
+        .iter()
+        .map(|e| (e.key.clone(), e.text.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            (key("2019-03-07 07:30 +0000"), "Morning"),
+            (key("2019-03-07 19:00 +0000"), "Evening"),
+        ],
+        entries
+    );
+    assert!(split_text(DIARY_TXT, "(", &tz, clock).is_err());
+}
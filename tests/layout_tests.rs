@@ -0,0 +1,99 @@
+use diary_core::{
+    Diary, DiaryEntryKey, DiaryError, DirectoryScheme, Extension, FileRepoError, Layout,
+    MatchingDateBehavior,
+};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn add_entry(diary: &Diary, text: &str, date: &str) -> DiaryEntryKey {
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string(date).unwrap();
+    diary
+        .add_entry(
+            &index,
+            text,
+            Some(key.clone()),
+            MatchingDateBehavior::Append,
+        )
+        .unwrap();
+    key
+}
+
+#[test]
+fn test_migrate_layout() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let (k1, k2) = {
+        let diary = Diary::open(&path).unwrap();
+        let k1 = add_entry(&diary, "First #one", "2020-09-21 13:37 +0000");
+        let k2 = add_entry(&diary, "Second", "2021-01-02 03:04 +0000");
+        let index = diary.open_index().unwrap();
+        diary.attach_file(&index, &k1, "a.txt", b"data").unwrap();
+        (k1, k2)
+    };
+    assert!(path.join("2020/09/20200921T1337").exists());
+
+    let layout = Layout {
+        extension: Extension::Md,
+        directories: DirectoryScheme::Flat,
+        file_name: "%Y-%m-%d_%H%M".to_string(),
+    };
+    assert_eq!(
+        2,
+        Diary::migrate_layout(&path, layout.clone(), Duration::from_secs(0)).unwrap()
+    );
+    assert_eq!(layout, Diary::layout(&path).unwrap());
+    assert!(path.join("2020-09-21_1337.md").exists());
+    assert!(path.join("2021-01-02_0304.md").exists());
+    assert!(path.join("20200921T1337.attachments/a.txt").exists());
+    assert!(!path.join("2020").exists());
+    assert!(!path.join("2021").exists());
+
+    let diary = Diary::open(&path).unwrap();
+    assert_eq!(vec![k1.clone(), k2.clone()], diary.list_keys().unwrap());
+    assert!(diary
+        .get_text_for_entry(&k1)
+        .unwrap()
+        .contains("(20200921T1337.attachments/a.txt)"));
+    assert_eq!(
+        b"data".to_vec(),
+        diary.get_attachment(&k1, "a.txt").unwrap()
+    );
+    drop(diary);
+
+    let layout = Layout {
+        directories: DirectoryScheme::YearMonthDay,
+        ..layout
+    };
+    Diary::migrate_layout(&path, layout, Duration::from_secs(0)).unwrap();
+    assert!(path.join("2020/09/21/2020-09-21_1337.md").exists());
+    assert!(path
+        .join("2020/09/21/20200921T1337.attachments/a.txt")
+        .exists());
+    assert!(!path.join("2020-09-21_1337.md").exists());
+    let diary = Diary::open(&path).unwrap();
+    assert_eq!(vec![k1, k2], diary.list_keys().unwrap());
+}
+
+#[test]
+fn test_migrate_layout_does_not_overwrite_files() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path).unwrap();
+    let key = add_entry(&diary, "Entry", "2020-09-21 13:37 +0000");
+    fs::write(path.join("20200921T1337.txt"), "Something else").unwrap();
+
+    let layout = Layout {
+        extension: Extension::Txt,
+        directories: DirectoryScheme::Flat,
+        ..Layout::default()
+    };
+    assert!(matches!(
+        Diary::migrate_layout(&path, layout, Duration::from_secs(0)),
+        Err(DiaryError::FileRepoError(FileRepoError::LayoutConflict(_)))
+    ));
+    assert_eq!(Layout::default(), Diary::layout(&path).unwrap());
+    assert_eq!(vec![key], diary.list_keys().unwrap());
+}
@@ -0,0 +1,109 @@
+use diary_core::{
+    Diary, DiaryEntryKey, DiaryError, MatchingDateBehavior, StorageError, StorageFormat,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn key(s: &str) -> DiaryEntryKey {
+    DiaryEntryKey::parse_from_string(s).unwrap()
+}
+
+#[test]
+fn test_convert_between_tree_and_sqlite() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let k1 = key("2020-09-21 13:37 +0000");
+    let k2 = key("2020-09-22 13:37 +0000");
+    {
+        let diary = Diary::open(&path).unwrap();
+        let index = diary.open_index().unwrap();
+        diary
+            .add_entry(
+                &index,
+                "First #cat",
+                Some(k1.clone()),
+                MatchingDateBehavior::Append,
+            )
+            .unwrap();
+        diary.attach_file(&index, &k1, "cat.jpg", b"meow").unwrap();
+        diary.add_tag_alias(&index, "kitty", "cat").unwrap();
+    }
+
+    assert_eq!(
+        1,
+        Diary::convert_storage(&path, StorageFormat::Sqlite, Duration::from_secs(0)).unwrap()
+    );
+    assert!(path.join("diary.sqlite").exists());
+    assert!(!path.join("2020").exists());
+    assert!(!path.join("index.sqlite").exists());
+    {
+        let diary = Diary::open(&path).unwrap();
+        let index = diary.open_index().unwrap();
+        assert_eq!(vec![k1.clone()], diary.list_keys().unwrap());
+        assert_eq!(
+            vec![k1.clone()],
+            diary.search_tags(&index, &["kitty"]).unwrap()
+        );
+        assert_eq!(
+            b"meow".to_vec(),
+            diary.get_attachment(&k1, "cat.jpg").unwrap()
+        );
+        diary
+            .add_entry(
+                &index,
+                "Second #dog",
+                Some(k2.clone()),
+                MatchingDateBehavior::Append,
+            )
+            .unwrap();
+        assert_eq!(
+            vec![k2.clone()],
+            diary.search_tags(&index, &["dog"]).unwrap()
+        );
+        assert!(matches!(
+            Diary::migrate_layout(&path, Default::default(), Duration::from_secs(0)),
+            Err(DiaryError::Unsupported(_))
+        ));
+    }
+
+    assert_eq!(
+        2,
+        Diary::convert_storage(&path, StorageFormat::Tree, Duration::from_secs(0)).unwrap()
+    );
+    assert!(!path.join("diary.sqlite").exists());
+    assert!(path.join("2020/09/20200922T1337").exists());
+    assert!(path
+        .join("2020/09/20200921T1337.attachments/cat.jpg")
+        .exists());
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    assert_eq!(vec![k1.clone(), k2], diary.list_keys().unwrap());
+    assert_eq!(vec![k1], diary.search_tags(&index, &["kitty"]).unwrap());
+}
+
+#[test]
+fn test_sqlite_text_and_tags_share_transactions() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    Diary::convert_storage(&path, StorageFormat::Sqlite, Duration::from_secs(0)).unwrap();
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    let k = key("2020-09-21 13:37 +0000");
+
+    let result = index.transaction(|| -> Result<(), DiaryError> {
+        diary.add_entry(
+            &index,
+            "Lost #tag",
+            Some(k.clone()),
+            MatchingDateBehavior::Append,
+        )?;
+        Err(DiaryError::StorageError(StorageError::EntryNotFound(
+            k.date,
+        )))
+    });
+
+    assert!(result.is_err());
+    assert!(diary.list_keys().unwrap().is_empty());
+    assert!(diary.search_tags(&index, &["tag"]).unwrap().is_empty());
+}
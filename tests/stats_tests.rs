@@ -0,0 +1,97 @@
+use chrono::{TimeZone, Utc};
+use diary_core::{
+    parse_range_end_with_clock, Diary, DiaryEntryKey, MatchingDateBehavior, RangeEnd,
+};
+
+#[test]
+fn test_stats() {
+    let clock = || Utc.with_ymd_and_hms(2020, 9, 2, 8, 0, 0).unwrap();
+    let diary = Diary::in_memory_custom(clock).unwrap();
+    let index = diary.open_index().unwrap();
+    let entries = [
+        ("2020-08-20 10:00 +00:00", "one #work"),
+        ("2020-08-21 10:00 +00:00", "two words #work"),
+        ("2020-08-22 10:00 +00:00", "three words here"),
+        ("2020-09-01 10:00 +00:00", "four #work #home here"),
+        ("2020-09-02 07:00 +00:00", "five"),
+    ];
+    for (date, text) in &entries {
+        let key = DiaryEntryKey::parse_from_string(date).unwrap();
+        diary
+            .add_entry(&index, text, Some(key), MatchingDateBehavior::Append)
+            .unwrap();
+    }
+
+    let stats = diary.stats(&index, None, None, &Utc).unwrap();
+    assert_eq!(stats.entries, 5);
+    assert_eq!(stats.words, 13);
+    assert_eq!(stats.average_words, 13.0 / 5.0);
+    assert_eq!(
+        stats.entries_per_month.into_iter().collect::<Vec<_>>(),
+        vec![((2020, 8), 3), ((2020, 9), 2)]
+    );
+    // 2020-08-20 was a Thursday.
+    assert_eq!(stats.entries_per_weekday, [0, 1, 1, 1, 1, 1, 0]);
+    assert_eq!(stats.current_streak, 2);
+    assert_eq!(stats.longest_streak, 3);
+    assert_eq!(stats.tags[0].tag, "work");
+    assert_eq!(stats.tags[0].count, 3);
+
+    let from = DiaryEntryKey::parse_from_string("2020-08-21 00:00 +00:00").unwrap();
+    let to = DiaryEntryKey::parse_from_string("2020-09-01 23:59 +00:00").unwrap();
+    let stats = diary.stats(&index, Some(&from), Some(&to), &Utc).unwrap();
+    assert_eq!(stats.entries, 3);
+    assert_eq!(stats.words, 10);
+    assert_eq!(stats.tags[0].tag, "work");
+    assert_eq!(stats.tags[0].count, 2);
+}
+
+#[test]
+fn test_stats_counts_appended_text() {
+    let diary = Diary::in_memory().unwrap();
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string("2020-08-20 10:00 +00:00").unwrap();
+    for text in &["first #one", "second #two"] {
+        diary
+            .add_entry(
+                &index,
+                text,
+                Some(key.clone()),
+                MatchingDateBehavior::Append,
+            )
+            .unwrap();
+    }
+
+    let stats = diary.stats(&index, None, None, &Utc).unwrap();
+    assert_eq!(stats.words, 4);
+    assert_eq!(
+        diary.search_tags(&index, &["one"]).unwrap(),
+        vec![key.clone()]
+    );
+    assert_eq!(diary.search_tags(&index, &["two"]).unwrap(), vec![key]);
+}
+
+#[test]
+fn test_stats_date_range_covers_whole_days() {
+    let diary = Diary::in_memory().unwrap();
+    let index = diary.open_index().unwrap();
+    for date in &["2020-08-20 07:00 +00:00", "2020-08-21 18:30 +00:00"] {
+        let key = DiaryEntryKey::parse_from_string(date).unwrap();
+        diary
+            .add_entry(&index, "text", Some(key), MatchingDateBehavior::Append)
+            .unwrap();
+    }
+    let parse = |s, end| {
+        parse_range_end_with_clock(s, end, &Utc, Utc::now).map(|date| DiaryEntryKey { date })
+    };
+
+    let from = parse("2020-08-21", RangeEnd::From);
+    let to = parse("2020-08-21", RangeEnd::To);
+    let stats = diary
+        .stats(&index, from.as_ref(), to.as_ref(), &Utc)
+        .unwrap();
+    assert_eq!(stats.entries, 1);
+    let to = parse("2020-08-20", RangeEnd::To);
+    let stats = diary.stats(&index, None, to.as_ref(), &Utc).unwrap();
+    assert_eq!(stats.entries, 1);
+}
@@ -0,0 +1,146 @@
+use chrono::{DateTime, Utc};
+use diary_core::{
+    Diary, DiaryEntryKey, DiaryError, EntryStat, LockError, MatchingDateBehavior, MemoryStorage,
+    Storage, StorageError, StorageResult,
+};
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+/// Storage that fails writes when told to.
+#[derive(Debug, Default)]
+struct FlakyStorage {
+    inner: MemoryStorage,
+    fail_writes: Cell<bool>,
+}
+
+impl Storage for FlakyStorage {
+    fn list(&self) -> StorageResult<Vec<DateTime<Utc>>> {
+        self.inner.list()
+    }
+
+    fn get(&self, dt: &DateTime<Utc>) -> StorageResult<String> {
+        self.inner.get(dt)
+    }
+
+    fn put(&self, dt: &DateTime<Utc>, text: &str) -> StorageResult<()> {
+        if self.fail_writes.get() {
+            Err(StorageError::IoError(io::Error::other("disk full")))
+        } else {
+            self.inner.put(dt, text)
+        }
+    }
+
+    fn delete(&self, dt: &DateTime<Utc>) -> StorageResult<()> {
+        self.inner.delete(dt)
+    }
+
+    fn stat(&self, dt: &DateTime<Utc>) -> StorageResult<EntryStat> {
+        self.inner.stat(dt)
+    }
+}
+
+#[test]
+fn test_failed_write_leaves_index_unchanged() {
+    let dir = tempdir().unwrap();
+    let storage = FlakyStorage::default();
+    let diary = Diary::open_custom(&PathBuf::from(dir.path()), &storage, Utc::now).unwrap();
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string("2020-08-30 13:37 +03:00").unwrap();
+    diary
+        .add_entry(
+            &index,
+            "#before",
+            Some(key.clone()),
+            MatchingDateBehavior::Append,
+        )
+        .unwrap();
+
+    storage.fail_writes.set(true);
+    let result = diary.add_entry(
+        &index,
+        "#after",
+        Some(key.clone()),
+        MatchingDateBehavior::Overwrite,
+    );
+
+    assert!(result.is_err());
+    assert_eq!(diary.get_text_for_entry(&key).unwrap(), "#before\n");
+    assert_eq!(
+        diary.search_tags(&index, &["before"]).unwrap(),
+        vec![key.clone()]
+    );
+    assert!(diary.search_tags(&index, &["after"]).unwrap().is_empty());
+}
+
+#[test]
+fn test_tree_writes_leave_no_temporary_files() {
+    let dir = tempdir().unwrap();
+    let diary = Diary::open(&PathBuf::from(dir.path())).unwrap();
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string("2020-08-30 13:37 +00:00").unwrap();
+    for text in &["first", "second"] {
+        diary
+            .add_entry(
+                &index,
+                text,
+                Some(key.clone()),
+                MatchingDateBehavior::Append,
+            )
+            .unwrap();
+    }
+
+    let names: Vec<String> = fs::read_dir(dir.path().join("2020").join("08"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names, vec!["20200830T1337"]);
+    assert_eq!(diary.get_text_for_entry(&key).unwrap(), "first\n\nsecond\n");
+}
+
+#[test]
+fn test_writes_fail_while_diary_is_locked() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    let key = DiaryEntryKey::parse_from_string("2020-08-30 13:37 +00:00").unwrap();
+    diary
+        .add_entry(
+            &index,
+            "first",
+            Some(key.clone()),
+            MatchingDateBehavior::Append,
+        )
+        .unwrap();
+
+    let lock_file = fs::OpenOptions::new()
+        .write(true)
+        .open(path.join(".lock"))
+        .unwrap();
+    fs2::FileExt::try_lock_exclusive(&lock_file).unwrap();
+
+    let result = diary.add_entry(
+        &index,
+        "second",
+        Some(key.clone()),
+        MatchingDateBehavior::Append,
+    );
+    assert!(matches!(
+        result,
+        Err(DiaryError::LockError(LockError::Locked(_)))
+    ));
+    assert_eq!(diary.get_text_for_entry(&key).unwrap(), "first\n");
+
+    drop(lock_file);
+    diary
+        .add_entry(
+            &index,
+            "second",
+            Some(key.clone()),
+            MatchingDateBehavior::Append,
+        )
+        .unwrap();
+}
@@ -0,0 +1,165 @@
+use diary_core::{Diary, DiaryEntryKey, MatchingDateBehavior, Resolution, SyncReport, TagIndex};
+
+fn key(s: &str) -> DiaryEntryKey {
+    DiaryEntryKey::parse_from_string(s).unwrap()
+}
+
+fn write(diary: &Diary, index: &TagIndex, k: &DiaryEntryKey, text: &str) {
+    diary
+        .add_entry(
+            index,
+            text,
+            Some(k.clone()),
+            MatchingDateBehavior::Overwrite,
+        )
+        .unwrap();
+}
+
+fn text(diary: &Diary, k: &DiaryEntryKey) -> String {
+    diary.get_text_for_entry(k).unwrap()
+}
+
+#[test]
+fn test_sync_copies_missing_and_newer_entries() {
+    let this = Diary::in_memory().unwrap();
+    let this_index = this.open_index().unwrap();
+    let other = Diary::in_memory().unwrap();
+    let other_index = other.open_index().unwrap();
+    let k1 = key("2020-09-21 13:37 +0000");
+    let k2 = key("2020-09-22 13:37 +0000");
+    let k3 = key("2020-09-23 13:37 +0000");
+    write(&this, &this_index, &k1, "Only here #laptop");
+    write(&other, &other_index, &k2, "Only there #desktop");
+    write(&this, &this_index, &k3, "Draft");
+    write(&other, &other_index, &k3, "Draft");
+    write(&other, &other_index, &k3, "Draft, #finished");
+
+    let report = this
+        .sync_with(&this_index, &other, &other_index, |_| {
+            panic!("No conflicts expected")
+        })
+        .unwrap();
+
+    assert_eq!(
+        SyncReport {
+            copied_here: vec![k2.clone(), k3.clone()],
+            copied_there: vec![k1.clone()],
+            conflicts: vec![],
+        },
+        report
+    );
+    for (diary, index) in [(&this, &this_index), (&other, &other_index)] {
+        assert_eq!(
+            vec![k1.clone(), k2.clone(), k3.clone()],
+            diary.list_keys().unwrap()
+        );
+        assert_eq!("Draft, #finished\n", text(diary, &k3));
+        assert_eq!(
+            vec![k1.clone()],
+            diary.search_tags(index, &["laptop"]).unwrap()
+        );
+        assert_eq!(
+            vec![k3.clone()],
+            diary.search_tags(index, &["finished"]).unwrap()
+        );
+    }
+
+    let report = this
+        .sync_with(&this_index, &other, &other_index, |_| {
+            panic!("No conflicts expected")
+        })
+        .unwrap();
+    assert_eq!(SyncReport::default(), report);
+}
+
+fn conflicting_diaries<'a>() -> (Diary<'a>, TagIndex, Diary<'a>, TagIndex) {
+    let this = Diary::in_memory().unwrap();
+    let this_index = this.open_index().unwrap();
+    let other = Diary::in_memory().unwrap();
+    let other_index = other.open_index().unwrap();
+    let k = key("2020-09-21 13:37 +0000");
+    write(&this, &this_index, &k, "Written on the laptop");
+    write(&other, &other_index, &k, "Written on the desktop");
+    (this, this_index, other, other_index)
+}
+
+#[test]
+fn test_sync_conflict_keep_both() {
+    let (this, this_index, other, other_index) = conflicting_diaries();
+    let k = key("2020-09-21 13:37 +0000");
+    let mut conflicts = Vec::new();
+    let report = this
+        .sync_with(&this_index, &other, &other_index, |conflict| {
+            conflicts.push(conflict.clone());
+            Resolution::KeepBoth
+        })
+        .unwrap();
+    assert_eq!(1, conflicts.len());
+    assert_eq!("Written on the laptop\n", conflicts[0].this_text);
+    assert_eq!("Written on the desktop\n", conflicts[0].other_text);
+    assert_eq!(vec![(k.clone(), Resolution::KeepBoth)], report.conflicts);
+    let next = key("2020-09-21 13:38 +0000");
+    for diary in [&this, &other] {
+        assert_eq!(vec![k.clone(), next.clone()], diary.list_keys().unwrap());
+        assert_eq!("Written on the laptop\n", text(diary, &k));
+        assert_eq!("Written on the desktop\n", text(diary, &next));
+    }
+}
+
+#[test]
+fn test_sync_conflict_markers() {
+    let (this, this_index, other, other_index) = conflicting_diaries();
+    let k = key("2020-09-21 13:37 +0000");
+    this.sync_with(&this_index, &other, &other_index, |_| Resolution::Markers)
+        .unwrap();
+    for diary in [&this, &other] {
+        assert_eq!(
+            "<<<<<<< this\nWritten on the laptop\n=======\nWritten on the desktop\n>>>>>>> other\n",
+            text(diary, &k)
+        );
+    }
+}
+
+#[test]
+fn test_sync_conflict_keep_other() {
+    let (this, this_index, other, other_index) = conflicting_diaries();
+    let k = key("2020-09-21 13:37 +0000");
+    this.sync_with(&this_index, &other, &other_index, |_| Resolution::KeepOther)
+        .unwrap();
+    assert_eq!("Written on the desktop\n", text(&this, &k));
+    assert_eq!("Written on the desktop\n", text(&other, &k));
+    // The replaced text is still in the history.
+    assert_eq!(
+        "Written on the laptop\n",
+        this.get_entry_version(&k, 1).unwrap()
+    );
+}
+
+#[test]
+fn test_sync_copies_attachments() {
+    let (this, this_index, other, other_index) = conflicting_diaries();
+    let k = key("2020-09-21 13:37 +0000");
+    let only_here = key("2020-09-22 13:37 +0000");
+    this.attach_file(&this_index, &only_here, "map.png", b"laptop map")
+        .unwrap();
+    other
+        .attach_file(&other_index, &k, "photo.jpg", b"desktop photo")
+        .unwrap();
+    this.sync_with(&this_index, &other, &other_index, |_| Resolution::KeepBoth)
+        .unwrap();
+
+    let next = key("2020-09-21 13:38 +0000");
+    for diary in [&this, &other] {
+        assert_eq!(
+            b"laptop map".to_vec(),
+            diary.get_attachment(&only_here, "map.png").unwrap()
+        );
+        // The attachment moved along with the entry it belongs to.
+        assert_eq!(vec!["photo.jpg"], diary.list_attachments(&next).unwrap());
+        assert_eq!(
+            b"desktop photo".to_vec(),
+            diary.get_attachment(&next, "photo.jpg").unwrap()
+        );
+        assert!(diary.list_attachments(&k).unwrap().is_empty());
+    }
+}
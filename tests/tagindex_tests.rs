@@ -1,13 +1,14 @@
 use chrono::{TimeZone, Utc};
-use diary_core::{Diary, DiaryEntryKey, MatchingDateBehavior};
+use diary_core::{Diary, DiaryEntryKey, MatchingDateBehavior, TagCount, Tree};
 use std::path::PathBuf;
 use tempfile::tempdir;
 
 #[test]
 fn test_tags_store() {
     let dir = tempdir().unwrap();
-    let clock = || Utc.ymd(2020, 8, 30).and_hms(13, 37, 00);
-    let diary = Diary::open_custom(&PathBuf::from(dir.path()), clock).unwrap();
+    let clock = || Utc.with_ymd_and_hms(2020, 8, 30, 13, 37, 0).unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open_custom(&path, Tree::new(&path).unwrap(), clock).unwrap();
     let index = diary.open_index().unwrap();
     let key1 = DiaryEntryKey::parse_from_string("2020-08-30 13:37 +03:00").unwrap();
     let key2 = DiaryEntryKey::parse_from_string("2020-08-31 13:37 +03:00").unwrap();
@@ -29,19 +30,20 @@ fn test_tags_store() {
         .unwrap();
     assert_eq!(key1, rkey1);
     assert_eq!(key2, rkey2);
-    let keys = diary.search_tags(&index, &vec!["tags"]).unwrap();
+    let keys = diary.search_tags(&index, &["tags"]).unwrap();
     assert_eq!(keys, vec![key1.clone(), key2.clone()]);
-    let keys = diary.search_tags(&index, &vec!["hello world"]).unwrap();
+    let keys = diary.search_tags(&index, &["hello world"]).unwrap();
     assert_eq!(keys, vec![key1.clone()]);
-    let keys = diary.search_tags(&index, &vec!["with", "more"]).unwrap();
+    let keys = diary.search_tags(&index, &["with", "more"]).unwrap();
     assert_eq!(keys, vec![key1.clone(), key2.clone()]);
 }
 
 #[test]
 fn test_tags_store_reindex() {
     let dir = tempdir().unwrap();
-    let clock = || Utc.ymd(2020, 8, 30).and_hms(13, 37, 00);
-    let diary = Diary::open_custom(&PathBuf::from(dir.path()), clock).unwrap();
+    let clock = || Utc.with_ymd_and_hms(2020, 8, 30, 13, 37, 0).unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open_custom(&path, Tree::new(&path).unwrap(), clock).unwrap();
     let index = diary.open_index().unwrap();
     let key1 = DiaryEntryKey::parse_from_string("2020-08-30 13:37 +03:00").unwrap();
     let key2 = DiaryEntryKey::parse_from_string("2020-08-31 13:37 +03:00").unwrap();
@@ -66,10 +68,188 @@ fn test_tags_store_reindex() {
 
     diary.reindex(&index).unwrap();
 
-    let keys = diary.search_tags(&index, &vec!["tags"]).unwrap();
+    let keys = diary.search_tags(&index, &["tags"]).unwrap();
     assert_eq!(keys, vec![key1.clone(), key2.clone()]);
-    let keys = diary.search_tags(&index, &vec!["hello world"]).unwrap();
+    let keys = diary.search_tags(&index, &["hello world"]).unwrap();
     assert_eq!(keys, vec![key1.clone()]);
-    let keys = diary.search_tags(&index, &vec!["with", "more"]).unwrap();
+    let keys = diary.search_tags(&index, &["with", "more"]).unwrap();
     assert_eq!(keys, vec![key1.clone(), key2.clone()]);
 }
+
+#[test]
+fn test_tags_match_case_and_composition() {
+    let diary = Diary::in_memory().unwrap();
+    let index = diary.open_index().unwrap();
+    let key1 = DiaryEntryKey::parse_from_string("2020-08-30 13:37 +03:00").unwrap();
+    let key2 = DiaryEntryKey::parse_from_string("2020-08-31 13:37 +03:00").unwrap();
+    let key3 = DiaryEntryKey::parse_from_string("2020-09-01 13:37 +03:00").unwrap();
+    for (key, text) in &[
+        (&key1, "#Work at the #café"),
+        (&key2, "more #work at the #cafe\u{301}"),
+        (&key3, "#work #WORK #café"),
+    ] {
+        diary
+            .add_entry(
+                &index,
+                text,
+                Some((*key).clone()),
+                MatchingDateBehavior::Append,
+            )
+            .unwrap();
+    }
+
+    let keys = diary.search_tags(&index, &["WORK"]).unwrap();
+    assert_eq!(keys, vec![key1.clone(), key2.clone(), key3.clone()]);
+    let keys = diary.search_tags(&index, &["Café"]).unwrap();
+    assert_eq!(keys, vec![key1.clone(), key2.clone(), key3.clone()]);
+
+    let tags = diary.list_tags(&index).unwrap();
+    assert_eq!(
+        tags,
+        vec![
+            TagCount {
+                tag: "café".to_string(),
+                count: 3,
+                aliases: vec![],
+            },
+            TagCount {
+                tag: "work".to_string(),
+                count: 3,
+                aliases: vec![],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_tag_aliases() {
+    let diary = Diary::in_memory().unwrap();
+    let index = diary.open_index().unwrap();
+    let key1 = DiaryEntryKey::parse_from_string("2020-08-30 13:37 +03:00").unwrap();
+    let key2 = DiaryEntryKey::parse_from_string("2020-08-31 13:37 +03:00").unwrap();
+    let key3 = DiaryEntryKey::parse_from_string("2020-09-01 13:37 +03:00").unwrap();
+    for (key, text) in &[
+        (&key1, "#mtg with Bob"),
+        (&key2, "another #meeting"),
+        (&key3, "#meetings and #meeting"),
+    ] {
+        diary
+            .add_entry(
+                &index,
+                text,
+                Some((*key).clone()),
+                MatchingDateBehavior::Append,
+            )
+            .unwrap();
+    }
+    diary.add_tag_alias(&index, "mtg", "meeting").unwrap();
+    diary.add_tag_alias(&index, "Meetings", "mtg").unwrap();
+
+    for tag in &["mtg", "meeting", "meetings"] {
+        let keys = diary.search_tags(&index, &[tag]).unwrap();
+        assert_eq!(keys, vec![key1.clone(), key2.clone(), key3.clone()]);
+    }
+    let tags = diary.list_tags(&index).unwrap();
+    assert_eq!(
+        tags,
+        vec![TagCount {
+            tag: "meeting".to_string(),
+            count: 3,
+            aliases: vec!["meetings".to_string(), "mtg".to_string()],
+        }]
+    );
+    assert!(diary.add_tag_alias(&index, "meeting", "mtg").is_err());
+
+    assert!(diary.remove_tag_alias(&index, "mtg").unwrap());
+    assert!(!diary.remove_tag_alias(&index, "mtg").unwrap());
+    let keys = diary.search_tags(&index, &["mtg"]).unwrap();
+    assert_eq!(keys, vec![key1.clone()]);
+}
+
+#[test]
+fn test_related_tags() {
+    let diary = Diary::in_memory().unwrap();
+    let index = diary.open_index().unwrap();
+    let texts = [
+        "#work #coffee",
+        "#work #coffee #late",
+        "#work #late",
+        "#home #coffee",
+        "Nothing tagged",
+    ];
+    for (i, text) in texts.iter().enumerate() {
+        let key = DiaryEntryKey::parse_from_string(&format!("2020-08-{:02} 13:37 +00:00", i + 1))
+            .unwrap();
+        diary
+            .add_entry(&index, text, Some(key), MatchingDateBehavior::Append)
+            .unwrap();
+    }
+
+    let related = diary.related_tags(&index, "Work").unwrap();
+    let summary: Vec<(&str, usize, f64)> = related
+        .iter()
+        .map(|r| (r.tag.as_str(), r.count, r.lift))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("late", 2, 5.0 * 2.0 / (3.0 * 2.0)),
+            ("coffee", 2, 5.0 * 2.0 / (3.0 * 3.0))
+        ]
+    );
+    assert!(diary.related_tags(&index, "nothing").unwrap().is_empty());
+
+    let dot = diary.tag_graph_dot(&index).unwrap();
+    assert!(dot.starts_with("graph tags {"));
+    assert!(dot.contains("\"coffee\" -- \"work\" [weight=2, label=\"2\"];"));
+    assert!(dot.contains("\"coffee\" -- \"home\" [weight=1, label=\"1\"];"));
+}
+
+#[test]
+fn test_delete_entry_removes_tags() {
+    let diary = Diary::in_memory().unwrap();
+    let index = diary.open_index().unwrap();
+    let key1 = DiaryEntryKey::parse_from_string("2020-08-30 13:37 +03:00").unwrap();
+    let key2 = DiaryEntryKey::parse_from_string("2020-08-31 13:37 +03:00").unwrap();
+    for key in &[&key1, &key2] {
+        diary
+            .add_entry(
+                &index,
+                "#tagged",
+                Some((*key).clone()),
+                MatchingDateBehavior::Append,
+            )
+            .unwrap();
+    }
+
+    diary.delete_entry(&index, &key1).unwrap();
+
+    assert_eq!(diary.list_keys().unwrap(), vec![key2.clone()]);
+    assert_eq!(diary.search_tags(&index, &["tagged"]).unwrap(), vec![key2]);
+    assert!(diary.get_text_for_entry(&key1).is_err());
+    assert!(diary.delete_entry(&index, &key1).is_err());
+}
+
+#[test]
+fn test_in_memory_index_is_shared() {
+    let diary = Diary::in_memory().unwrap();
+    let key = DiaryEntryKey::parse_from_string("2020-08-30 13:37 +03:00").unwrap();
+    {
+        let index = diary.open_index().unwrap();
+        diary
+            .add_entry(
+                &index,
+                "#remembered",
+                Some(key.clone()),
+                MatchingDateBehavior::Append,
+            )
+            .unwrap();
+    }
+    let index = diary.open_index().unwrap();
+    assert_eq!(
+        diary.search_tags(&index, &["remembered"]).unwrap(),
+        vec![key.clone()]
+    );
+    assert_eq!(diary.list_keys().unwrap(), vec![key.clone()]);
+    assert_eq!(diary.get_text_for_entry(&key).unwrap(), "#remembered\n");
+}
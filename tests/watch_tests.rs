@@ -0,0 +1,171 @@
+use diary_core::{Diary, DiaryEntryKey, MatchingDateBehavior, WatchEvent};
+use fs2::FileExt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn key(s: &str) -> DiaryEntryKey {
+    DiaryEntryKey::parse_from_string(s).unwrap()
+}
+
+#[test]
+fn test_index_changed_files() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    for (text, date) in [
+        ("Edited #before", "2020-09-21 13:37 +0000"),
+        ("Deleted #gone", "2020-09-22 13:37 +0000"),
+    ] {
+        diary
+            .add_entry(&index, text, Some(key(date)), MatchingDateBehavior::Append)
+            .unwrap();
+    }
+    fs::write(path.join("2020/09/20200921T1337"), "Edited #after").unwrap();
+    fs::remove_file(path.join("2020/09/20200922T1337")).unwrap();
+    fs::write(path.join("2020/09/20200923T1337"), b"Caf\xe9 #latin").unwrap();
+    fs::write(path.join("2020/09/notes.txt"), "Not an entry #no").unwrap();
+    fs::create_dir_all(path.join("2020/10")).unwrap();
+    fs::write(path.join("2020/10/20200924T1337"), "Misplaced #no").unwrap();
+    fs::create_dir_all(path.join("2020/09/20200925T1337")).unwrap();
+
+    let changed = [
+        path.join("2020/09/20200921T1337"),
+        path.join("2020/09/20200922T1337"),
+        path.join("2020/09/20200923T1337"),
+        path.join("2020/09/notes.txt"),
+        path.join("2020/10/20200924T1337"),
+        path.join("index.sqlite"),
+        path.join(".lock"),
+        path.join("2020/09/20200925T1337"),
+        path.join("2020/09/20200921T1337"),
+    ];
+    let events = diary.index_files(&index, &changed).unwrap();
+    assert_eq!(
+        vec![
+            WatchEvent::Indexed(key("2020-09-21 13:37 +0000")),
+            WatchEvent::Removed(key("2020-09-22 13:37 +0000")),
+            WatchEvent::Skipped(key("2020-09-23 13:37 +0000")),
+        ],
+        events[..3]
+    );
+    // An entry that can't be read doesn't keep the others from being indexed.
+    assert_eq!(4, events.len());
+    match &events[3] {
+        WatchEvent::Failed(message) => assert!(message.starts_with("Entry 2020-09-25 13:37")),
+        event => panic!("Unexpected event {:?}", event),
+    }
+    assert_eq!(
+        vec![key("2020-09-21 13:37 +0000")],
+        diary.search_tags(&index, &["after"]).unwrap()
+    );
+    assert!(diary.search_tags(&index, &["before"]).unwrap().is_empty());
+    assert!(diary.search_tags(&index, &["gone"]).unwrap().is_empty());
+    assert!(diary.search_tags(&index, &["no"]).unwrap().is_empty());
+}
+
+#[test]
+fn test_watch_indexes_edited_entries() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    let k = key("2020-09-21 13:37 +0000");
+    diary
+        .add_entry(
+            &index,
+            "Before #old",
+            Some(k.clone()),
+            MatchingDateBehavior::Append,
+        )
+        .unwrap();
+
+    // Keep replacing the file until the watch has seen it, so the test
+    // doesn't depend on when the watcher gets started.
+    let done = Arc::new(AtomicBool::new(false));
+    let editor = {
+        let done = done.clone();
+        let dir = path.join("2020/09");
+        thread::spawn(move || {
+            for _ in 0..20 {
+                if done.load(Ordering::SeqCst) {
+                    break;
+                }
+                fs::write(dir.join(".edit"), "After #new").unwrap();
+                fs::rename(dir.join(".edit"), dir.join("20200921T1337")).unwrap();
+                thread::sleep(Duration::from_millis(500));
+            }
+        })
+    };
+    let mut events = Vec::new();
+    diary
+        .watch(&index, Duration::from_millis(100), |event| {
+            events.push(event.clone());
+            false
+        })
+        .unwrap();
+    done.store(true, Ordering::SeqCst);
+    editor.join().unwrap();
+
+    assert_eq!(vec![WatchEvent::Indexed(k.clone())], events);
+    assert_eq!(vec![k], diary.search_tags(&index, &["new"]).unwrap());
+    assert!(diary.search_tags(&index, &["old"]).unwrap().is_empty());
+}
+
+#[test]
+fn test_watch_retries_when_the_diary_is_locked() {
+    let dir = tempdir().unwrap();
+    let path = PathBuf::from(dir.path());
+    let diary = Diary::open(&path).unwrap();
+    let index = diary.open_index().unwrap();
+    let k = key("2020-09-21 13:37 +0000");
+    diary
+        .add_entry(
+            &index,
+            "Before #old",
+            Some(k.clone()),
+            MatchingDateBehavior::Append,
+        )
+        .unwrap();
+    let lock = fs::File::create(path.join(".lock")).unwrap();
+    lock.lock_exclusive().unwrap();
+    let mut lock = Some(lock);
+
+    let done = Arc::new(AtomicBool::new(false));
+    let editor = {
+        let done = done.clone();
+        let dir = path.join("2020/09");
+        thread::spawn(move || {
+            for _ in 0..20 {
+                if done.load(Ordering::SeqCst) {
+                    break;
+                }
+                fs::write(dir.join(".edit"), "After #new").unwrap();
+                fs::rename(dir.join(".edit"), dir.join("20200921T1337")).unwrap();
+                thread::sleep(Duration::from_millis(500));
+            }
+        })
+    };
+    // The edit is indexed once the lock is released, without another change
+    // to the file.
+    let mut events = Vec::new();
+    diary
+        .watch(&index, Duration::from_millis(100), |event| {
+            events.push(event.clone());
+            done.store(true, Ordering::SeqCst);
+            lock = None;
+            matches!(event, WatchEvent::Failed(_))
+        })
+        .unwrap();
+    editor.join().unwrap();
+
+    assert_eq!(2, events.len());
+    assert!(matches!(events[0], WatchEvent::Failed(_)));
+    assert_eq!(WatchEvent::Indexed(k.clone()), events[1]);
+    assert_eq!(vec![k], diary.search_tags(&index, &["new"]).unwrap());
+}